@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE email_events(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NULL
+        REFERENCES subscriptions (id),
+    email TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    description TEXT NULL,
+    received_at timestamptz NOT NULL
+);
@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE suppressions(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    kind TEXT NOT NULL,
+    value TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    UNIQUE (kind, value)
+);
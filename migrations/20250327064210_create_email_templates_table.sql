@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE email_templates(
+    name TEXT NOT NULL,
+    PRIMARY KEY (name),
+    subject TEXT NOT NULL,
+    html_body TEXT NOT NULL,
+    text_body TEXT NOT NULL,
+    updated_at timestamptz NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+
+ALTER TABLE email_templates ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+ALTER TABLE email_templates DROP CONSTRAINT email_templates_pkey;
+ALTER TABLE email_templates ADD PRIMARY KEY (name, locale);
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN unsubscribe_reason TEXT NULL;
@@ -0,0 +1,28 @@
+-- Add migration script here
+CREATE TABLE newsletter_lists(
+    key TEXT NOT NULL,
+    PRIMARY KEY (key),
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+INSERT INTO newsletter_lists (key, name, description, created_at)
+VALUES ('general', 'General', 'Every new issue of the newsletter', now());
+
+-- subscribers receive every list unless they opted out of it
+CREATE TABLE list_opt_outs(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    list_key TEXT NOT NULL REFERENCES newsletter_lists (key),
+    PRIMARY KEY (subscriber_id, list_key)
+);
+
+ALTER TABLE subscriptions ADD COLUMN frequency TEXT NOT NULL DEFAULT 'every_issue';
+-- when a subscriber last got an issue of a list, weekly and monthly
+-- subscribers are skipped until it is that old
+CREATE TABLE list_deliveries(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    list_key TEXT NOT NULL REFERENCES newsletter_lists (key),
+    last_sent_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, list_key)
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- NULL means the list follows the global `application.opt_in_mode` setting
+ALTER TABLE newsletter_lists ADD COLUMN opt_in_mode TEXT NULL;
@@ -0,0 +1,8 @@
+-- Add migration script here
+-- admin overrides of the bundled disposable domain list
+CREATE TABLE email_domain_rules(
+    domain TEXT NOT NULL,
+    PRIMARY KEY (domain),
+    rule TEXT NOT NULL CHECK (rule IN ('allow', 'block')),
+    created_at timestamptz NOT NULL
+);
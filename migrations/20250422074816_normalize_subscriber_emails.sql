@@ -0,0 +1,47 @@
+-- Add migration script here
+-- Merge subscribers whose addresses only differ by case, keeping the row
+-- with the most restrictive status (or else the oldest) of each group, so an
+-- address that left or complained is not mailed again.
+CREATE TEMPORARY TABLE duplicate_subscribers AS
+SELECT id, first_value(id) OVER (
+    PARTITION BY lower(email)
+    ORDER BY
+        CASE status
+            WHEN 'complained' THEN 0
+            WHEN 'bounced' THEN 1
+            WHEN 'unsubscribed' THEN 2
+            WHEN 'confirmed' THEN 3
+            ELSE 4
+        END,
+        subscribed_at,
+        id
+) AS keep_id
+FROM subscriptions;
+DELETE FROM duplicate_subscribers WHERE id = keep_id;
+
+UPDATE subscription_tokens t SET subscriber_id = d.keep_id
+FROM duplicate_subscribers d WHERE t.subscriber_id = d.id;
+UPDATE email_events e SET subscriber_id = d.keep_id
+FROM duplicate_subscribers d WHERE e.subscriber_id = d.id;
+-- an opt-out of any duplicate is kept
+INSERT INTO list_opt_outs (subscriber_id, list_key)
+SELECT d.keep_id, o.list_key
+FROM list_opt_outs o JOIN duplicate_subscribers d ON o.subscriber_id = d.id
+ON CONFLICT DO NOTHING;
+DELETE FROM list_opt_outs o USING duplicate_subscribers d WHERE o.subscriber_id = d.id;
+DELETE FROM list_deliveries l USING duplicate_subscribers d WHERE l.subscriber_id = d.id;
+DELETE FROM subscriptions s USING duplicate_subscribers d WHERE s.id = d.id;
+DROP TABLE duplicate_subscribers;
+
+-- Domains are case-insensitive, the local part keeps the case it was typed
+-- in. Internationalized domains are converted to punycode by the application,
+-- which also converts the existing rows, see `normalize_stored_emails`.
+UPDATE subscriptions
+SET email = substring(email from '^(.*)@') || '@' || lower(substring(email from '@([^@]*)$'))
+WHERE email LIKE '%@%';
+
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_lower_key ON subscriptions (lower(email));
+
+-- the form the address was typed in, `email` holds the normalized one
+ALTER TABLE subscriptions ADD COLUMN email_display TEXT;
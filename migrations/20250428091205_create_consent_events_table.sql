@@ -0,0 +1,44 @@
+-- Add migration script here
+-- proof of consent: who did what, when, from where and after seeing which text
+CREATE TABLE consent_events(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    event_type TEXT NOT NULL,
+    source TEXT NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    consent_text_version TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id, occurred_at);
+
+-- events are never changed or removed, the only exception is clearing the
+-- network details when the subscriber's data is erased
+CREATE FUNCTION consent_events_append_only() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('DELETE', 'TRUNCATE') THEN
+        RAISE EXCEPTION 'consent_events is append-only';
+    END IF;
+    IF NEW.id <> OLD.id
+        OR NEW.subscriber_id <> OLD.subscriber_id
+        OR NEW.event_type <> OLD.event_type
+        OR NEW.source <> OLD.source
+        OR NEW.consent_text_version IS DISTINCT FROM OLD.consent_text_version
+        OR NEW.occurred_at <> OLD.occurred_at
+        OR (NEW.ip_address IS NOT NULL AND NEW.ip_address IS DISTINCT FROM OLD.ip_address)
+        OR (NEW.user_agent IS NOT NULL AND NEW.user_agent IS DISTINCT FROM OLD.user_agent)
+    THEN
+        RAISE EXCEPTION 'consent_events is append-only';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER consent_events_append_only
+BEFORE UPDATE OR DELETE ON consent_events
+FOR EACH ROW EXECUTE FUNCTION consent_events_append_only();
+
+CREATE TRIGGER consent_events_no_truncate
+BEFORE TRUNCATE ON consent_events
+FOR EACH STATEMENT EXECUTE FUNCTION consent_events_append_only();
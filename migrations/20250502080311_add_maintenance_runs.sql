@@ -0,0 +1,41 @@
+-- Add migration script here
+-- pending subscribers get a single reminder before they are cleaned up
+ALTER TABLE subscriptions ADD COLUMN reminder_sent_at timestamptz NULL;
+
+CREATE TABLE maintenance_runs(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    started_at timestamptz NOT NULL,
+    finished_at timestamptz NOT NULL,
+    reminders_sent INT NOT NULL,
+    subscribers_deleted INT NOT NULL,
+    tokens_deleted INT NOT NULL,
+    error TEXT NULL
+);
+
+-- consent events outlive the pending subscribers deleted by the cleanup, they
+-- are detached from the deleted row instead
+ALTER TABLE consent_events ALTER COLUMN subscriber_id DROP NOT NULL;
+ALTER TABLE consent_events DROP CONSTRAINT consent_events_subscriber_id_fkey;
+ALTER TABLE consent_events ADD CONSTRAINT consent_events_subscriber_id_fkey
+    FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE SET NULL;
+
+CREATE OR REPLACE FUNCTION consent_events_append_only() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP IN ('DELETE', 'TRUNCATE') THEN
+        RAISE EXCEPTION 'consent_events is append-only';
+    END IF;
+    IF NEW.id <> OLD.id
+        OR (NEW.subscriber_id IS NOT NULL AND NEW.subscriber_id IS DISTINCT FROM OLD.subscriber_id)
+        OR NEW.event_type <> OLD.event_type
+        OR NEW.source <> OLD.source
+        OR NEW.consent_text_version IS DISTINCT FROM OLD.consent_text_version
+        OR NEW.occurred_at <> OLD.occurred_at
+        OR (NEW.ip_address IS NOT NULL AND NEW.ip_address IS DISTINCT FROM OLD.ip_address)
+        OR (NEW.user_agent IS NOT NULL AND NEW.user_agent IS DISTINCT FROM OLD.user_agent)
+    THEN
+        RAISE EXCEPTION 'consent_events is append-only';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
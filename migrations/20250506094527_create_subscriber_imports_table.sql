@@ -0,0 +1,18 @@
+-- Add migration script here
+-- free-form labels carried over from the previous tool by the CSV import
+ALTER TABLE subscriptions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+-- pending rows imported without a confirmation email are left alone by the
+-- reminder and cleanup jobs until a link is requested
+ALTER TABLE subscriptions ADD COLUMN confirmation_requested BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- an uploaded CSV waits here between the dry run and the actual import
+CREATE TABLE subscriber_imports(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    csv TEXT NOT NULL,
+    send_confirmations BOOLEAN NOT NULL,
+    -- the list the addresses were collected for, its opt-in mode applies
+    list_key TEXT NULL REFERENCES newsletter_lists (key),
+    created_at timestamptz NOT NULL,
+    imported_at timestamptz NULL
+);
@@ -0,0 +1,3 @@
+-- Add migration script here
+-- the admin subscriber list pages through (subscribed_at, id), newest first
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at DESC, id DESC);
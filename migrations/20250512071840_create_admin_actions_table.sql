@@ -0,0 +1,12 @@
+-- Add migration script here
+-- changes support staff made to subscribers by hand
+CREATE TABLE admin_actions(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    -- no foreign key: the record outlives a deleted subscriber
+    subscriber_id uuid NOT NULL,
+    action TEXT NOT NULL,
+    performed_at timestamptz NOT NULL
+);
+CREATE INDEX admin_actions_subscriber_id_idx ON admin_actions (subscriber_id, performed_at);
@@ -0,0 +1,13 @@
+-- Add migration script here
+-- extra fields marketing collects on the subscribe form, defined by admins
+CREATE TABLE subscriber_attributes(
+    key TEXT NOT NULL,
+    PRIMARY KEY (key),
+    label TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    required BOOLEAN NOT NULL,
+    -- the allowed values of an `enum` attribute
+    options TEXT[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL
+);
+ALTER TABLE subscriptions ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
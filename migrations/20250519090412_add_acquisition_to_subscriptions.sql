@@ -0,0 +1,10 @@
+-- Add migration script here
+-- where a subscriber came from, as sent with their first sign-up
+ALTER TABLE subscriptions
+    ADD COLUMN utm_source TEXT NULL,
+    ADD COLUMN utm_medium TEXT NULL,
+    ADD COLUMN utm_campaign TEXT NULL,
+    ADD COLUMN utm_term TEXT NULL,
+    ADD COLUMN utm_content TEXT NULL,
+    -- the host of the referring page, or a free-form source such as "podcast"
+    ADD COLUMN referrer TEXT NULL;
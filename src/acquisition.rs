@@ -0,0 +1,167 @@
+//! src/acquisition.rs
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const MAX_VALUE_LENGTH: usize = 200;
+
+/// The query parameters the home page passes on to the subscribe form.
+pub const UTM_PARAMETERS: [&str; 5] = ["utm_source", "utm_medium", "utm_campaign", "utm_term", "utm_content"];
+
+/// Where a subscriber came from, as sent with the subscription. Tracking data
+/// never fails a subscription: empty values are dropped and long ones cut.
+#[derive(Debug, Default, PartialEq, serde::Deserialize)]
+pub struct Acquisition {
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+    /// The referring page or a free-form source such as "podcast".
+    pub referrer: Option<String>,
+}
+
+impl Acquisition {
+    pub fn normalize(self) -> Self {
+        Self {
+            utm_source: normalize(self.utm_source),
+            utm_medium: normalize(self.utm_medium),
+            utm_campaign: normalize(self.utm_campaign),
+            utm_term: normalize(self.utm_term),
+            utm_content: normalize(self.utm_content),
+            // only the host of a page is kept, its path may identify the visitor
+            referrer: normalize(self.referrer).map(|referrer| {
+                match reqwest::Url::parse(&referrer).ok().and_then(|url| url.host_str().map(str::to_lowercase)) {
+                    Some(host) => host,
+                    None => referrer,
+                }
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+fn normalize(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().chars().take(MAX_VALUE_LENGTH).collect::<String>())
+        .filter(|v| !v.is_empty())
+}
+
+/// Records where a new subscriber came from. Returning subscribers keep the
+/// source of their first sign-up.
+#[tracing::instrument(name = "Saving the acquisition source of a subscriber", skip(transaction))]
+pub async fn save_acquisition(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    acquisition: &Acquisition,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET utm_source = $2, utm_medium = $3, utm_campaign = $4, utm_term = $5, utm_content = $6, referrer = $7
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        acquisition.utm_source,
+        acquisition.utm_medium,
+        acquisition.utm_campaign,
+        acquisition.utm_term,
+        acquisition.utm_content,
+        acquisition.referrer,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Sign-ups of a source and campaign, `utm_source` falling back to the referrer.
+pub struct SourceBreakdown {
+    /// `None` for sign-ups with neither.
+    pub source: Option<String>,
+    pub campaign: Option<String>,
+    pub signups: i64,
+    /// Those who confirmed at some point, even if they left since.
+    pub confirmed: i64,
+}
+
+impl SourceBreakdown {
+    /// The share of sign-ups that confirmed, in percent.
+    pub fn confirmation_rate(&self) -> f64 {
+        if self.signups == 0 {
+            0.0
+        } else {
+            self.confirmed as f64 * 100.0 / self.signups as f64
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get the sign-ups by source", skip(pool))]
+pub async fn get_signups_by_source(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<SourceBreakdown>, sqlx::Error> {
+    sqlx::query_as!(
+        SourceBreakdown,
+        r#"
+        SELECT
+            COALESCE(s.utm_source, s.referrer) AS source,
+            s.utm_campaign AS campaign,
+            count(*) AS "signups!",
+            count(*) FILTER (WHERE EXISTS (
+                SELECT 1 FROM consent_events c
+                WHERE c.subscriber_id = s.id AND c.event_type = 'confirmed'
+            )) AS "confirmed!"
+        FROM subscriptions s
+        WHERE s.subscribed_at >= $1
+        GROUP BY 1, 2
+        ORDER BY 3 DESC, 1, 2
+        LIMIT $2
+        "#,
+        since,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::acquisition::{Acquisition, SourceBreakdown};
+
+    #[test]
+    fn values_are_trimmed_cut_and_empty_ones_dropped() {
+        let acquisition = Acquisition {
+            utm_source: Some(" newsletter-swap ".into()),
+            utm_medium: Some("  ".into()),
+            utm_campaign: Some("x".repeat(250)),
+            ..Default::default()
+        }
+        .normalize();
+
+        assert_eq!(acquisition.utm_source.as_deref(), Some("newsletter-swap"));
+        assert_eq!(acquisition.utm_medium, None);
+        assert_eq!(acquisition.utm_campaign.unwrap().len(), 200);
+        assert!(Acquisition { utm_term: Some("".into()), ..Default::default() }.normalize().is_empty());
+    }
+
+    #[test]
+    fn only_the_host_of_a_referring_page_is_kept() {
+        let referrer = |value: &str| Acquisition { referrer: Some(value.into()), ..Default::default() }.normalize().referrer;
+
+        assert_eq!(referrer("https://News.example.com/item?id=42&user=ursula").as_deref(), Some("news.example.com"));
+        assert_eq!(referrer("podcast").as_deref(), Some("podcast"));
+    }
+
+    #[test]
+    fn the_confirmation_rate_is_a_percentage() {
+        let breakdown = |signups, confirmed| SourceBreakdown { source: None, campaign: None, signups, confirmed };
+
+        assert_eq!(breakdown(4, 3).confirmation_rate(), 75.0);
+        assert_eq!(breakdown(0, 0).confirmation_rate(), 0.0);
+    }
+}
@@ -0,0 +1,73 @@
+//! src/admin_actions.rs
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// A change support staff made to a subscriber by hand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdminAction {
+    Added,
+    Renamed,
+    Confirmed,
+    Unsubscribed,
+    Deleted,
+}
+
+impl AdminAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminAction::Added => "added",
+            AdminAction::Renamed => "renamed",
+            AdminAction::Confirmed => "confirmed",
+            AdminAction::Unsubscribed => "unsubscribed",
+            AdminAction::Deleted => "deleted",
+        }
+    }
+}
+
+pub struct AdminActionRecord {
+    pub action: String,
+    pub username: String,
+    pub performed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Recording an admin action", skip(transaction))]
+pub async fn record_admin_action(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    subscriber_id: Uuid,
+    action: AdminAction,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_actions (id, user_id, subscriber_id, action, performed_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        subscriber_id,
+        action.as_str(),
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get the admin actions on a subscriber", skip(pool))]
+pub async fn get_admin_actions(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<AdminActionRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        AdminActionRecord,
+        r#"
+        SELECT a.action, u.username, a.performed_at
+        FROM admin_actions a
+        JOIN users u ON u.user_id = a.user_id
+        WHERE a.subscriber_id = $1
+        ORDER BY a.performed_at DESC
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,280 @@
+//! src/bot_protection.rs
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configurations::{BotProtectionSettings, ChallengeSettings};
+use crate::startup::HmacSecret;
+
+/// Form stamps older than this are rejected, so they cannot be collected once
+/// and replayed forever.
+const FORM_STAMP_MAX_AGE_HOURS: i64 = 24;
+
+/// Layered checks in front of `POST /subscriptions` and the other public
+/// endpoints sending an email to whatever address they are given.
+///
+/// Shared by every worker, so the rate limits apply to the whole process.
+pub struct BotProtection {
+    min_fill_time: chrono::Duration,
+    ip_limiter: RateLimiter,
+    email_domain_limiter: RateLimiter,
+    email_limiter: RateLimiter,
+    challenge: Option<ChallengeVerifier>,
+}
+
+impl BotProtection {
+    pub fn new(settings: &BotProtectionSettings) -> Self {
+        let window = Duration::from_secs(settings.rate_limit_window_seconds);
+        Self {
+            min_fill_time: chrono::Duration::seconds(settings.min_fill_seconds as i64),
+            ip_limiter: RateLimiter::new(settings.per_ip_limit, window),
+            email_domain_limiter: RateLimiter::new(settings.per_email_domain_limit, window),
+            email_limiter: RateLimiter::new(settings.per_email_limit, window),
+            challenge: settings.challenge.as_ref().map(ChallengeVerifier::new),
+        }
+    }
+
+    /// Checks the signed timestamp embedded in the subscribe form or fetched
+    /// by API clients. Disabled when the minimum fill time is zero.
+    pub fn check_form_stamp(
+        &self,
+        stamp: Option<&str>,
+        secret: &HmacSecret,
+        now: DateTime<Utc>,
+    ) -> Result<(), String> {
+        if self.min_fill_time.is_zero() {
+            return Ok(());
+        }
+        let stamp = stamp.ok_or_else(|| "The form stamp is missing.".to_string())?;
+        let issued_at = verify_form_stamp(stamp, secret)
+            .map_err(|_| "The form stamp is not valid.".to_string())?;
+        if now - issued_at < self.min_fill_time {
+            return Err("The form was submitted too quickly, please try again.".into());
+        }
+        if now - issued_at > chrono::Duration::hours(FORM_STAMP_MAX_AGE_HOURS) {
+            return Err("The form has expired, please reload the page and try again.".into());
+        }
+        Ok(())
+    }
+
+    pub fn allow_ip(&self, ip: &str) -> bool {
+        self.ip_limiter.allow(ip)
+    }
+
+    pub fn allow_email_domain(&self, domain: &str) -> bool {
+        self.email_domain_limiter.allow(&domain.to_lowercase())
+    }
+
+    pub fn allow_email(&self, email: &str) -> bool {
+        self.email_limiter.allow(&email.to_lowercase())
+    }
+
+    /// `Ok(true)` when no challenge is configured.
+    pub async fn verify_challenge(
+        &self,
+        response: Option<&str>,
+        remote_ip: Option<&str>,
+    ) -> Result<bool, anyhow::Error> {
+        match (&self.challenge, response) {
+            (None, _) => Ok(true),
+            (Some(_), None) => Ok(false),
+            (Some(verifier), Some(response)) => verifier.verify(response, remote_ip).await,
+        }
+    }
+}
+
+/// Signs the time the subscribe form was rendered, as `<unix seconds>.<hex hmac>`.
+pub fn form_stamp(secret: &HmacSecret, issued_at: DateTime<Utc>) -> String {
+    let timestamp = issued_at.timestamp().to_string();
+    let signature = hex::encode(form_stamp_mac(secret, &timestamp).finalize().into_bytes());
+    format!("{}.{}", timestamp, signature)
+}
+
+/// Returns the time the stamp was issued at.
+pub fn verify_form_stamp(stamp: &str, secret: &HmacSecret) -> Result<DateTime<Utc>, anyhow::Error> {
+    let (timestamp, signature) = stamp
+        .split_once('.')
+        .context("The form stamp is missing its signature")?;
+    let signature = hex::decode(signature)
+        .context("The form stamp signature is not hex-encoded")?;
+    form_stamp_mac(secret, timestamp)
+        .verify_slice(&signature)
+        .context("The form stamp signature does not match")?;
+    let timestamp: i64 = timestamp.parse().context("The form stamp is not a timestamp")?;
+    DateTime::from_timestamp(timestamp, 0).context("The form stamp is out of range")
+}
+
+fn form_stamp_mac(secret: &HmacSecret, timestamp: &str) -> Hmac<sha2::Sha256> {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(b"form_stamp:");
+    mac.update(timestamp.as_bytes());
+    mac
+}
+
+/// Sliding window limiter keeping the last attempts of each key in memory.
+pub struct RateLimiter {
+    max_attempts: u32,
+    window: Duration,
+    attempts: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl RateLimiter {
+    pub fn new(max_attempts: u32, window: Duration) -> Self {
+        Self {
+            max_attempts,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records an attempt for `key`, returns `false` if it is over the limit.
+    pub fn allow(&self, key: &str) -> bool {
+        self.allow_at(key, Instant::now())
+    }
+
+    fn allow_at(&self, key: &str, now: Instant) -> bool {
+        let mut attempts = self.attempts.lock().unwrap();
+        // drop keys nobody used within the window, so the map does not grow forever
+        if attempts.len() > 10_000 {
+            attempts.retain(|_, a| a.back().is_some_and(|last| now.duration_since(*last) < self.window));
+        }
+        let key_attempts = attempts.entry(key.to_owned()).or_default();
+        while key_attempts
+            .front()
+            .is_some_and(|first| now.duration_since(*first) >= self.window)
+        {
+            key_attempts.pop_front();
+        }
+        if key_attempts.len() >= self.max_attempts as usize {
+            return false;
+        }
+        key_attempts.push_back(now);
+        true
+    }
+}
+
+/// Verifies the response token of a CAPTCHA-like challenge widget.
+pub enum ChallengeVerifier {
+    /// A `siteverify`-style endpoint (hCaptcha, Turnstile, reCAPTCHA): the
+    /// secret and the response are posted as a form, the reply is a JSON
+    /// object with a `success` flag.
+    Remote {
+        http_client: reqwest::Client,
+        verify_url: String,
+        secret_key: Secret<String>,
+    },
+    /// Accepts a single fixed response, for local development and tests.
+    Stub { expected_response: String },
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+impl ChallengeVerifier {
+    pub fn new(settings: &ChallengeSettings) -> Self {
+        match settings {
+            ChallengeSettings::Remote { verify_url, secret_key } => ChallengeVerifier::Remote {
+                http_client: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(5))
+                    .build()
+                    .unwrap(),
+                verify_url: verify_url.clone(),
+                secret_key: secret_key.clone(),
+            },
+            ChallengeSettings::Stub { expected_response } => ChallengeVerifier::Stub {
+                expected_response: expected_response.clone(),
+            },
+        }
+    }
+
+    #[tracing::instrument(name = "Verifying a challenge response", skip(self, response))]
+    pub async fn verify(&self, response: &str, remote_ip: Option<&str>) -> Result<bool, anyhow::Error> {
+        match self {
+            ChallengeVerifier::Remote { http_client, verify_url, secret_key } => {
+                let mut form = vec![
+                    ("secret", secret_key.expose_secret().as_str()),
+                    ("response", response),
+                ];
+                if let Some(ip) = remote_ip {
+                    form.push(("remoteip", ip));
+                }
+                let reply: SiteVerifyResponse = http_client
+                    .post(verify_url)
+                    .form(&form)
+                    .send()
+                    .await
+                    .context("Failed to reach the challenge verification endpoint")?
+                    .error_for_status()
+                    .context("The challenge verification endpoint returned an error")?
+                    .json()
+                    .await
+                    .context("Failed to parse the challenge verification response")?;
+                Ok(reply.success)
+            }
+            ChallengeVerifier::Stub { expected_response } => Ok(response == expected_response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot_protection::{form_stamp, verify_form_stamp, BotProtection, RateLimiter};
+    use crate::configurations::BotProtectionSettings;
+    use crate::startup::HmacSecret;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+    use std::time::Instant;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("a-secret".into()))
+    }
+
+    #[test]
+    fn form_stamps_round_trip_and_reject_tampering() {
+        let issued_at = Utc::now();
+        let stamp = form_stamp(&secret(), issued_at);
+
+        assert_eq!(verify_form_stamp(&stamp, &secret()).unwrap().timestamp(), issued_at.timestamp());
+        let (_, signature) = stamp.split_once('.').unwrap();
+        let forged = format!("{}.{}", issued_at.timestamp() - 60, signature);
+        assert_err!(verify_form_stamp(&forged, &secret()));
+        assert_err!(verify_form_stamp(&stamp, &HmacSecret(Secret::new("another-secret".into()))));
+    }
+
+    #[test]
+    fn form_stamps_must_be_neither_too_fresh_nor_too_old() {
+        let protection = BotProtection::new(&BotProtectionSettings {
+            min_fill_seconds: 3,
+            ..BotProtectionSettings::default()
+        });
+        let now = Utc::now();
+        let stamp_from = |age: Duration| form_stamp(&secret(), now - age);
+
+        assert_err!(protection.check_form_stamp(None, &secret(), now));
+        assert_err!(protection.check_form_stamp(Some(&stamp_from(Duration::seconds(1))), &secret(), now));
+        assert_ok!(protection.check_form_stamp(Some(&stamp_from(Duration::seconds(5))), &secret(), now));
+        assert_err!(protection.check_form_stamp(Some(&stamp_from(Duration::days(2))), &secret(), now));
+    }
+
+    #[test]
+    fn rate_limiter_allows_attempts_again_once_the_window_has_passed() {
+        let limiter = RateLimiter::new(2, std::time::Duration::from_secs(60));
+        let start = Instant::now();
+
+        assert!(limiter.allow_at("1.2.3.4", start));
+        assert!(limiter.allow_at("1.2.3.4", start));
+        assert!(!limiter.allow_at("1.2.3.4", start));
+        assert!(limiter.allow_at("5.6.7.8", start));
+        assert!(limiter.allow_at("1.2.3.4", start + std::time::Duration::from_secs(61)));
+    }
+}
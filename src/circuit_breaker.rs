@@ -0,0 +1,156 @@
+//! src/circuit_breaker.rs
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Tracks consecutive failures of a downstream dependency.
+///
+/// After `failure_threshold` consecutive failures the breaker opens and
+/// `allow_request` returns `false` until `cooldown` has elapsed. Once the
+/// cooldown is over a single probe request is let through: a success closes
+/// the breaker again, a failure re-opens it for another cooldown period.
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: Arc::new(Mutex::new(BreakerState::default())),
+        }
+    }
+
+    /// Returns `None` while the breaker is open. The outcome of the request is
+    /// reported through the returned permit.
+    pub fn allow_request(&self) -> Option<Permit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let is_probe = match state.opened_at {
+            None => false,
+            Some(opened_at) => {
+                if opened_at.elapsed() >= self.cooldown && !state.probe_in_flight {
+                    state.probe_in_flight = true;
+                    true
+                } else {
+                    return None;
+                }
+            }
+        };
+        Some(Permit { breaker: self, is_probe, reported: false })
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        *state = BreakerState::default();
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        state.probe_in_flight = false;
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().opened_at.is_some()
+    }
+}
+
+/// A request let through by the breaker. Dropping it without recording an
+/// outcome, e.g. when the request's future is cancelled, frees the probe slot
+/// so the breaker does not stay open forever.
+pub struct Permit<'a> {
+    breaker: &'a CircuitBreaker,
+    is_probe: bool,
+    reported: bool,
+}
+
+impl Permit<'_> {
+    pub fn record_success(mut self) {
+        self.reported = true;
+        self.breaker.record_success();
+    }
+
+    pub fn record_failure(mut self) {
+        self.reported = true;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.is_probe && !self.reported {
+            self.breaker.state.lock().unwrap().probe_in_flight = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::circuit_breaker::CircuitBreaker;
+    use std::time::Duration;
+
+    #[test]
+    fn breaker_opens_after_reaching_the_failure_threshold() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.allow_request().unwrap().record_failure();
+        assert!(breaker.allow_request().is_some());
+
+        breaker.allow_request().unwrap().record_failure();
+        assert!(breaker.is_open());
+        assert!(breaker.allow_request().is_none());
+    }
+
+    #[test]
+    fn a_single_probe_is_allowed_after_the_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.allow_request().unwrap().record_failure();
+
+        let probe = breaker.allow_request();
+        assert!(probe.is_some());
+        assert!(breaker.allow_request().is_none());
+
+        probe.unwrap().record_failure();
+        assert!(breaker.allow_request().is_some());
+    }
+
+    #[test]
+    fn a_successful_probe_closes_the_breaker() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.allow_request().unwrap().record_failure();
+
+        breaker.allow_request().unwrap().record_success();
+
+        assert!(!breaker.is_open());
+        let first = breaker.allow_request();
+        let second = breaker.allow_request();
+        assert!(first.is_some() && second.is_some());
+    }
+
+    #[test]
+    fn a_probe_dropped_without_an_outcome_lets_the_next_one_through() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.allow_request().unwrap().record_failure();
+
+        let probe = breaker.allow_request();
+        assert!(probe.is_some());
+        drop(probe);
+
+        assert!(breaker.is_open());
+        assert!(breaker.allow_request().is_some());
+    }
+}
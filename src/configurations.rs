@@ -1,11 +1,14 @@
 //! src/configurations.rs
  
+use std::net::IpAddr;
+
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
 
-use crate::domain::SubscriberEmail;
+use crate::dkim::{DkimAlgorithm, DkimSigner};
+use crate::domain::{OptInMode, SubscriberEmail};
 
 #[derive(serde::Deserialize, Clone)]
 // 使用派生 trait 宏自动为结构体实现指定 trait，相当于使用 impl Clone for struct 并递归地为结构体中的每一类型进行 .clone() 方法调用（前提是每一类型都实现了Clone trait）
@@ -15,6 +18,12 @@ pub struct Settings {
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    #[serde(default)]
+    pub bot_protection: BotProtectionSettings,
+    #[serde(default)]
+    pub email_domain_policy: EmailDomainPolicySettings,
+    #[serde(default)]
+    pub maintenance: MaintenanceSettings,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -23,6 +32,134 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_millisec: u64,
+    pub webhook_secret: Secret<String>,
+    #[serde(default)]
+    pub fallback_providers: Vec<EmailProviderSettings>,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerSettings,
+    /// Write emails as `.eml` files into this directory instead of
+    /// posting them to the providers.
+    #[serde(default)]
+    pub file_backend_directory: Option<String>,
+    /// Signs the messages we build ourselves, i.e. the file backend's.
+    #[serde(default)]
+    pub dkim: Option<DkimSettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DkimSettings {
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: DkimAlgorithm,
+    pub private_key: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailProviderSettings {
+    pub base_url: String,
+    pub authorization_token: Secret<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,
+    pub cooldown_millisec: u64,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown_millisec: 30_000,
+        }
+    }
+}
+
+/// Limits for the public `POST /subscriptions` endpoint, see `BotProtection`.
+#[derive(serde::Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    /// Zero disables the signed form timestamp check.
+    pub min_fill_seconds: u64,
+    pub per_ip_limit: u32,
+    pub per_email_domain_limit: u32,
+    /// Confirmation links sent to a single address, by subscribing, resending
+    /// or requesting its data.
+    pub per_email_limit: u32,
+    pub rate_limit_window_seconds: u64,
+    #[serde(default)]
+    pub challenge: Option<ChallengeSettings>,
+}
+
+impl Default for BotProtectionSettings {
+    fn default() -> Self {
+        Self {
+            min_fill_seconds: 3,
+            per_ip_limit: 10,
+            per_email_domain_limit: 100,
+            per_email_limit: 3,
+            rate_limit_window_seconds: 3600,
+            challenge: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailDomainPolicySettings {
+    pub block_disposable: bool,
+    /// Extra disposable domains on top of the bundled list, one per line.
+    #[serde(default)]
+    pub disposable_domains_path: Option<String>,
+}
+
+impl Default for EmailDomainPolicySettings {
+    fn default() -> Self {
+        Self {
+            block_disposable: true,
+            disposable_domains_path: None,
+        }
+    }
+}
+
+/// The periodic cleanup of subscribers who never confirmed, see `maintenance_worker`.
+#[derive(serde::Deserialize, Clone)]
+pub struct MaintenanceSettings {
+    pub interval_seconds: u64,
+    /// Pending subscribers get a single reminder once their confirmation link is this old.
+    pub reminder_after_hours: i64,
+    /// Pending subscribers are deleted once their first confirmation link is
+    /// this old, a reminder does not postpone it.
+    pub pending_retention_days: i64,
+    /// Uploaded imports the admin never confirmed are deleted once this old.
+    pub import_retention_hours: i64,
+}
+
+impl MaintenanceSettings {
+    pub fn interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.interval_seconds)
+    }
+}
+
+impl Default for MaintenanceSettings {
+    fn default() -> Self {
+        Self {
+            interval_seconds: 3600,
+            reminder_after_hours: 24,
+            pending_retention_days: 30,
+            import_retention_hours: 24,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChallengeSettings {
+    Remote {
+        verify_url: String,
+        secret_key: Secret<String>,
+    },
+    Stub {
+        expected_response: String,
+    },
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -32,6 +169,26 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub subscription_token_ttl_hours: u64,
+    /// Used by lists that do not set their own opt-in mode.
+    #[serde(default)]
+    pub opt_in_mode: OptInMode,
+    #[serde(default)]
+    pub redirects: SubscriberRedirects,
+    /// Reverse proxies whose `X-Forwarded-For` entries are believed
+    /// when working out a client's address, anyone else could forge them.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// Pages to send subscribers to instead of our own, for sites hosting their
+/// own thank-you pages.
+#[derive(serde::Deserialize, Clone, Default)]
+pub struct SubscriberRedirects {
+    /// After subscribing through the HTML form, JSON clients are never redirected.
+    pub subscribed_url: Option<String>,
+    /// After clicking a valid confirmation link.
+    pub confirmed_url: Option<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -125,4 +282,27 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_millisec)
     }
+}
+
+impl ApplicationSettings {
+    pub fn subscription_token_ttl(&self) -> chrono::Duration {
+        chrono::Duration::hours(self.subscription_token_ttl_hours as i64)
+    }
+}
+
+impl DkimSettings {
+    pub fn signer(&self) -> Result<DkimSigner, anyhow::Error> {
+        DkimSigner::new(
+            self.domain.clone(),
+            self.selector.clone(),
+            self.algorithm,
+            &self.private_key,
+        )
+    }
+}
+
+impl CircuitBreakerSettings {
+    pub fn cooldown(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.cooldown_millisec)
+    }
 }
\ No newline at end of file
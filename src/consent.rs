@@ -0,0 +1,187 @@
+//! src/consent.rs
+
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+use crate::utils::client_ip;
+
+/// Version of the consent text shown next to the subscribe form (the
+/// `home.consent` message), bump it whenever that text changes.
+pub const CONSENT_TEXT_VERSION: &str = "2025-04-28";
+
+/// Every consent text subscribers may have seen. Keep the old versions when
+/// bumping [`CONSENT_TEXT_VERSION`], pages rendered before still post theirs.
+pub const KNOWN_CONSENT_TEXT_VERSIONS: &[&str] = &[CONSENT_TEXT_VERSION];
+
+pub fn is_known_consent_text_version(version: &str) -> bool {
+    KNOWN_CONSENT_TEXT_VERSIONS.contains(&version)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsentEvent {
+    Subscribed,
+    Confirmed,
+    PreferencesUpdated,
+    Unsubscribed,
+}
+
+impl ConsentEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentEvent::Subscribed => "subscribed",
+            ConsentEvent::Confirmed => "confirmed",
+            ConsentEvent::PreferencesUpdated => "preferences_updated",
+            ConsentEvent::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+/// Where a consent event came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConsentSource {
+    /// One of our HTML forms.
+    Form,
+    /// A JSON request to the subscribe endpoint.
+    Api,
+    /// An admin import of existing subscribers.
+    Import,
+    /// A link emailed to the subscriber, e.g. the confirmation link.
+    EmailLink,
+    /// Support staff acting on the subscriber's behalf.
+    Admin,
+}
+
+impl ConsentSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsentSource::Form => "form",
+            ConsentSource::Api => "api",
+            ConsentSource::Import => "import",
+            ConsentSource::EmailLink => "email_link",
+            ConsentSource::Admin => "admin",
+        }
+    }
+}
+
+/// The circumstances of a consent event, shared by the events recorded while
+/// handling one request.
+#[derive(Debug, Clone)]
+pub struct ConsentContext {
+    pub source: ConsentSource,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub consent_text_version: Option<String>,
+}
+
+impl ConsentContext {
+    pub fn from_request(request: &HttpRequest, source: ConsentSource) -> Self {
+        Self {
+            source,
+            ip_address: client_ip(request),
+            user_agent: request
+                .headers()
+                .get(USER_AGENT)
+                .and_then(|h| h.to_str().ok())
+                .map(ToOwned::to_owned),
+            consent_text_version: None,
+        }
+    }
+
+    /// For changes not made by the subscriber themselves, whose network
+    /// details would say nothing about them.
+    pub fn without_request(source: ConsentSource) -> Self {
+        Self {
+            source,
+            ip_address: None,
+            user_agent: None,
+            consent_text_version: None,
+        }
+    }
+
+    pub fn with_consent_text_version(mut self, version: impl Into<String>) -> Self {
+        self.consent_text_version = Some(version.into());
+        self
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentEventRecord {
+    /// `None` once the subscription has been cleaned up.
+    pub subscriber_id: Option<Uuid>,
+    pub event_type: String,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub consent_text_version: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Appends to the `consent_events` table, which rejects updates and deletes.
+#[tracing::instrument(name = "Recording a consent event", skip(transaction, context))]
+pub async fn record_consent_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    event: ConsentEvent,
+    context: &ConsentContext,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events
+            (id, subscriber_id, event_type, source, ip_address, user_agent, consent_text_version, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        event.as_str(),
+        context.source.as_str(),
+        context.ip_address,
+        context.user_agent,
+        context.consent_text_version,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get consent events", skip(pool))]
+pub async fn get_consent_events(
+    pool: &PgPool,
+    subscriber_ids: &[Uuid],
+) -> Result<Vec<ConsentEventRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEventRecord,
+        r#"
+        SELECT subscriber_id, event_type, source, ip_address, user_agent, consent_text_version, occurred_at
+        FROM consent_events
+        WHERE subscriber_id = ANY($1)
+        ORDER BY occurred_at
+        "#,
+        subscriber_ids,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The consent history of every subscription of an address.
+#[tracing::instrument(name = "Get consent events by email", skip(pool, email))]
+pub async fn get_consent_events_by_email(
+    pool: &PgPool,
+    email: &str,
+) -> Result<Vec<ConsentEventRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEventRecord,
+        r#"
+        SELECT c.subscriber_id, c.event_type, c.source, c.ip_address, c.user_agent, c.consent_text_version, c.occurred_at
+        FROM consent_events c
+        JOIN subscriptions s ON s.id = c.subscriber_id
+        WHERE lower(s.email) = lower($1)
+        ORDER BY c.occurred_at
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+}
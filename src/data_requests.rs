@@ -0,0 +1,382 @@
+//! src/data_requests.rs
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::consent::{get_consent_events, ConsentEventRecord};
+use crate::startup::HmacSecret;
+
+/// How long the link confirming a subscriber-initiated request stays valid.
+const DATA_REQUEST_LINK_TTL_HOURS: i64 = 24;
+
+/// A data-subject request about an email address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataRequestKind {
+    Export,
+    Erasure,
+}
+
+impl DataRequestKind {
+    pub const ALL: [DataRequestKind; 2] = [DataRequestKind::Export, DataRequestKind::Erasure];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataRequestKind::Export => "export",
+            DataRequestKind::Erasure => "erasure",
+        }
+    }
+}
+
+impl TryFrom<String> for DataRequestKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("{} is not a supported data request.", value))
+    }
+}
+
+/// The emailed link proving the requester controls the address. Unlike
+/// `SubscriberLink` it expires, an erasure cannot be undone.
+pub struct DataRequestLink {
+    pub kind: DataRequestKind,
+    pub email: String,
+    pub expires_at: i64,
+}
+
+impl DataRequestLink {
+    pub fn new(kind: DataRequestKind, email: &str, now: DateTime<Utc>) -> Self {
+        Self {
+            kind,
+            email: email.to_owned(),
+            expires_at: (now + Duration::hours(DATA_REQUEST_LINK_TTL_HOURS)).timestamp(),
+        }
+    }
+
+    pub fn url(&self, base_url: &str, secret: &HmacSecret) -> String {
+        format!(
+            "{}/subscriptions/data/confirm?request={}&email={}&expires_at={}&signature={}",
+            base_url,
+            self.kind.as_str(),
+            urlencoding::encode(&self.email),
+            self.expires_at,
+            self.sign(secret),
+        )
+    }
+
+    pub fn sign(&self, secret: &HmacSecret) -> String {
+        hex::encode(self.mac(secret).finalize().into_bytes())
+    }
+
+    pub fn verify(&self, signature: &str, secret: &HmacSecret, now: DateTime<Utc>) -> Result<(), anyhow::Error> {
+        let signature = hex::decode(signature)
+            .context("The signature is not hex-encoded")?;
+        self.mac(secret)
+            .verify_slice(&signature)
+            .context("The signature does not match the request")?;
+        if now.timestamp() > self.expires_at {
+            anyhow::bail!("The data request link has expired");
+        }
+        Ok(())
+    }
+
+    fn mac(&self, secret: &HmacSecret) -> Hmac<sha2::Sha256> {
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"data_request:");
+        mac.update(self.kind.as_str().as_bytes());
+        mac.update(b":");
+        mac.update(self.expires_at.to_string().as_bytes());
+        mac.update(b":");
+        mac.update(self.email.to_lowercase().as_bytes());
+        mac
+    }
+}
+
+/// Everything stored about an email address.
+#[derive(serde::Serialize)]
+pub struct SubscriberDataExport {
+    pub email: String,
+    pub exported_at: DateTime<Utc>,
+    pub subscriptions: Vec<SubscriptionRecord>,
+    pub subscription_tokens: Vec<SubscriptionTokenRecord>,
+    pub list_opt_outs: Vec<ListOptOutRecord>,
+    pub email_events: Vec<EmailEventRecord>,
+    pub suppressions: Vec<SuppressionRecord>,
+    pub consent_events: Vec<ConsentEventRecord>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub email_display: Option<String>,
+    pub name: String,
+    pub status: String,
+    pub locale: String,
+    pub frequency: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub unsubscribe_reason: Option<String>,
+    pub tags: Vec<String>,
+    pub attributes: serde_json::Value,
+    pub utm_source: Option<String>,
+    pub utm_medium: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub utm_term: Option<String>,
+    pub utm_content: Option<String>,
+    pub referrer: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionTokenRecord {
+    pub subscriber_id: Uuid,
+    pub subscription_token: String,
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ListOptOutRecord {
+    pub subscriber_id: Uuid,
+    pub list_key: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailEventRecord {
+    pub id: Uuid,
+    pub event_type: String,
+    pub description: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SuppressionRecord {
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Check whether we hold data about an address", skip(pool, email))]
+pub async fn has_subscriber_data(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM subscriptions WHERE lower(email) = lower($1)
+        ) OR EXISTS (
+            SELECT 1 FROM email_events WHERE lower(email) = lower($1)
+        ) AS "exists!"
+        "#,
+        email,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.exists)
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(pool, email))]
+pub async fn export_subscriber_data(pool: &PgPool, email: &str) -> Result<SubscriberDataExport, anyhow::Error> {
+    let subscriptions = sqlx::query_as!(
+        SubscriptionRecord,
+        r#"
+        SELECT id, email, email_display, name, status, locale, frequency, subscribed_at, unsubscribed_at, unsubscribe_reason, tags, attributes,
+            utm_source, utm_medium, utm_campaign, utm_term, utm_content, referrer
+        FROM subscriptions
+        WHERE lower(email) = lower($1)
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to export the subscriptions")?;
+    let ids: Vec<Uuid> = subscriptions.iter().map(|s| s.id).collect();
+
+    let subscription_tokens = sqlx::query_as!(
+        SubscriptionTokenRecord,
+        r#"
+        SELECT subscriber_id, subscription_token, created_at, used_at
+        FROM subscription_tokens
+        WHERE subscriber_id = ANY($1)
+        ORDER BY created_at
+        "#,
+        &ids,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to export the subscription tokens")?;
+    let list_opt_outs = sqlx::query_as!(
+        ListOptOutRecord,
+        r#"SELECT subscriber_id, list_key FROM list_opt_outs WHERE subscriber_id = ANY($1)"#,
+        &ids,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to export the list opt-outs")?;
+    let email_events = sqlx::query_as!(
+        EmailEventRecord,
+        r#"
+        SELECT id, event_type, description, received_at
+        FROM email_events
+        WHERE subscriber_id = ANY($1) OR lower(email) = lower($2)
+        ORDER BY received_at
+        "#,
+        &ids,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to export the delivery history")?;
+    let suppressions = sqlx::query_as!(
+        SuppressionRecord,
+        r#"
+        SELECT reason, source, created_at
+        FROM suppressions
+        WHERE kind = 'address' AND value = lower($1)
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to export the suppressions")?;
+    let consent_events = get_consent_events(pool, &ids).await
+        .context("Failed to export the consent history")?;
+
+    Ok(SubscriberDataExport {
+        email: email.to_owned(),
+        exported_at: Utc::now(),
+        subscriptions,
+        subscription_tokens,
+        list_opt_outs,
+        email_events,
+        suppressions,
+        consent_events,
+    })
+}
+
+/// Deletes the tokens, opt-outs and pending imports of an address and
+/// anonymizes its subscriptions and delivery history in place, so counts by
+/// status, date or event type stay correct.
+///
+/// Suppression list entries are kept: they are what guarantees the address is
+/// never emailed again. So is the consent history, which is append-only, only
+/// its IP addresses and user agents are cleared.
+#[tracing::instrument(name = "Erase subscriber data", skip(pool, email))]
+pub async fn erase_subscriber_data(pool: &PgPool, email: &str) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let ids: Vec<Uuid> = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE"#,
+        email,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to look up the subscriptions to erase")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    sqlx::query!(r#"DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)"#, &ids)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the subscription tokens")?;
+    sqlx::query!(r#"DELETE FROM list_opt_outs WHERE subscriber_id = ANY($1)"#, &ids)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the list opt-outs")?;
+    sqlx::query!(r#"DELETE FROM list_deliveries WHERE subscriber_id = ANY($1)"#, &ids)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the list deliveries")?;
+    sqlx::query!(
+        r#"
+        UPDATE email_events
+        SET email = '', description = NULL
+        WHERE subscriber_id = ANY($1) OR lower(email) = lower($2)
+        "#,
+        &ids,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize the delivery history")?;
+    // an upload waiting for its import cannot be edited, it is dropped whole
+    sqlx::query!(
+        r#"DELETE FROM subscriber_imports WHERE imported_at IS NULL AND strpos(lower(csv), lower($1)) > 0"#,
+        email,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the pending imports")?;
+    sqlx::query!(
+        r#"UPDATE consent_events SET ip_address = NULL, user_agent = NULL WHERE subscriber_id = ANY($1)"#,
+        &ids,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize the consent history")?;
+    // the placeholder address keeps the unique index on lower(email) satisfied
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET email = 'erased-' || id || '@erased.invalid',
+            email_display = NULL,
+            name = '',
+            status = 'erased',
+            unsubscribed_at = COALESCE(unsubscribed_at, $2),
+            unsubscribe_reason = NULL,
+            tags = '{}',
+            attributes = '{}'
+        WHERE id = ANY($1)
+        "#,
+        &ids,
+        Utc::now(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize the subscriptions")?;
+
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to erase subscriber data")?;
+    Ok(ids.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_requests::{DataRequestKind, DataRequestLink};
+    use crate::startup::HmacSecret;
+    use chrono::{Duration, Utc};
+    use claim::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    fn secret() -> HmacSecret {
+        HmacSecret(Secret::new("a-secret".into()))
+    }
+
+    #[test]
+    fn data_request_links_cover_the_request_and_the_address() {
+        let now = Utc::now();
+        let link = DataRequestLink::new(DataRequestKind::Export, "ursula@example.com", now);
+        let signature = link.sign(&secret());
+
+        assert_ok!(link.verify(&signature, &secret(), now));
+        let erasure = DataRequestLink { kind: DataRequestKind::Erasure, ..link };
+        assert_err!(erasure.verify(&signature, &secret(), now));
+        let other_address = DataRequestLink::new(DataRequestKind::Export, "someone@example.com", now);
+        assert_err!(other_address.verify(&signature, &secret(), now));
+    }
+
+    #[test]
+    fn data_request_links_expire() {
+        let now = Utc::now();
+        let link = DataRequestLink::new(DataRequestKind::Erasure, "ursula@example.com", now);
+        let signature = link.sign(&secret());
+
+        assert_err!(link.verify(&signature, &secret(), now + Duration::hours(25)));
+    }
+}
@@ -0,0 +1,39 @@
+//! src/domain/delivery_frequency.rs
+
+/// How often a subscriber wants to hear from us. Weekly and monthly
+/// subscribers skip the issues of a list published within a week or a month
+/// of the last one of that list they got.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DeliveryFrequency {
+    #[default]
+    EveryIssue,
+    Weekly,
+    Monthly,
+}
+
+impl DeliveryFrequency {
+    pub const ALL: [DeliveryFrequency; 3] = [
+        DeliveryFrequency::EveryIssue,
+        DeliveryFrequency::Weekly,
+        DeliveryFrequency::Monthly,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryFrequency::EveryIssue => "every_issue",
+            DeliveryFrequency::Weekly => "weekly",
+            DeliveryFrequency::Monthly => "monthly",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryFrequency {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.as_str() == value)
+            .ok_or_else(|| format!("{} is not a supported delivery frequency.", value))
+    }
+}
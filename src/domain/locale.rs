@@ -0,0 +1,95 @@
+//! src/domain/locale.rs
+
+/// The languages subscriber-facing emails and pages are available in.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Locale {
+    #[default]
+    English,
+    Chinese,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::English, Locale::Chinese];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::English => "en",
+            Locale::Chinese => "zh",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Locale::English => "English",
+            Locale::Chinese => "中文",
+        }
+    }
+
+    /// Accepts language tags such as `zh`, `zh-CN` or `en_US`, only the
+    /// primary subtag is taken into account.
+    pub fn parse(tag: &str) -> Result<Locale, String> {
+        let primary = tag
+            .trim()
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|l| l.as_str() == primary)
+            .ok_or_else(|| format!("{} is not a supported locale.", tag))
+    }
+
+    /// Picks the supported language with the highest quality value from an
+    /// `Accept-Language` header, e.g. `fr-CH, fr;q=0.9, zh;q=0.8, en;q=0.5`.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut best: Option<(Locale, f32)> = None;
+        for item in header.split(',') {
+            let mut parts = item.split(';');
+            let tag = parts.next().unwrap_or_default();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map(|q| q.trim().parse::<f32>().unwrap_or(0.0))
+                .unwrap_or(1.0);
+            if let Ok(locale) = Locale::parse(tag) {
+                if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                    best = Some((locale, quality));
+                }
+            }
+        }
+        best.map(|(locale, _)| locale)
+    }
+}
+
+impl TryFrom<String> for Locale {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Locale;
+    use claim::{assert_err, assert_none};
+
+    #[test]
+    fn region_subtags_are_ignored() {
+        assert_eq!(Locale::parse("zh-CN"), Ok(Locale::Chinese));
+        assert_eq!(Locale::parse("EN_us"), Ok(Locale::English));
+        assert_err!(Locale::parse("fr"));
+    }
+
+    #[test]
+    fn the_supported_language_with_the_highest_quality_is_picked() {
+        let locale = Locale::from_accept_language("fr-CH, fr;q=0.9, en;q=0.5, zh-TW;q=0.8");
+        assert_eq!(locale, Some(Locale::Chinese));
+    }
+
+    #[test]
+    fn unsupported_or_refused_languages_are_ignored() {
+        assert_none!(Locale::from_accept_language("fr, de;q=0.8, zh;q=0"));
+        assert_none!(Locale::from_accept_language(""));
+    }
+}
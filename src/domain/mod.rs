@@ -3,7 +3,13 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod locale;
+mod delivery_frequency;
+mod opt_in_mode;
 
 pub use subscriber_name::SubscriberName;
 pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
\ No newline at end of file
+pub use subscriber_email::SubscriberEmail;
+pub use locale::Locale;
+pub use delivery_frequency::DeliveryFrequency;
+pub use opt_in_mode::OptInMode;
\ No newline at end of file
@@ -1,8 +1,9 @@
 //! src/domain/new_subscriber.rs
 
-use crate::domain::{subscriber_name::SubscriberName, subscriber_email::SubscriberEmail};
+use crate::domain::{subscriber_name::SubscriberName, subscriber_email::SubscriberEmail, Locale};
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub locale: Locale,
 }
\ No newline at end of file
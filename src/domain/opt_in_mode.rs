@@ -0,0 +1,45 @@
+//! src/domain/opt_in_mode.rs
+
+/// Whether new subscribers have to confirm their address before they
+/// receive newsletters.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OptInMode {
+    /// Subscribers get a confirmation link and stay pending until they click it.
+    #[default]
+    Double,
+    /// Subscribers are confirmed right away and get a welcome email, only for
+    /// consent recorded elsewhere (e.g. at an event). Imports into a single
+    /// opt-in list and admin additions use it, public subscriptions only if it
+    /// is the global setting.
+    Single,
+}
+
+impl OptInMode {
+    pub const ALL: [OptInMode; 2] = [OptInMode::Double, OptInMode::Single];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OptInMode::Double => "double",
+            OptInMode::Single => "single",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OptInMode::Double => "Double opt-in",
+            OptInMode::Single => "Single opt-in",
+        }
+    }
+}
+
+impl TryFrom<String> for OptInMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|m| m.as_str() == value)
+            .ok_or_else(|| format!("{} is not a supported opt-in mode.", value))
+    }
+}
@@ -3,28 +3,49 @@
 use validator::validate_email;
 
 #[derive(Debug, Clone)]
-pub struct SubscriberEmail(String); // 元祖结构体，直接用索引访问值（.0）
+pub struct SubscriberEmail {
+    normalized: String,
+    display: String,
+}
 
 impl SubscriberEmail {
+    /// The domain is lowercased and converted to punycode, the local part is
+    /// kept as typed. Addresses are unique regardless of case, see the
+    /// `subscriptions_email_lower_key` index.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
+        let invalid = || format!("{} is not a valid subscriber email", s);
+        let trimmed = s.trim();
+        let (local_part, domain) = trimmed.rsplit_once('@').ok_or_else(invalid)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        let normalized = format!("{}@{}", local_part, domain);
+        if validate_email(&normalized) {
+            Ok(Self { normalized, display: trimmed.to_owned() })
         } else {
-            Err(format!("{} is not a valid subscriber email", s))
+            Err(invalid())
         }
     }
+
+    /// The part after the `@`.
+    pub fn domain(&self) -> &str {
+        self.normalized.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default()
+    }
+
+    /// The address as it was typed, e.g. with an internationalized domain.
+    pub fn display_form(&self) -> &str {
+        &self.display
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.normalized
     }
 }
 
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // write!(f, "{}", self.0)
-        self.0.fmt(f)
+        self.normalized.fmt(f)
     }
     
 }
@@ -33,9 +54,9 @@ impl std::fmt::Display for SubscriberEmail {
 mod tests {
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
-    // use claim::assert_err;
 
     use crate::domain::SubscriberEmail;
+    use claim::assert_err;
 
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
@@ -52,4 +73,24 @@ mod tests {
         dbg!(&valid_email.0);
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn the_domain_is_normalized_and_the_local_part_kept() {
+        let email = SubscriberEmail::parse(" Alice@Example.COM ".into()).unwrap();
+        assert_eq!(email.as_ref(), "Alice@example.com");
+    }
+
+    #[test]
+    fn internationalized_domains_are_converted_to_punycode() {
+        let email = SubscriberEmail::parse("ursula@Bücher.de".into()).unwrap();
+        assert_eq!(email.as_ref(), "ursula@xn--bcher-kva.de");
+        assert_eq!(email.display_form(), "ursula@Bücher.de");
+    }
+
+    #[test]
+    fn addresses_without_a_valid_domain_are_rejected() {
+        for email in ["ursula", "ursula@", "@example.com", "ursula@exa mple.com"] {
+            assert_err!(SubscriberEmail::parse(email.into()));
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! src/domain_policy.rs
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::configurations::EmailDomainPolicySettings;
+use crate::domain::SubscriberEmail;
+
+const BUNDLED_DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+/// An admin override for a domain and its subdomains.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DomainRule {
+    /// Accepted even if the domain is on the disposable list.
+    Allow,
+    Block,
+}
+
+impl DomainRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DomainRule::Allow => "allow",
+            DomainRule::Block => "block",
+        }
+    }
+}
+
+impl TryFrom<String> for DomainRule {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "allow" => Ok(Self::Allow),
+            "block" => Ok(Self::Block),
+            other => Err(format!("{} is not a supported domain rule. Use either 'allow' or 'block'.", other)),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DomainPolicyError {
+    #[error("{0}")]
+    Rejected(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+pub struct DomainRuleEntry {
+    pub domain: String,
+    pub rule: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Decides which email domains may subscribe: the most specific admin rule
+/// first, then the list of disposable email providers.
+#[derive(Clone)]
+pub struct DomainPolicy {
+    pool: PgPool,
+    block_disposable: bool,
+    disposable_domains: Arc<HashSet<String>>,
+}
+
+impl DomainPolicy {
+    pub fn new(pool: PgPool, settings: &EmailDomainPolicySettings) -> Result<Self, anyhow::Error> {
+        let mut disposable_domains = parse_domain_list(BUNDLED_DISPOSABLE_DOMAINS);
+        if let Some(path) = &settings.disposable_domains_path {
+            let extra = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the disposable domain list at {}", path))?;
+            disposable_domains.extend(parse_domain_list(&extra));
+        }
+        Ok(Self {
+            pool,
+            block_disposable: settings.block_disposable,
+            disposable_domains: Arc::new(disposable_domains),
+        })
+    }
+
+    #[tracing::instrument(name = "Checking the email domain policy", skip(self, email))]
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), DomainPolicyError> {
+        let domain = email.domain().to_lowercase();
+        let candidates = parent_domains(&domain);
+        let rules = sqlx::query!(
+            r#"SELECT domain, rule FROM email_domain_rules WHERE domain = ANY($1)"#,
+            &candidates,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to retrieve the email domain rules")?;
+
+        // the rule closest to the address decides, a block on a subdomain
+        // wins over an allow on its parent and the other way around
+        let rule = rules.into_iter()
+            .max_by_key(|r| r.domain.len())
+            .map(|r| DomainRule::try_from(r.rule))
+            .transpose()
+            .map_err(anyhow::Error::msg)?;
+        match rule {
+            Some(DomainRule::Allow) => return Ok(()),
+            Some(DomainRule::Block) => {
+                return Err(DomainPolicyError::Rejected(format!(
+                    "Email addresses at {} are not accepted.",
+                    domain,
+                )))
+            }
+            None => {}
+        }
+        if self.block_disposable && candidates.iter().any(|d| self.disposable_domains.contains(d)) {
+            return Err(DomainPolicyError::Rejected(format!(
+                "{} is a disposable email provider, please use a permanent address.",
+                domain,
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// `mail.example.com` yields `mail.example.com` and `example.com`, a bare
+/// top level domain is never a candidate.
+pub(crate) fn parent_domains(domain: &str) -> Vec<String> {
+    let mut candidates = vec![];
+    let mut rest = domain;
+    while rest.contains('.') {
+        candidates.push(rest.to_owned());
+        rest = rest.split_once('.').map(|(_, parent)| parent).unwrap_or_default();
+    }
+    candidates
+}
+
+fn parse_domain_list(list: &str) -> HashSet<String> {
+    list.lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| idna::domain_to_ascii(l).ok())
+        .collect()
+}
+
+/// Domains are converted to punycode the way `SubscriberEmail::parse` does
+/// it, so rules match the `SubscriberEmail::domain` they are checked against.
+pub fn parse_domain(value: &str) -> Result<String, String> {
+    let value = value.trim().trim_start_matches('@');
+    let invalid = || format!("{} is not a valid domain.", value);
+    let domain = idna::domain_to_ascii(value).map_err(|_| invalid())?;
+    let is_valid = domain.contains('.')
+        && !domain.contains('@')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !domain.chars().any(char::is_whitespace);
+    if is_valid {
+        Ok(domain)
+    } else {
+        Err(invalid())
+    }
+}
+
+#[tracing::instrument(name = "Get email domain rules", skip(pool))]
+pub async fn get_domain_rules(pool: &PgPool) -> Result<Vec<DomainRuleEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        DomainRuleEntry,
+        r#"SELECT domain, rule, created_at FROM email_domain_rules ORDER BY domain"#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Replaces any existing rule for the domain.
+#[tracing::instrument(name = "Set an email domain rule", skip(pool))]
+pub async fn set_domain_rule(pool: &PgPool, domain: &str, rule: DomainRule) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_domain_rules (domain, rule, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (domain) DO UPDATE
+        SET rule = EXCLUDED.rule, created_at = EXCLUDED.created_at
+        "#,
+        domain,
+        rule.as_str(),
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Remove an email domain rule", skip(pool))]
+pub async fn remove_domain_rule(pool: &PgPool, domain: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM email_domain_rules WHERE domain = $1"#, domain)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain_policy::{parent_domains, parse_domain, parse_domain_list, BUNDLED_DISPOSABLE_DOMAINS};
+    use claim::assert_err;
+
+    #[test]
+    fn subdomains_are_matched_against_their_parents() {
+        assert_eq!(
+            parent_domains("inbox.mailinator.com"),
+            vec!["inbox.mailinator.com", "mailinator.com"],
+        );
+        assert!(parent_domains("localhost").is_empty());
+    }
+
+    #[test]
+    fn the_bundled_list_skips_comments_and_blank_lines() {
+        let domains = parse_domain_list(BUNDLED_DISPOSABLE_DOMAINS);
+
+        assert!(domains.contains("mailinator.com"));
+        assert!(domains.iter().all(|d| !d.starts_with('#') && !d.is_empty()));
+    }
+
+    #[test]
+    fn domains_are_normalized_and_validated() {
+        assert_eq!(parse_domain(" @Example.COM ").unwrap(), "example.com");
+        for domain in ["", "example", "user@example.com", ".example.com", "exa mple.com"] {
+            assert_err!(parse_domain(domain));
+        }
+    }
+
+    #[test]
+    fn internationalized_domains_are_converted_to_punycode() {
+        assert_eq!(parse_domain("Bücher.de").unwrap(), "xn--bcher-kva.de");
+        assert_eq!(parse_domain("@\u{ff22}ücher\u{3002}de").unwrap(), "xn--bcher-kva.de");
+        assert!(parse_domain_list("bücher.de\n").contains("xn--bcher-kva.de"));
+    }
+}
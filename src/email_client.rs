@@ -1,15 +1,40 @@
 //! src/email_client.rs
 
+use crate::circuit_breaker::CircuitBreaker;
+use crate::dkim::DkimSigner;
 use crate::domain::SubscriberEmail;
+use crate::suppression::SuppressionList;
+use base64::Engine;
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct EmailClient {
     sender: SubscriberEmail,
     http_client: Client,
+    providers: Vec<EmailProvider>,  // tried in order, the first healthy one wins
+    failure_threshold: u32,
+    cooldown: std::time::Duration,
+    suppression_list: Option<SuppressionList>,
+    file_backend: Option<FileBackend>,
+}
+
+/// Writes raw messages to a directory instead of posting them to a provider,
+/// so we build and sign them ourselves.
+#[derive(Clone)]
+struct FileBackend {
+    directory: PathBuf,
+    dkim_signer: Option<Arc<DkimSigner>>,
+}
+
+#[derive(Clone)]
+struct EmailProvider {
     base_url: String,  // stored the link to trigger a third-party email sending service API
     authorization_token: Secret<String>,
+    circuit_breaker: CircuitBreaker,
 }
 
 #[derive(serde::Serialize)]
@@ -23,7 +48,22 @@ pub struct SendEmailRequest<'a> {
     text_body: &'a str,
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error("Failed to send the email request")]
+    RequestError(#[from] reqwest::Error),
+    #[error("No email provider is currently available")]
+    NoProviderAvailable,
+    #[error("Failed to check the suppression list")]
+    SuppressionListError(#[source] sqlx::Error),
+    #[error("Failed to write the email to the file backend")]
+    FileBackendError(#[source] std::io::Error),
+}
+
 impl EmailClient {
+    const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+    const DEFAULT_COOLDOWN: std::time::Duration = std::time::Duration::from_secs(30);
+
     pub fn new(
         base_url: String, 
         sender: SubscriberEmail, 
@@ -37,53 +77,251 @@ impl EmailClient {
 
         Self {
             http_client,
-            base_url,
             sender,
+            providers: vec![],
+            failure_threshold: Self::DEFAULT_FAILURE_THRESHOLD,
+            cooldown: Self::DEFAULT_COOLDOWN,
+            suppression_list: None,
+            file_backend: None,
+        }
+        .with_fallback_provider(base_url, authorization_token)
+    }
+
+    /// Appends a provider that is used when every provider before it fails
+    /// or has its circuit breaker open.
+    pub fn with_fallback_provider(
+        mut self,
+        base_url: String,
+        authorization_token: Secret<String>,
+    ) -> Self {
+        self.providers.push(EmailProvider {
+            base_url,
             authorization_token,
+            circuit_breaker: CircuitBreaker::new(self.failure_threshold, self.cooldown),
+        });
+        self
+    }
+
+    pub fn with_circuit_breaker(
+        mut self,
+        failure_threshold: u32,
+        cooldown: std::time::Duration,
+    ) -> Self {
+        self.failure_threshold = failure_threshold;
+        self.cooldown = cooldown;
+        for provider in self.providers.iter_mut() {
+            provider.circuit_breaker = CircuitBreaker::new(failure_threshold, cooldown);
         }
+        self
+    }
+
+    /// Recipients on the suppression list are silently skipped.
+    pub fn with_suppression_list(mut self, suppression_list: SuppressionList) -> Self {
+        self.suppression_list = Some(suppression_list);
+        self
+    }
+
+    /// Every email is written to `directory` as an `.eml` file, DKIM signed
+    /// when a signer is given, and the providers are not called at all.
+    pub fn with_file_backend(mut self, directory: PathBuf, dkim_signer: Option<DkimSigner>) -> Self {
+        self.file_backend = Some(FileBackend {
+            directory,
+            dkim_signer: dkim_signer.map(Arc::new),
+        });
+        self
     }
+
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
-        // todo!()
-        let url = format!("{}/email", self.base_url);   // 此处 format! 宏没有消耗 base_url（使用的是其引用）
-        // base_url/email is a third-party service provider defined, sending-service request link format 
+    ) -> Result<(), SendEmailError> {
+        if let Some(suppression_list) = &self.suppression_list {
+            let is_suppressed = suppression_list
+                .is_suppressed(recipient)
+                .await
+                .map_err(SendEmailError::SuppressionListError)?;
+            if is_suppressed {
+                let suppressed_sends = suppression_list.record_suppressed_send();
+                tracing::info!(
+                    recipient = %recipient,
+                    suppressed_sends,
+                    "Skipping an email to a suppressed recipient"
+                );
+                return Ok(());
+            }
+        }
+
+        if let Some(file_backend) = &self.file_backend {
+            return self
+                .write_to(file_backend, recipient, subject, html_content, text_content)
+                .await
+                .map_err(SendEmailError::FileBackendError);
+        }
+
         let request_body = SendEmailRequest {
             // from: self.sender.as_ref().to_owned(),
             from: self.sender.as_ref(),
             to: recipient.as_ref(),
-            subject: subject,
+            subject,
             html_body: html_content,
             text_body: text_content,
         };
+
+        let mut last_error = None;
+        for provider in &self.providers {
+            let Some(permit) = provider.circuit_breaker.allow_request() else {
+                tracing::warn!(
+                    provider = %provider.base_url,
+                    "Skipping email provider because its circuit breaker is open"
+                );
+                continue;
+            };
+            match self.send_with(provider, &request_body).await {
+                Ok(()) => {
+                    permit.record_success();
+                    return Ok(());
+                }
+                Err(e) if should_fail_over(&e) => {
+                    permit.record_failure();
+                    tracing::warn!(
+                        provider = %provider.base_url,
+                        error.cause_chain = ?e,
+                        "Email provider failed, falling over to the next one"
+                    );
+                    last_error = Some(e);
+                }
+                Err(e) => {
+                    // A 4xx means the provider is up but rejected this particular request,
+                    // the next provider would not do any better.
+                    permit.record_success();
+                    return Err(e.into());
+                }
+            }
+        }
+
+        Err(last_error.map_or(SendEmailError::NoProviderAvailable, SendEmailError::RequestError))
+    }
+
+    async fn send_with(
+        &self,
+        provider: &EmailProvider,
+        request_body: &SendEmailRequest<'_>,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!("{}/email", provider.base_url);   // 此处 format! 宏没有消耗 base_url（使用的是其引用）
+        // base_url/email is a third-party service provider defined, sending-service request link format 
         let _builder = self
             .http_client
             .post(&url)
             .header(
                 "Some-sort-of-a-token", 
-                self.authorization_token.expose_secret(),
+                provider.authorization_token.expose_secret(),
             )
-            .json(&request_body)
+            .json(request_body)
             .send()
             .await?
             .error_for_status()?;
         Ok(())
     }
+
+    async fn write_to(
+        &self,
+        file_backend: &FileBackend,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), std::io::Error> {
+        let message_id = format!("{}@{}", Uuid::new_v4(), self.sender.domain());
+        let message_id_header = format!("<{}>", message_id);
+        let subject = encode_header_value(subject);
+        let date = chrono::Utc::now().to_rfc2822();
+        let boundary = format!("=_{}", Uuid::new_v4().simple());
+        let content_type = format!("multipart/alternative; boundary=\"{}\"", boundary);
+        let headers = [
+            ("From", self.sender.as_ref()),
+            ("To", recipient.as_ref()),
+            ("Subject", subject.as_str()),
+            ("Date", date.as_str()),
+            ("Message-ID", message_id_header.as_str()),
+            ("MIME-Version", "1.0"),
+            ("Content-Type", content_type.as_str()),
+        ];
+        let body = format!(
+            "--{boundary}\r\n\
+            Content-Type: text/plain; charset=utf-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            {}\r\n\
+            --{boundary}\r\n\
+            Content-Type: text/html; charset=utf-8\r\n\
+            Content-Transfer-Encoding: base64\r\n\r\n\
+            {}\r\n\
+            --{boundary}--\r\n",
+            encode_body_part(text_content),
+            encode_body_part(html_content),
+        );
+
+        let mut message = String::new();
+        if let Some(dkim_signer) = &file_backend.dkim_signer {
+            message.push_str(&format!("DKIM-Signature: {}\r\n", dkim_signer.sign(&headers, &body)));
+        }
+        for (name, value) in headers {
+            message.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        message.push_str("\r\n");
+        message.push_str(&body);
+
+        tokio::fs::create_dir_all(&file_backend.directory).await?;
+        tokio::fs::write(file_backend.directory.join(format!("{}.eml", message_id)), message).await
+    }
+}
+
+/// Non-ASCII text (and anything that could start a new header line) becomes
+/// an RFC 2047 encoded word.
+fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() && !value.contains(['\r', '\n']) {
+        value.to_owned()
+    } else {
+        format!("=?utf-8?B?{}?=", base64::engine::general_purpose::STANDARD.encode(value))
+    }
+}
+
+/// Base64 in lines of 76 characters, so no line gets near the 998 limit.
+fn encode_body_part(content: &str) -> String {
+    base64::engine::general_purpose::STANDARD
+        .encode(content)
+        .as_bytes()
+        .chunks(76)
+        .map(|line| std::str::from_utf8(line).expect("base64 is ASCII"))
+        .collect::<Vec<_>>()
+        .join("\r\n")
+}
+
+/// Transport errors (connection refused, timeouts, ...) and 5xx responses are
+/// treated as a provider outage.
+fn should_fail_over(e: &reqwest::Error) -> bool {
+    match e.status() {
+        Some(status) => status.is_server_error(),
+        None => true,
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::dkim::{canonicalize_body_relaxed, canonicalize_header_relaxed, DkimAlgorithm, DkimSigner};
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{EmailClient, SendEmailError};
+    use base64::Engine;
+    use ed25519_dalek::Verifier;
     use claim::{assert_ok, assert_err};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
     use wiremock::matchers::{any, header, header_exists, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
@@ -203,4 +441,226 @@ mod tests {
 
         assert_err!(outcome);
     }
+
+    fn email_client_with_fallback(primary: String, fallback: String) -> EmailClient {
+        email_client(primary)
+            .with_circuit_breaker(1, std::time::Duration::from_secs(60))
+            .with_fallback_provider(fallback, Secret::new(Faker.fake()))
+    }
+
+    async fn send_test_email(email_client: &EmailClient) -> Result<(), SendEmailError> {
+        let subscriber_email = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let subject: String = Sentence(1..2).fake();
+        let content: String = Paragraph(1..10).fake();
+
+        email_client.send_email(&subscriber_email, &subject, &content, &content).await
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_over_to_the_next_provider_if_the_primary_returns_500() {
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), fallback.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        let outcome = send_test_email(&email_client).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_falls_over_to_the_next_provider_if_the_primary_times_out() {
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), fallback.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180)))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        let outcome = send_test_email(&email_client).await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_fall_over_on_a_client_error() {
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), fallback.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback)
+            .await;
+
+        let outcome = send_test_email(&email_client).await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn an_unhealthy_provider_is_skipped_until_its_cooldown_is_over() {
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), fallback.uri());
+
+        // the breaker opens after the first failure, the second email goes straight to the fallback
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&fallback)
+            .await;
+
+        assert_ok!(send_test_email(&email_client).await);
+        assert_ok!(send_test_email(&email_client).await);
+    }
+
+    #[tokio::test]
+    async fn an_unhealthy_provider_is_probed_again_after_its_cooldown() {
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = email_client(primary.uri())
+            .with_circuit_breaker(1, std::time::Duration::ZERO)
+            .with_fallback_provider(fallback.uri(), Secret::new(Faker.fake()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        assert_ok!(send_test_email(&email_client).await);
+        assert_ok!(send_test_email(&email_client).await);
+    }
+
+    // Ed25519 key pair from RFC 8463, appendix A
+    const DKIM_ED25519_KEY: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+    const DKIM_ED25519_PUBLIC_KEY: &str = "11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+
+    #[tokio::test]
+    async fn the_file_backend_writes_a_dkim_signed_message_instead_of_calling_the_providers() {
+        let mock_server = MockServer::start().await;
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let signer = DkimSigner::new(
+            "example.com".into(),
+            "newsletter".into(),
+            DkimAlgorithm::Ed25519Sha256,
+            &Secret::new(DKIM_ED25519_KEY.into()),
+        )
+        .unwrap();
+        let email_client = email_client(mock_server.uri()).with_file_backend(directory.clone(), Some(signer));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok!(send_test_email(&email_client).await);
+
+        let files: Vec<_> = std::fs::read_dir(&directory).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let message = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let (header_block, body) = message.split_once("\r\n\r\n").unwrap();
+        let headers: Vec<(&str, &str)> = header_block
+            .split("\r\n")
+            .map(|line| line.split_once(": ").unwrap())
+            .collect();
+        let (name, dkim_header) = headers[0];
+        assert_eq!(name, "DKIM-Signature");
+
+        let body_hash = base64::engine::general_purpose::STANDARD
+            .encode(Sha256::digest(canonicalize_body_relaxed(body).as_bytes()));
+        assert!(dkim_header.contains(&format!("bh={};", body_hash)));
+
+        let (unsigned, signature) = dkim_header.split_once(" b=").unwrap();
+        let signed_names = unsigned.split("h=").nth(1).unwrap().split(';').next().unwrap();
+        let mut signed_data: String = signed_names
+            .split(':')
+            .map(|name| {
+                let (_, value) = headers.iter().rev().find(|(n, _)| n.eq_ignore_ascii_case(name)).unwrap();
+                canonicalize_header_relaxed(name, value) + "\r\n"
+            })
+            .collect();
+        signed_data.push_str(&canonicalize_header_relaxed("DKIM-Signature", &format!("{} b=", unsigned)));
+
+        let public_key: [u8; 32] = base64::engine::general_purpose::STANDARD
+            .decode(DKIM_ED25519_PUBLIC_KEY)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let signature: [u8; 64] = base64::engine::general_purpose::STANDARD
+            .decode(signature)
+            .unwrap()
+            .try_into()
+            .unwrap();
+        ed25519_dalek::VerifyingKey::from_bytes(&public_key)
+            .unwrap()
+            .verify(&Sha256::digest(signed_data.as_bytes()), &ed25519_dalek::Signature::from_bytes(&signature))
+            .expect("The DKIM signature did not verify");
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_every_provider_is_down() {
+        let primary = MockServer::start().await;
+        let fallback = MockServer::start().await;
+        let email_client = email_client_with_fallback(primary.uri(), fallback.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&fallback)
+            .await;
+
+        assert_err!(send_test_email(&email_client).await);
+        // both breakers are open now, nothing is sent
+        assert_err!(send_test_email(&email_client).await);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,385 @@
+//! src/email_templates.rs
+
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::domain::Locale;
+
+/// Transactional emails whose content admins can customize.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailTemplateKind {
+    Confirmation,
+    Welcome,
+    UnsubscribeConfirmation,
+    PasswordReset,
+    DataRequest,
+    ConfirmationReminder,
+}
+
+impl EmailTemplateKind {
+    pub const ALL: [EmailTemplateKind; 6] = [
+        EmailTemplateKind::Confirmation,
+        EmailTemplateKind::Welcome,
+        EmailTemplateKind::UnsubscribeConfirmation,
+        EmailTemplateKind::PasswordReset,
+        EmailTemplateKind::DataRequest,
+        EmailTemplateKind::ConfirmationReminder,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailTemplateKind::Confirmation => "confirmation",
+            EmailTemplateKind::Welcome => "welcome",
+            EmailTemplateKind::UnsubscribeConfirmation => "unsubscribe_confirmation",
+            EmailTemplateKind::PasswordReset => "password_reset",
+            EmailTemplateKind::DataRequest => "data_request",
+            EmailTemplateKind::ConfirmationReminder => "confirmation_reminder",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            EmailTemplateKind::Confirmation => "Subscription confirmation",
+            EmailTemplateKind::Welcome => "Welcome",
+            EmailTemplateKind::UnsubscribeConfirmation => "Unsubscribe confirmation",
+            EmailTemplateKind::PasswordReset => "Password reset",
+            EmailTemplateKind::DataRequest => "Data request confirmation",
+            EmailTemplateKind::ConfirmationReminder => "Confirmation reminder",
+        }
+    }
+
+    /// The placeholders a template of this kind can use, e.g. `{{subscriber_name}}`.
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            EmailTemplateKind::Confirmation => &["subscriber_name", "confirmation_link"],
+            EmailTemplateKind::Welcome => &["subscriber_name"],
+            EmailTemplateKind::UnsubscribeConfirmation => &["subscriber_name", "resubscribe_link"],
+            EmailTemplateKind::PasswordReset => &["username", "reset_link"],
+            EmailTemplateKind::DataRequest => &["request", "confirmation_link"],
+            EmailTemplateKind::ConfirmationReminder => &["subscriber_name", "confirmation_link"],
+        }
+    }
+
+    pub fn default_template(&self, locale: Locale) -> EmailTemplate {
+        let (subject, html_body, text_body) = match (self, locale) {
+            (EmailTemplateKind::Confirmation, Locale::English) => (
+                "Welcome",
+                "Welcome to our newsletter!<br />\
+                Click <a href=\"{{confirmation_link}}\">here</a> to confirm your subscription.",
+                "Welcome to our newsletter!\n\
+                Visit {{confirmation_link}} to confirm your subscription.",
+            ),
+            (EmailTemplateKind::Confirmation, Locale::Chinese) => (
+                "欢迎订阅",
+                "欢迎订阅我们的电子报！<br />\
+                请点击<a href=\"{{confirmation_link}}\">这里</a>确认您的订阅。",
+                "欢迎订阅我们的电子报！\n\
+                请访问 {{confirmation_link}} 确认您的订阅。",
+            ),
+            (EmailTemplateKind::Welcome, Locale::English) => (
+                "Welcome aboard",
+                "Hi {{subscriber_name}},<br />\
+                thanks for subscribing to our newsletter, the next issue will land in your inbox soon.",
+                "Hi {{subscriber_name}},\n\
+                thanks for subscribing to our newsletter, the next issue will land in your inbox soon.",
+            ),
+            (EmailTemplateKind::Welcome, Locale::Chinese) => (
+                "订阅成功",
+                "{{subscriber_name}}，您好：<br />\
+                感谢您订阅我们的电子报，下一期很快就会送达您的邮箱。",
+                "{{subscriber_name}}，您好：\n\
+                感谢您订阅我们的电子报，下一期很快就会送达您的邮箱。",
+            ),
+            (EmailTemplateKind::UnsubscribeConfirmation, Locale::English) => (
+                "You have been unsubscribed",
+                "Hi {{subscriber_name}},<br />\
+                you will not receive our newsletter anymore. \
+                Changed your mind? <a href=\"{{resubscribe_link}}\">Subscribe again</a>.",
+                "Hi {{subscriber_name}},\n\
+                you will not receive our newsletter anymore.\n\
+                Changed your mind? Visit {{resubscribe_link}} to subscribe again.",
+            ),
+            (EmailTemplateKind::UnsubscribeConfirmation, Locale::Chinese) => (
+                "您已退订",
+                "{{subscriber_name}}，您好：<br />\
+                您将不会再收到我们的电子报。\
+                改变主意了？<a href=\"{{resubscribe_link}}\">重新订阅</a>。",
+                "{{subscriber_name}}，您好：\n\
+                您将不会再收到我们的电子报。\n\
+                改变主意了？请访问 {{resubscribe_link}} 重新订阅。",
+            ),
+            (EmailTemplateKind::PasswordReset, Locale::English) => (
+                "Reset your password",
+                "Hi {{username}},<br />\
+                click <a href=\"{{reset_link}}\">here</a> to choose a new password. \
+                If you did not ask for a password reset you can ignore this email.",
+                "Hi {{username}},\n\
+                visit {{reset_link}} to choose a new password.\n\
+                If you did not ask for a password reset you can ignore this email.",
+            ),
+            (EmailTemplateKind::PasswordReset, Locale::Chinese) => (
+                "重置密码",
+                "{{username}}，您好：<br />\
+                请点击<a href=\"{{reset_link}}\">这里</a>设置新密码。\
+                如果您没有申请重置密码，请忽略此邮件。",
+                "{{username}}，您好：\n\
+                请访问 {{reset_link}} 设置新密码。\n\
+                如果您没有申请重置密码，请忽略此邮件。",
+            ),
+            (EmailTemplateKind::DataRequest, Locale::English) => (
+                "Confirm your data request",
+                "We received a request for the {{request}} of the data we hold about this address.<br />\
+                Click <a href=\"{{confirmation_link}}\">here</a> to confirm it, the link is valid for 24 hours. \
+                If you did not make this request you can ignore this email.",
+                "We received a request for the {{request}} of the data we hold about this address.\n\
+                Visit {{confirmation_link}} to confirm it, the link is valid for 24 hours.\n\
+                If you did not make this request you can ignore this email.",
+            ),
+            (EmailTemplateKind::DataRequest, Locale::Chinese) => (
+                "确认您的数据请求",
+                "我们收到了对该地址相关数据的{{request}}请求。<br />\
+                请点击<a href=\"{{confirmation_link}}\">这里</a>确认，链接在 24 小时内有效。\
+                如果这不是您本人的请求，请忽略此邮件。",
+                "我们收到了对该地址相关数据的{{request}}请求。\n\
+                请访问 {{confirmation_link}} 确认，链接在 24 小时内有效。\n\
+                如果这不是您本人的请求，请忽略此邮件。",
+            ),
+            (EmailTemplateKind::ConfirmationReminder, Locale::English) => (
+                "Please confirm your subscription",
+                "Hi {{subscriber_name}},<br />\
+                you signed up for our newsletter but have not confirmed your subscription yet. \
+                Click <a href=\"{{confirmation_link}}\">here</a> to confirm it. \
+                If you changed your mind you can ignore this email, we will not remind you again.",
+                "Hi {{subscriber_name}},\n\
+                you signed up for our newsletter but have not confirmed your subscription yet.\n\
+                Visit {{confirmation_link}} to confirm it.\n\
+                If you changed your mind you can ignore this email, we will not remind you again.",
+            ),
+            (EmailTemplateKind::ConfirmationReminder, Locale::Chinese) => (
+                "请确认您的订阅",
+                "{{subscriber_name}}，您好：<br />\
+                您已注册订阅我们的电子报，但尚未确认订阅。\
+                请点击<a href=\"{{confirmation_link}}\">这里</a>确认。\
+                如果您改变了主意，请忽略此邮件，我们不会再次提醒您。",
+                "{{subscriber_name}}，您好：\n\
+                您已注册订阅我们的电子报，但尚未确认订阅。\n\
+                请访问 {{confirmation_link}} 确认。\n\
+                如果您改变了主意，请忽略此邮件，我们不会再次提醒您。",
+            ),
+        };
+        EmailTemplate {
+            subject: subject.into(),
+            html_body: html_body.into(),
+            text_body: text_body.into(),
+        }
+    }
+}
+
+impl TryFrom<String> for EmailTemplateKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == value)
+            .ok_or_else(|| format!("{} is not a known email template.", value))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailTemplate {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+#[derive(Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html_body: String,
+    pub text_body: String,
+}
+
+impl EmailTemplate {
+    /// Substitutes `{{variable}}` placeholders. Values are HTML-escaped in the
+    /// HTML body, unknown placeholders are an error.
+    pub fn render(&self, variables: &[(&str, &str)]) -> Result<RenderedEmail, String> {
+        Ok(RenderedEmail {
+            subject: render_placeholders(&self.subject, variables, false)?,
+            html_body: render_placeholders(&self.html_body, variables, true)?,
+            text_body: render_placeholders(&self.text_body, variables, false)?,
+        })
+    }
+}
+
+/// Substitutes the `{{variable}}` placeholders of a single text, see
+/// `EmailTemplate::render`.
+pub fn render_placeholders(
+    template: &str,
+    variables: &[(&str, &str)],
+    escape_html: bool,
+) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| "A placeholder is missing its closing '}}'.".to_string())?;
+        let name = rest[start + 2..start + end].trim();
+        let value = variables
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| *v)
+            .ok_or_else(|| format!("{{{{{}}}}} is not a known variable.", name))?;
+        if escape_html {
+            rendered.push_str(&encode_minimal(value));
+        } else {
+            rendered.push_str(value);
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+/// Renders the admin-customized template for the locale, falling back to the
+/// built-in default if there is none or it cannot be loaded or rendered.
+#[tracing::instrument(
+    name = "Rendering a transactional email",
+    skip(pool, variables)
+)]
+pub async fn render_email(
+    pool: &PgPool,
+    kind: EmailTemplateKind,
+    locale: Locale,
+    variables: &[(&str, &str)],
+) -> RenderedEmail {
+    let customized = match get_template(pool, kind, locale).await {
+        Ok(template) => template,
+        Err(e) => {
+            tracing::warn!(error.cause_chain = ?e, "Failed to load a customized email template");
+            None
+        }
+    };
+    if let Some(template) = customized {
+        match template.render(variables) {
+            Ok(rendered) => return rendered,
+            Err(e) => tracing::warn!(error = %e, "Failed to render a customized email template"),
+        }
+    }
+    kind.default_template(locale)
+        .render(variables)
+        .expect("Built-in email templates only use known variables")
+}
+
+#[tracing::instrument(name = "Get email template", skip(pool))]
+pub async fn get_template(
+    pool: &PgPool,
+    kind: EmailTemplateKind,
+    locale: Locale,
+) -> Result<Option<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        r#"
+        SELECT subject, html_body, text_body
+        FROM email_templates
+        WHERE name = $1 AND locale = $2
+        "#,
+        kind.as_str(),
+        locale.as_str(),
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Save email template", skip(pool, template))]
+pub async fn save_template(
+    pool: &PgPool,
+    kind: EmailTemplateKind,
+    locale: Locale,
+    template: &EmailTemplate,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_templates (name, locale, subject, html_body, text_body, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (name, locale) DO UPDATE
+        SET subject = EXCLUDED.subject,
+            html_body = EXCLUDED.html_body,
+            text_body = EXCLUDED.text_body,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        kind.as_str(),
+        locale.as_str(),
+        template.subject,
+        template.html_body,
+        template.text_body,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Reset email template", skip(pool))]
+pub async fn reset_template(
+    pool: &PgPool,
+    kind: EmailTemplateKind,
+    locale: Locale,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM email_templates WHERE name = $1 AND locale = $2"#,
+        kind.as_str(),
+        locale.as_str(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Locale;
+    use crate::email_templates::{EmailTemplate, EmailTemplateKind};
+    use claim::assert_err;
+
+    fn template(body: &str) -> EmailTemplate {
+        EmailTemplate {
+            subject: "Hello {{subscriber_name}}".into(),
+            html_body: body.into(),
+            text_body: body.into(),
+        }
+    }
+
+    #[test]
+    fn placeholders_are_substituted_and_escaped_in_html() {
+        let rendered = template("<p>Hi {{ subscriber_name }}</p>")
+            .render(&[("subscriber_name", "<b>Ursula</b>")])
+            .unwrap();
+
+        assert_eq!(rendered.subject, "Hello <b>Ursula</b>");
+        assert_eq!(rendered.html_body, "<p>Hi &lt;b&gt;Ursula&lt;/b&gt;</p>");
+        assert_eq!(rendered.text_body, "<p>Hi <b>Ursula</b></p>");
+    }
+
+    #[test]
+    fn unknown_or_unterminated_placeholders_are_rejected() {
+        let variables = [("subscriber_name", "Ursula")];
+
+        assert_err!(template("Hi {{unknown}}").render(&variables));
+        assert_err!(template("Hi {{subscriber_name").render(&variables));
+    }
+
+    #[test]
+    fn built_in_templates_only_use_their_own_variables() {
+        for kind in EmailTemplateKind::ALL {
+            let variables: Vec<_> = kind.variables().iter().map(|v| (*v, "value")).collect();
+            for locale in Locale::ALL {
+                let rendered = kind.default_template(locale).render(&variables);
+                assert!(rendered.is_ok(), "{:?} ({:?})", kind, locale);
+            }
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! src/i18n.rs
+
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::HttpRequest;
+
+use crate::domain::Locale;
+
+/// Message catalogs for the public pages, keyed by message id.
+/// Messages missing from a catalog fall back to English.
+const EN: &[(&str, &str)] = &[
+    ("home.title", "Home"),
+    ("home.welcome", "Welcome to our newsletter!"),
+    ("home.name", "Name"),
+    ("home.email", "Email address"),
+    ("home.subscribe", "Subscribe"),
+    ("home.consent", "By subscribing you agree to receive our newsletter by email. You can unsubscribe at any time with the link at the bottom of every issue."),
+    ("login.title", "Login"),
+    ("login.username", "Username"),
+    ("login.username_placeholder", "Enter username"),
+    ("login.password", "Password"),
+    ("login.password_placeholder", "Enter password"),
+    ("login.submit", "Login"),
+    ("subscribe.title", "Subscription"),
+    ("subscribe.check_inbox", "Thank you for subscribing! Please check your inbox, we have sent you an email."),
+    ("subscribe.failed", "We could not process your subscription:"),
+    ("subscribe.unexpected", "Something went wrong, please try again later."),
+    ("subscribe.back", "Back to the form"),
+    ("confirm.title", "Subscription confirmation"),
+    ("confirm.done", "Your subscription is confirmed, welcome aboard!"),
+    ("confirm.already", "Your subscription is already confirmed, there is nothing else to do."),
+    ("confirm.invalid", "This confirmation link is not valid. It may have been replaced by a newer one."),
+    ("confirm.expired", "This confirmation link has expired."),
+    ("confirm.resend", "Request a new confirmation link"),
+    ("resend.title", "Resend the confirmation email"),
+    ("resend.email", "Email address"),
+    ("resend.submit", "Send a new link"),
+    ("resend.sent", "If this address is waiting for confirmation, a new confirmation email is on its way."),
+    ("newsletter.unsubscribe", "Unsubscribe"),
+    ("newsletter.preferences", "Manage your preferences"),
+    ("preferences.title", "Your subscription preferences"),
+    ("preferences.name", "Name"),
+    ("preferences.email", "Email address"),
+    ("preferences.lists", "Topics you want to receive"),
+    ("preferences.frequency", "How often"),
+    ("preferences.frequency.every_issue", "Every issue"),
+    ("preferences.frequency.weekly", "Weekly"),
+    ("preferences.frequency.monthly", "Monthly"),
+    ("preferences.submit", "Save"),
+    ("preferences.saved", "Your preferences have been saved."),
+    ("preferences.saved_confirm_email", "Your preferences have been saved. Please confirm your new email address."),
+    ("preferences.email_taken", "This email address cannot be used."),
+    ("preferences.saved_email_unchanged", "Your preferences have been saved. The email address can only be changed while you are subscribed."),
+    ("unsubscribe.title", "Unsubscribe"),
+    ("unsubscribe.question", "Do you want to stop receiving our newsletter?"),
+    ("unsubscribe.reason", "Would you tell us why? (optional)"),
+    ("unsubscribe.submit", "Unsubscribe"),
+    ("unsubscribe.done", "You have been unsubscribed and will not receive our newsletter anymore."),
+    ("data.title", "Your data"),
+    ("data.email", "Email address"),
+    ("data.request", "Request"),
+    ("data.request.export", "Export my data"),
+    ("data.request.erasure", "Erase my data"),
+    ("data.email_request.export", "export"),
+    ("data.email_request.erasure", "erasure"),
+    ("data.submit", "Send the request"),
+    ("data.sent", "If we hold data about this address, an email with a link to confirm the request is on its way."),
+    ("data.confirm_export", "Download everything we store about your email address?"),
+    ("data.confirm_erasure", "Erase everything we store about your email address? You will not receive our newsletter anymore and this cannot be undone."),
+    ("data.confirm_submit", "Confirm"),
+    ("data.erased", "Your data has been erased."),
+];
+
+const ZH: &[(&str, &str)] = &[
+    ("home.title", "首页"),
+    ("home.welcome", "欢迎订阅我们的电子报！"),
+    ("home.name", "姓名"),
+    ("home.email", "邮箱地址"),
+    ("home.subscribe", "订阅"),
+    ("home.consent", "订阅即表示您同意通过邮件接收我们的电子报。您可以随时通过每期邮件底部的链接退订。"),
+    ("login.title", "登录"),
+    ("login.username", "用户名"),
+    ("login.username_placeholder", "请输入用户名"),
+    ("login.password", "密码"),
+    ("login.password_placeholder", "请输入密码"),
+    ("login.submit", "登录"),
+    ("subscribe.title", "订阅"),
+    ("subscribe.check_inbox", "感谢您的订阅！我们已向您发送了一封邮件，请查收。"),
+    ("subscribe.failed", "我们无法处理您的订阅："),
+    ("subscribe.unexpected", "出了点问题，请稍后再试。"),
+    ("subscribe.back", "返回表单"),
+    ("confirm.title", "订阅确认"),
+    ("confirm.done", "您的订阅已确认，欢迎加入！"),
+    ("confirm.already", "您的订阅已经确认过了，无需其他操作。"),
+    ("confirm.invalid", "该确认链接无效，可能已被新的链接取代。"),
+    ("confirm.expired", "该确认链接已过期。"),
+    ("confirm.resend", "获取新的确认链接"),
+    ("resend.title", "重新发送确认邮件"),
+    ("resend.email", "邮箱地址"),
+    ("resend.submit", "发送新链接"),
+    ("resend.sent", "如果该地址正在等待确认，新的确认邮件已发出。"),
+    ("newsletter.unsubscribe", "退订"),
+    ("newsletter.preferences", "管理订阅偏好"),
+    ("preferences.title", "您的订阅偏好"),
+    ("preferences.name", "姓名"),
+    ("preferences.email", "邮箱地址"),
+    ("preferences.lists", "您想接收的主题"),
+    ("preferences.frequency", "接收频率"),
+    ("preferences.frequency.every_issue", "每期"),
+    ("preferences.frequency.weekly", "每周"),
+    ("preferences.frequency.monthly", "每月"),
+    ("preferences.submit", "保存"),
+    ("preferences.saved", "您的偏好已保存。"),
+    ("preferences.saved_confirm_email", "您的偏好已保存，请确认您的新邮箱地址。"),
+    ("preferences.email_taken", "该邮箱地址无法使用。"),
+    ("preferences.saved_email_unchanged", "您的偏好已保存。只有在订阅期间才能更改邮箱地址。"),
+    ("unsubscribe.title", "退订"),
+    ("unsubscribe.question", "您确定不再接收我们的电子报吗？"),
+    ("unsubscribe.reason", "能告诉我们原因吗？（选填）"),
+    ("unsubscribe.submit", "退订"),
+    ("unsubscribe.done", "您已成功退订，将不会再收到我们的电子报。"),
+    ("data.title", "您的数据"),
+    ("data.email", "邮箱地址"),
+    ("data.request", "请求"),
+    ("data.request.export", "导出我的数据"),
+    ("data.request.erasure", "删除我的数据"),
+    ("data.email_request.export", "导出"),
+    ("data.email_request.erasure", "删除"),
+    ("data.submit", "发送请求"),
+    ("data.sent", "如果我们存有该地址的数据，确认请求的邮件已发出。"),
+    ("data.confirm_export", "下载我们存储的与您邮箱地址相关的全部数据？"),
+    ("data.confirm_erasure", "删除我们存储的与您邮箱地址相关的全部数据？您将不会再收到我们的电子报，且此操作无法撤销。"),
+    ("data.confirm_submit", "确认"),
+    ("data.erased", "您的数据已被删除。"),
+];
+
+fn catalog(locale: Locale) -> &'static [(&'static str, &'static str)] {
+    match locale {
+        Locale::English => EN,
+        Locale::Chinese => ZH,
+    }
+}
+
+/// Looks up a message, falling back to English and then to the id itself.
+pub fn translate(locale: Locale, id: &'static str) -> &'static str {
+    let lookup = |catalog: &'static [(&'static str, &'static str)]| {
+        catalog.iter().find(|(key, _)| *key == id).map(|(_, message)| *message)
+    };
+    lookup(catalog(locale))
+        .or_else(|| lookup(EN))
+        .unwrap_or(id)
+}
+
+/// The language of a public page: an explicit `lang` query parameter wins
+/// over the `Accept-Language` header, English is the default.
+pub fn request_locale(request: &HttpRequest) -> Locale {
+    let from_query = request
+        .query_string()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("lang="))
+        .and_then(|tag| Locale::parse(tag).ok());
+    from_query
+        .or_else(|| {
+            request
+                .headers()
+                .get(ACCEPT_LANGUAGE)
+                .and_then(|h| h.to_str().ok())
+                .and_then(Locale::from_accept_language)
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::Locale;
+    use crate::i18n::{translate, EN, ZH};
+
+    #[test]
+    fn every_english_message_is_translated() {
+        for (id, _) in EN {
+            assert!(ZH.iter().any(|(key, _)| key == id), "{} is missing from the zh catalog", id);
+        }
+    }
+
+    #[test]
+    fn unknown_messages_fall_back_to_their_id() {
+        assert_eq!(translate(Locale::Chinese, "home.title"), "首页");
+        assert_eq!(translate(Locale::Chinese, "does.not_exist"), "does.not_exist");
+    }
+}
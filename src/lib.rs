@@ -6,6 +6,24 @@ pub mod startup;
 pub mod telemetry;
 pub mod domain;
 pub mod email_client;
+pub mod circuit_breaker;
+pub mod suppression;
+pub mod dkim;
+pub mod email_templates;
+pub mod i18n;
+pub mod subscriber_links;
+pub mod newsletter_lists;
+pub mod bot_protection;
+pub mod domain_policy;
+pub mod data_requests;
+pub mod consent;
+pub mod maintenance_worker;
+pub mod subscriber_import;
+pub mod subscriber_export;
+pub mod subscribers;
+pub mod admin_actions;
+pub mod subscriber_attributes;
+pub mod acquisition;
 pub mod authentication;
 pub mod session_state;
 pub mod utils;
\ No newline at end of file
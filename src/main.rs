@@ -1,7 +1,10 @@
 //! src/main.rs
 
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
 use zero2prod::startup;
 use zero2prod::configurations::get_configuration;
+use zero2prod::maintenance_worker::run_worker_until_stopped;
 // use env_logger::Env;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 // use secrecy::ExposeSecret;
@@ -13,8 +16,42 @@ async fn main() -> anyhow::Result<()> {
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read configuration");
-    
-    let application = startup::Application::build(configuration).await?;
-    application.run_until_stopped().await?;
+
+    let application = startup::Application::build(configuration.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+
+    // whichever stops first brings the whole process down
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = worker_task => report_exit("Maintenance worker", o),
+    };
     Ok(())
-}
\ No newline at end of file
+}
+
+fn report_exit(
+    task_name: &str,
+    outcome: Result<Result<(), impl Debug + Display>, JoinError>,
+) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
+}
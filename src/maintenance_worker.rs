@@ -0,0 +1,267 @@
+//! src/maintenance_worker.rs
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configurations::{MaintenanceSettings, Settings};
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_templates::{render_email, EmailTemplateKind};
+use crate::routes::{generate_subscription_token, store_token};
+use crate::startup::{get_connection_pool, get_email_client};
+use crate::subscriber_import::delete_expired_imports;
+use crate::subscribers::delete_subscribers;
+
+/// What a maintenance run did, stored in `maintenance_runs`.
+#[derive(Debug, Default, PartialEq)]
+pub struct MaintenanceReport {
+    pub reminders_sent: i32,
+    pub subscribers_deleted: i32,
+    pub tokens_deleted: i32,
+}
+
+pub struct MaintenanceRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub reminders_sent: i32,
+    pub subscribers_deleted: i32,
+    pub tokens_deleted: i32,
+    pub error: Option<String>,
+}
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = get_email_client(configuration.email_client, &connection_pool);
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.maintenance,
+    )
+    .await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    settings: MaintenanceSettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        // failures are recorded and logged, the next run tries again
+        let _ = run_maintenance(&pool, &email_client, &base_url, &settings, Utc::now()).await;
+        tokio::time::sleep(settings.interval()).await;
+    }
+}
+
+/// Reminds, then cleans up, subscribers stuck in `pending_confirmation`, and
+/// records the outcome. Reminders are due once the newest confirmation link is
+/// old enough, clean-up once the oldest one is: the reminder does not extend
+/// the retention, while a resend replaces every link and starts over. Uploads
+/// never imported are deleted too.
+#[tracing::instrument(name = "Running maintenance", skip_all)]
+pub async fn run_maintenance(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    settings: &MaintenanceSettings,
+    now: DateTime<Utc>,
+) -> Result<MaintenanceReport, anyhow::Error> {
+    let started_at = Utc::now();
+    let mut report = MaintenanceReport::default();
+    let result = async {
+        report.reminders_sent = send_reminders(
+            pool,
+            email_client,
+            base_url,
+            now - Duration::hours(settings.reminder_after_hours),
+        )
+        .await?;
+        let (subscribers_deleted, tokens_deleted) =
+            delete_stale_pending_subscribers(pool, now - Duration::days(settings.pending_retention_days)).await?;
+        report.subscribers_deleted = subscribers_deleted;
+        report.tokens_deleted = tokens_deleted;
+        let imports_deleted = delete_expired_imports(pool, now - Duration::hours(settings.import_retention_hours))
+            .await
+            .context("Failed to delete expired imports")?;
+        if imports_deleted > 0 {
+            tracing::info!(imports_deleted, "Deleted uploads that were never imported");
+        }
+        Ok::<_, anyhow::Error>(())
+    }
+    .await;
+
+    match &result {
+        Ok(()) => tracing::info!(
+            reminders_sent = report.reminders_sent,
+            subscribers_deleted = report.subscribers_deleted,
+            tokens_deleted = report.tokens_deleted,
+            "Maintenance run completed",
+        ),
+        Err(e) => tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            reminders_sent = report.reminders_sent,
+            "Maintenance run failed",
+        ),
+    }
+    let error = result.as_ref().err().map(|e| e.to_string());
+    sqlx::query!(
+        r#"
+        INSERT INTO maintenance_runs
+            (id, started_at, finished_at, reminders_sent, subscribers_deleted, tokens_deleted, error)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        started_at,
+        Utc::now(),
+        report.reminders_sent,
+        report.subscribers_deleted,
+        report.tokens_deleted,
+        error,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the maintenance run")?;
+    result.map(|()| report)
+}
+
+/// Sends a fresh confirmation link to every pending subscriber whose link was
+/// sent before `cutoff`, once. Subscribers are marked before the email goes
+/// out, a failed delivery is not retried.
+async fn send_reminders(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    cutoff: DateTime<Utc>,
+) -> Result<i32, anyhow::Error> {
+    let mut reminders_sent = 0;
+    loop {
+        let mut transaction = pool.begin().await
+            .context("Failed to acquire a pg connection from the pg pool")?;
+        // SKIP LOCKED lets several instances run the job at the same time
+        let subscriber = sqlx::query!(
+            r#"
+            SELECT s.id, s.email, s.name, s.locale
+            FROM subscriptions s
+            WHERE s.status = 'pending_confirmation'
+                AND s.confirmation_requested
+                AND s.reminder_sent_at IS NULL
+                AND COALESCE(
+                    (SELECT max(t.created_at) FROM subscription_tokens t WHERE t.subscriber_id = s.id),
+                    s.subscribed_at
+                ) < $1
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+            "#,
+            cutoff,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to look up a pending subscriber to remind")?;
+        let Some(subscriber) = subscriber else {
+            return Ok(reminders_sent);
+        };
+
+        sqlx::query!(
+            r#"UPDATE subscriptions SET reminder_sent_at = $2 WHERE id = $1"#,
+            subscriber.id,
+            Utc::now(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to mark a pending subscriber as reminded")?;
+        // the original link may have expired by now
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, subscriber.id, &subscription_token).await
+            .context("Failed to store the confirmation token for a reminder")?;
+        transaction.commit().await
+            .context("Failed to commit SQL transaction to remind a pending subscriber")?;
+
+        let email = match SubscriberEmail::parse(subscriber.email) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::warn!(subscriber_id = %subscriber.id, "Skipping a reminder to an invalid address: {}", e);
+                continue;
+            }
+        };
+        let confirmation_link = format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            base_url,
+            subscription_token,
+        );
+        let message = render_email(
+            pool,
+            EmailTemplateKind::ConfirmationReminder,
+            Locale::parse(&subscriber.locale).unwrap_or_default(),
+            &[
+                ("subscriber_name", &subscriber.name),
+                ("confirmation_link", &confirmation_link),
+            ],
+        ).await;
+        match email_client.send_email(&email, &message.subject, &message.html_body, &message.text_body).await {
+            Ok(()) => reminders_sent += 1,
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                subscriber_id = %subscriber.id,
+                "Failed to send a confirmation reminder",
+            ),
+        }
+    }
+}
+
+/// Deletes the pending subscribers whose oldest link was sent before `cutoff`,
+/// see `delete_subscribers`. Those without a link count from their sign-up.
+/// Imported subscribers who were never asked to confirm are kept, deleting
+/// them would lose the list the admin imported.
+async fn delete_stale_pending_subscribers(
+    pool: &PgPool,
+    cutoff: DateTime<Utc>,
+) -> Result<(i32, i32), anyhow::Error> {
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let ids: Vec<Uuid> = sqlx::query!(
+        r#"
+        SELECT s.id
+        FROM subscriptions s
+        WHERE s.status = 'pending_confirmation'
+            AND s.confirmation_requested
+            AND COALESCE(
+                (SELECT min(t.created_at) FROM subscription_tokens t WHERE t.subscriber_id = s.id),
+                s.subscribed_at
+            ) < $1
+        FOR UPDATE SKIP LOCKED
+        "#,
+        cutoff,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to look up stale pending subscribers")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    let (subscribers_deleted, tokens_deleted) = delete_subscribers(&mut transaction, &ids).await
+        .context("Failed to delete stale pending subscribers")?;
+
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to delete stale pending subscribers")?;
+    Ok((subscribers_deleted, tokens_deleted))
+}
+
+#[tracing::instrument(name = "Get the last maintenance run", skip(pool))]
+pub async fn get_last_maintenance_run(pool: &PgPool) -> Result<Option<MaintenanceRun>, sqlx::Error> {
+    sqlx::query_as!(
+        MaintenanceRun,
+        r#"
+        SELECT started_at, finished_at, reminders_sent, subscribers_deleted, tokens_deleted, error
+        FROM maintenance_runs
+        ORDER BY started_at DESC
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(pool)
+    .await
+}
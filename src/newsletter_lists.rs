@@ -0,0 +1,130 @@
+//! src/newsletter_lists.rs
+
+use chrono::Utc;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::OptInMode;
+
+/// The list created with the lists themselves, newsletters that do not target
+/// a list go to its subscribers.
+pub const GENERAL_LIST: &str = "general";
+
+/// A topic subscribers can opt out of, newsletters can target a single list.
+pub struct NewsletterList {
+    pub key: String,
+    pub name: String,
+    pub description: String,
+    /// With single opt-in, imported rows without a status are confirmed right
+    /// away when imported into this list. Public subscriptions through the list
+    /// can only be made stricter: whoever subscribes picks the list, so it
+    /// cannot stand for their consent. Either way they only receive this list.
+    pub opt_in_mode: Option<OptInMode>,
+}
+
+impl NewsletterList {
+    /// List keys end up in form field names and URLs: lowercase ASCII
+    /// letters, digits and `_` only.
+    pub fn parse_key(key: &str) -> Result<String, String> {
+        let key = key.trim().to_lowercase();
+        let is_valid = !key.is_empty()
+            && key.len() <= 64
+            && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if is_valid {
+            Ok(key)
+        } else {
+            Err(format!("{} is not a valid list key. Use lowercase letters, digits and '_'.", key))
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get newsletter lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<NewsletterList>, anyhow::Error> {
+    let lists = sqlx::query!(
+        r#"
+        SELECT key, name, description, opt_in_mode
+        FROM newsletter_lists
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| {
+        let opt_in_mode = row.opt_in_mode.map(OptInMode::try_from).transpose()
+            .map_err(anyhow::Error::msg)?;
+        Ok(NewsletterList {
+            key: row.key,
+            name: row.name,
+            description: row.description,
+            opt_in_mode,
+        })
+    })
+    .collect::<Result<_, anyhow::Error>>()?;
+    Ok(lists)
+}
+
+#[tracing::instrument(name = "Get a newsletter list", skip(pool))]
+pub async fn get_list(pool: &PgPool, key: &str) -> Result<Option<NewsletterList>, anyhow::Error> {
+    Ok(get_lists(pool).await?.into_iter().find(|l| l.key == key))
+}
+
+/// Returns `false` if a list with the same key already exists.
+#[tracing::instrument(name = "Add a newsletter list", skip(pool, list))]
+pub async fn add_list(pool: &PgPool, list: &NewsletterList) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO newsletter_lists (key, name, description, opt_in_mode, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        list.key,
+        list.name,
+        list.description,
+        list.opt_in_mode.map(|m| m.as_str()),
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Opts a subscriber whose consent was given for `list_key` out of every
+/// other list, as everybody receives the lists they did not opt out of.
+#[tracing::instrument(name = "Restrict a subscriber to a newsletter list", skip(transaction))]
+pub async fn restrict_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_key: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_opt_outs (subscriber_id, list_key)
+        SELECT $1, key FROM newsletter_lists WHERE key <> $2
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        list_key,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::newsletter_lists::NewsletterList;
+    use claim::assert_err;
+
+    #[test]
+    fn list_keys_are_normalized() {
+        assert_eq!(NewsletterList::parse_key(" Product_Updates2 ").unwrap(), "product_updates2");
+    }
+
+    #[test]
+    fn list_keys_with_other_characters_are_rejected() {
+        for key in ["", "product updates", "news-letter", "événements"] {
+            assert_err!(NewsletterList::parse_key(key));
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! src/routes/admin/attributes/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::subscriber_attributes::{get_attribute_definitions, AttributeKind};
+use crate::utils::e500;
+
+pub async fn subscriber_attributes(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let definitions = get_attribute_definitions(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for definition in &definitions {
+        writeln!(
+            rows_html,
+            r#"<tr><td>{key}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>
+            <form action="/admin/attributes/{key}/delete" method="post">
+                <button type="submit">Delete</button>
+            </form>
+        </td></tr>"#,
+            encode_minimal(&definition.label),
+            definition.kind.label(),
+            if definition.required { "Yes" } else { "No" },
+            encode_minimal(&definition.options.join(", ")),
+            key = definition.key,
+        )
+        .unwrap();
+    }
+
+    let mut kind_options_html = String::new();
+    for kind in AttributeKind::ALL {
+        write!(kind_options_html, r#"<option value="{}">{}</option>"#, kind.as_str(), kind.label()).unwrap();
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber attributes</title>
+</head>
+<body>
+    {msg_html}
+    <p>Attributes are extra fields on the subscribe form. Newsletters can be sent to the subscribers
+    matching conditions on them and use them as <code>{{{{attributes.&lt;key&gt;}}}}</code> placeholders.</p>
+    <h2>Add an attribute</h2>
+    <form action="/admin/attributes" method="post">
+        <label>Key
+            <input type="text" placeholder="company" name="key" required>
+        </label>
+        <label>Label
+            <input type="text" placeholder="Company" name="label" required>
+        </label>
+        <label>Type
+            <select name="kind">{kind_options_html}</select>
+        </label>
+        <label>Options, for a list
+            <input type="text" placeholder="Germany, France, Italy" name="options">
+        </label>
+        <label>
+            <input type="checkbox" name="required" value="true"> Required
+        </label>
+        <button type="submit">Add</button>
+    </form>
+    <h2>Attributes</h2>
+    <p>Deleting an attribute also deletes what subscribers filled in for it.</p>
+    <table>
+        <tr><th>Key</th><th>Label</th><th>Type</th><th>Required</th><th>Options</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
@@ -0,0 +1,7 @@
+//! src/routes/admin/attributes/mod.rs
+
+mod get;
+mod post;
+
+pub use get::subscriber_attributes;
+pub use post::{add_subscriber_attribute, delete_subscriber_attribute};
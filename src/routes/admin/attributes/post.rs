@@ -0,0 +1,57 @@
+//! src/routes/admin/attributes/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::subscriber_attributes::{add_attribute_definition, delete_attribute_definition, AttributeDefinition};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct AttributeFormData {
+    key: String,
+    label: String,
+    kind: String,
+    #[serde(default)]
+    options: String,
+    /// Only sent when the checkbox is ticked.
+    required: Option<String>,
+}
+
+pub async fn add_subscriber_attribute(
+    form: web::Form<AttributeFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let definition = match AttributeDefinition::parse(
+        &form.key,
+        &form.label,
+        &form.kind,
+        form.required.is_some(),
+        &form.options,
+    ) {
+        Ok(definition) => definition,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/attributes"));
+        }
+    };
+
+    if add_attribute_definition(&pool, &definition).await.map_err(e500)? {
+        FlashMessage::info(format!("The attribute {} has been added.", definition.key)).send();
+    } else {
+        FlashMessage::error(format!("An attribute named {} already exists.", definition.key)).send();
+    }
+    Ok(see_other("/admin/attributes"))
+}
+
+pub async fn delete_subscriber_attribute(
+    key: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if delete_attribute_definition(&pool, &key).await.map_err(e500)? {
+        FlashMessage::info(format!("The attribute {} has been deleted.", key)).send();
+    } else {
+        FlashMessage::error(format!("There is no attribute named {}.", key)).send();
+    }
+    Ok(see_other("/admin/attributes"))
+}
@@ -0,0 +1,85 @@
+//! src/routes/admin/consent.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::consent::get_consent_events_by_email;
+use crate::utils::e500;
+
+#[derive(serde::Deserialize)]
+pub struct ConsentQuery {
+    email: Option<String>,
+}
+
+/// The consent history of a subscriber, looked up by email address.
+pub async fn consent_history(
+    query: web::Query<ConsentQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = query.0.email.map(|e| e.trim().to_owned()).filter(|e| !e.is_empty());
+    let history_html = match &email {
+        None => String::new(),
+        Some(email) => {
+            let events = get_consent_events_by_email(&pool, email).await.map_err(e500)?;
+            if events.is_empty() {
+                format!("<p>No consent events recorded for {}.</p>", encode_minimal(email))
+            } else {
+                let mut rows_html = String::new();
+                for event in &events {
+                    writeln!(
+                        rows_html,
+                        r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+                        event.occurred_at.format("%Y-%m-%d %H:%M:%S"),
+                        event.event_type,
+                        event.source,
+                        encode_minimal(event.ip_address.as_deref().unwrap_or("-")),
+                        encode_minimal(event.user_agent.as_deref().unwrap_or("-")),
+                        encode_minimal(event.consent_text_version.as_deref().unwrap_or("-")),
+                    )
+                    .unwrap();
+                }
+                format!(
+                    r#"<h2>History of {}</h2>
+    <table>
+        <tr><th>When (UTC)</th><th>Event</th><th>Source</th><th>IP address</th><th>User agent</th><th>Consent text</th></tr>
+        {}
+    </table>"#,
+                    encode_minimal(email),
+                    rows_html,
+                )
+            }
+        }
+    };
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Consent history</title>
+</head>
+<body>
+    <form action="/admin/consent" method="get">
+        <label>Email
+            <input type="email" name="email" value="{}" required>
+        </label>
+        <button type="submit">Look up</button>
+    </form>
+    {history_html}
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#,
+        encode_minimal(email.as_deref().unwrap_or_default()),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
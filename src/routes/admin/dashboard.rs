@@ -5,7 +5,13 @@ use actix_web::{http::header::{ContentType, LOCATION}, web, HttpResponse};
 use sqlx::PgPool;
 use uuid::Uuid;
 use anyhow::Context;
+use chrono::{Duration, Utc};
+use htmlescape::encode_minimal;
+use std::fmt::Write;
 
+use crate::acquisition::get_signups_by_source;
+
+use crate::maintenance_worker::get_last_maintenance_run;
 use crate::session_state::TypedSession;
 use crate::utils::e500;
 
@@ -24,7 +30,38 @@ pub async fn admin_dashboard(
                         .finish()
                     );
         };
-    
+    let maintenance = match get_last_maintenance_run(&pool).await.map_err(e500)? {
+        None => "The maintenance job has not run yet.".to_string(),
+        Some(run) => match run.error {
+            Some(error) => format!(
+                "The last maintenance run failed at {}: {}",
+                run.finished_at.format("%Y-%m-%d %H:%M"),
+                encode_minimal(&error),
+            ),
+            None => format!(
+                "Last maintenance run at {}: {} reminder(s) sent, {} unconfirmed subscriber(s) and {} token(s) deleted.",
+                run.finished_at.format("%Y-%m-%d %H:%M"),
+                run.reminders_sent,
+                run.subscribers_deleted,
+                run.tokens_deleted,
+            ),
+        },
+    };
+    let mut sources_html = String::new();
+    let sources = get_signups_by_source(&pool, Utc::now() - Duration::days(30), 20).await.map_err(e500)?;
+    for source in &sources {
+        writeln!(
+            sources_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{:.0}%</td></tr>",
+            encode_minimal(source.source.as_deref().unwrap_or("(direct)")),
+            encode_minimal(source.campaign.as_deref().unwrap_or("-")),
+            source.signups,
+            source.confirmed,
+            source.confirmation_rate(),
+        )
+        .unwrap();
+    }
+
     let html = format!(r#"<!DOCTYPE html>
 <html lang = "en">
 <head>
@@ -34,13 +71,29 @@ pub async fn admin_dashboard(
     <p>Welcome {username}!</p>
     <p>Available actions:</p>
     <ol>
+        <li><a href="/admin/subscribers">Subscribers</a></li>
         <li><a href="/admin/password">Change password</a></li>
+        <li><a href="/admin/suppressions">Suppression list</a></li>
+        <li><a href="/admin/templates">Email templates</a></li>
+        <li><a href="/admin/lists">Newsletter lists</a></li>
+        <li><a href="/admin/attributes">Subscriber attributes</a></li>
+        <li><a href="/admin/domains">Email domains</a></li>
+        <li><a href="/admin/data-requests">Data requests</a></li>
+        <li><a href="/admin/consent">Consent history</a></li>
+        <li><a href="/admin/import">Import subscribers</a></li>
+        <li><a href="/admin/export">Export subscribers</a></li>
         <li>
             <form name="logoutForm" action="/admin/logout" method="post">
                 <input type="submit" value="Logout">
             </form>
         </li>
     </ol>
+    <p>{maintenance}</p>
+    <h3>Sign-ups by source, last 30 days</h3>
+    <table>
+        <tr><th>Source</th><th>Campaign</th><th>Sign-ups</th><th>Confirmed</th><th>Confirmation rate</th></tr>
+        {sources_html}
+    </table>
 </body>
 </html>"#);
 
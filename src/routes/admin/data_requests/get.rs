@@ -0,0 +1,47 @@
+//! src/routes/admin/data_requests/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use std::fmt::Write;
+
+pub async fn data_requests_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Data requests</title>
+</head>
+<body>
+    {msg_html}
+    <p>Handle a data request received outside of the subscriber self-service pages.</p>
+    <h2>Export</h2>
+    <form action="/admin/data-requests/export" method="post">
+        <label>Email
+            <input type="email" name="email" required>
+        </label>
+        <button type="submit">Download the data</button>
+    </form>
+    <h2>Erase</h2>
+    <p>Subscriptions are anonymized, tokens and list preferences deleted. Suppressions are kept so
+    the address is never emailed again. This cannot be undone.</p>
+    <form action="/admin/data-requests/erase" method="post">
+        <label>Email
+            <input type="email" name="email" required>
+        </label>
+        <button type="submit">Erase the data</button>
+    </form>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
@@ -0,0 +1,7 @@
+//! src/routes/admin/data_requests/mod.rs
+
+mod get;
+mod post;
+
+pub use get::data_requests_form;
+pub use post::{admin_erase_subscriber_data, admin_export_subscriber_data};
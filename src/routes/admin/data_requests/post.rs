@@ -0,0 +1,50 @@
+//! src/routes/admin/data_requests/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::data_requests::{erase_subscriber_data, export_subscriber_data};
+use crate::domain::SubscriberEmail;
+use crate::routes::data_export_response;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+}
+
+pub async fn admin_export_subscriber_data(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/data-requests"));
+        }
+    };
+    let export = export_subscriber_data(&pool, email.as_ref()).await.map_err(e500)?;
+    Ok(data_export_response(&export))
+}
+
+pub async fn admin_erase_subscriber_data(
+    form: web::Form<DataRequestFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/data-requests"));
+        }
+    };
+    let erased = erase_subscriber_data(&pool, email.as_ref()).await.map_err(e500)?;
+    FlashMessage::info(format!(
+        "The data of {} has been erased ({} subscription(s) anonymized).",
+        email, erased,
+    ))
+    .send();
+    Ok(see_other("/admin/data-requests"))
+}
@@ -0,0 +1,79 @@
+//! src/routes/admin/domains/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain_policy::get_domain_rules;
+use crate::utils::e500;
+
+pub async fn email_domain_rules(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let rules = get_domain_rules(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for rule in &rules {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>
+                <form action="/admin/domains/remove" method="post">
+                    <input type="hidden" name="domain" value="{}">
+                    <button type="submit">Remove</button>
+                </form>
+            </td>
+        </tr>"#,
+            encode_minimal(&rule.domain),
+            rule.rule,
+            rule.created_at.format("%Y-%m-%d %H:%M"),
+            encode_minimal(&rule.domain),
+        )
+        .unwrap();
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email domains</title>
+</head>
+<body>
+    {msg_html}
+    <p>Addresses at known disposable email providers are rejected on subscribe.
+    Rules below apply to a domain and its subdomains, an allow rule wins over everything else.</p>
+    <h2>Add a rule</h2>
+    <form action="/admin/domains" method="post">
+        <label>Domain
+            <input type="text" placeholder="example.com" name="domain" required>
+        </label>
+        <label>Rule
+            <select name="rule">
+                <option value="block">Block</option>
+                <option value="allow">Allow</option>
+            </select>
+        </label>
+        <button type="submit">Save</button>
+    </form>
+    <h2>Rules</h2>
+    <table>
+        <tr><th>Domain</th><th>Rule</th><th>Added</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
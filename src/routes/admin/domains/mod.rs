@@ -0,0 +1,7 @@
+//! src/routes/admin/domains/mod.rs
+
+mod get;
+mod post;
+
+pub use get::email_domain_rules;
+pub use post::{remove_email_domain_rule, set_email_domain_rule};
@@ -0,0 +1,47 @@
+//! src/routes/admin/domains/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::domain_policy::{parse_domain, remove_domain_rule, set_domain_rule, DomainRule};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct RuleFormData {
+    domain: String,
+    rule: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    domain: String,
+}
+
+pub async fn set_email_domain_rule(
+    form: web::Form<RuleFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let validated = parse_domain(&form.domain)
+        .and_then(|domain| Ok((domain, DomainRule::try_from(form.rule.clone())?)));
+    let (domain, rule) = match validated {
+        Ok(validated) => validated,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/domains"));
+        }
+    };
+
+    set_domain_rule(&pool, &domain, rule).await.map_err(e500)?;
+    FlashMessage::info(format!("{} is now {}ed.", domain, rule.as_str())).send();
+    Ok(see_other("/admin/domains"))
+}
+
+pub async fn remove_email_domain_rule(
+    form: web::Form<RemoveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    remove_domain_rule(&pool, &form.domain).await.map_err(e500)?;
+    FlashMessage::info(format!("The rule for {} has been removed.", form.domain)).send();
+    Ok(see_other("/admin/domains"))
+}
@@ -0,0 +1,100 @@
+//! src/routes/admin/export.rs
+
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::subscriber_export::{stream_subscribers, ExportFormat};
+use crate::subscribers::{SubscriberFilterForm, SUBSCRIBER_STATUSES};
+use crate::utils::see_other;
+
+#[derive(serde::Deserialize)]
+pub struct ExportQuery {
+    format: Option<String>,
+    #[serde(flatten)]
+    filter: SubscriberFilterForm,
+}
+
+pub async fn export_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut status_options_html = String::new();
+    for (status, label) in SUBSCRIBER_STATUSES {
+        writeln!(status_options_html, r#"<option value="{}">{}</option>"#, status, label).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Export subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/export/subscribers" method="get">
+        <label>Format
+            <select name="format">
+                <option value="csv">CSV</option>
+                <option value="ndjson">Newline-delimited JSON</option>
+            </select>
+        </label>
+        <br>
+        <label>Status
+            <select name="status">
+                <option value="">All</option>
+                {status_options_html}
+            </select>
+        </label>
+        <br>
+        <label>Email or name contains
+            <input type="text" name="search">
+        </label>
+        <br>
+        <label>Subscribed from
+            <input type="date" name="from">
+        </label>
+        <label>to
+            <input type="date" name="to">
+        </label>
+        <br>
+        <button type="submit">Download</button>
+    </form>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(name = "Exporting subscribers", skip(query, pool))]
+pub async fn export_subscribers(
+    query: web::Query<ExportQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let parsed = ExportFormat::try_from(query.format.as_deref().filter(|f| !f.is_empty()).unwrap_or("csv"))
+        .and_then(|format| Ok((format, query.filter.parse()?)));
+    let (format, filter) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/export"));
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format.file_name().into())],
+        })
+        .streaming(stream_subscribers(pool.get_ref().clone(), filter, format)))
+}
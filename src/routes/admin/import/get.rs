@@ -0,0 +1,79 @@
+//! src/routes/admin/import/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::OptInMode;
+use crate::newsletter_lists::get_lists;
+use crate::utils::e500;
+
+pub async fn import_subscribers_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let mut list_options_html = String::from(r#"<option value="">None</option>"#);
+    for list in get_lists(&pool).await.map_err(e500)? {
+        write!(
+            list_options_html,
+            r#"<option value="{}">{}{}</option>"#,
+            list.key,
+            encode_minimal(&list.name),
+            if list.opt_in_mode == Some(OptInMode::Single) { " (single opt-in)" } else { "" },
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <p>
+        Upload a CSV file with a header row. The <code>email</code> and <code>name</code> columns are required,
+        <code>tags</code> (separated by <code>;</code>) and <code>status</code>
+        (<code>pending</code>, <code>confirmed</code> or <code>unsubscribed</code>) are optional.
+        Nothing is imported before you have reviewed the dry run.
+    </p>
+    <form action="/admin/import" method="post" enctype="multipart/form-data">
+        <label>CSV file
+            <input type="file" name="file" accept=".csv,text/csv" required>
+        </label>
+        <br>
+        <label>Collected for the list
+            <select name="list">{list_options_html}</select>
+        </label>
+        <br>
+        <small>New subscribers only receive the list they were collected for. Importing into a
+        single opt-in list confirms the rows without a status right away and sends them a
+        welcome email, only pick one if their consent was recorded.</small>
+        <br>
+        <label>
+            <input type="checkbox" name="send_confirmations" value="on">
+            Send a confirmation email to pending subscribers
+        </label>
+        <br>
+        <small>Pending subscribers who get no confirmation email are neither reminded nor deleted
+        when they do not confirm, until a link is sent to them.</small>
+        <br>
+        <button type="submit">Check file</button>
+    </form>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
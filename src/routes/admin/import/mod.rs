@@ -0,0 +1,7 @@
+//! src/routes/admin/import/mod.rs
+
+mod get;
+mod post;
+
+pub use get::import_subscribers_form;
+pub use post::{confirm_subscriber_import, preview_subscriber_import};
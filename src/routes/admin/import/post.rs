@@ -0,0 +1,237 @@
+//! src/routes/admin/import/post.rs
+
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::domain::OptInMode;
+use crate::domain_policy::DomainPolicy;
+use crate::email_client::EmailClient;
+use crate::newsletter_lists::{get_list, NewsletterList};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscriber_import::{
+    check_import, get_import, import_subscribers, parse_import, store_import, ImportPreview, RejectedRow,
+};
+use crate::utils::{e500, see_other};
+
+#[derive(MultipartForm)]
+pub struct UploadForm {
+    file: Bytes,
+    send_confirmations: Option<Text<String>>,
+    /// Empty when the addresses were not collected for a particular list.
+    list: Option<Text<String>>,
+}
+
+#[tracing::instrument(name = "Checking an uploaded subscriber import", skip_all)]
+pub async fn preview_subscriber_import(
+    MultipartForm(form): MultipartForm<UploadForm>,
+    pool: web::Data<PgPool>,
+    domain_policy: web::Data<DomainPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(csv) = String::from_utf8(form.file.data.to_vec()) else {
+        FlashMessage::error("The file must be UTF-8 encoded.").send();
+        return Ok(see_other("/admin/import"));
+    };
+    let mut preview = match parse_import(&csv) {
+        Ok(preview) => preview,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/import"));
+        }
+    };
+    let list = match form.list.as_ref().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+        Some(key) => match get_list(&pool, key).await.map_err(e500)? {
+            Some(list) => Some(list),
+            None => {
+                FlashMessage::error(format!("There is no list named {}.", key)).send();
+                return Ok(see_other("/admin/import"));
+            }
+        },
+        None => None,
+    };
+    check_import(&pool, &domain_policy, &mut preview).await.map_err(e500)?;
+    let send_confirmations = form.send_confirmations.is_some();
+    let import_id = store_import(&pool, &csv, send_confirmations, list.as_ref().map(|l| l.key.as_str()))
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(preview_page(import_id, &preview, send_confirmations, list.as_ref())))
+}
+
+#[tracing::instrument(name = "Importing an uploaded subscriber import", skip(pool, email_client, base_url, domain_policy))]
+pub async fn confirm_subscriber_import(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    domain_policy: web::Data<DomainPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let stored = match get_import(&pool, import_id).await.map_err(e500)? {
+        Some(stored) if stored.imported_at.is_none() => stored,
+        Some(_) => {
+            FlashMessage::error("This file has already been imported.").send();
+            return Ok(see_other("/admin/import"));
+        }
+        None => {
+            FlashMessage::error("The uploaded file could not be found.").send();
+            return Ok(see_other("/admin/import"));
+        }
+    };
+    // subscribers may have signed up since the dry run, so the rows are checked again
+    let mut preview = match parse_import(&stored.csv) {
+        Ok(preview) => preview,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/import"));
+        }
+    };
+    check_import(&pool, &domain_policy, &mut preview).await.map_err(e500)?;
+    let list = match &stored.list_key {
+        Some(key) => get_list(&pool, key).await.map_err(e500)?,
+        None => None,
+    };
+
+    let Some(outcome) = import_subscribers(
+        &pool,
+        &email_client,
+        &base_url.0,
+        import_id,
+        preview.valid,
+        stored.send_confirmations,
+        list.as_ref(),
+    )
+    .await
+    .map_err(e500)?
+    else {
+        FlashMessage::error("This file has already been imported.").send();
+        return Ok(see_other("/admin/import"));
+    };
+
+    FlashMessage::info(format!(
+        "Imported {} subscribers, skipped {} duplicates and {} invalid rows.",
+        outcome.imported,
+        preview.duplicates.len() + outcome.skipped,
+        preview.invalid.len(),
+    )).send();
+    if stored.send_confirmations {
+        FlashMessage::info(format!("Sent {} confirmation emails.", outcome.confirmations_sent)).send();
+    }
+    if outcome.confirmations_failed > 0 {
+        FlashMessage::error(format!(
+            "{} confirmation emails could not be sent, those subscribers will get a reminder later.",
+            outcome.confirmations_failed,
+        )).send();
+    }
+    if outcome.welcomes_sent > 0 {
+        FlashMessage::info(format!("Sent {} welcome emails.", outcome.welcomes_sent)).send();
+    }
+    if outcome.welcomes_failed > 0 {
+        FlashMessage::error(format!("{} welcome emails could not be sent.", outcome.welcomes_failed)).send();
+    }
+    Ok(see_other("/admin/import"))
+}
+
+fn preview_page(
+    import_id: Uuid,
+    preview: &ImportPreview,
+    send_confirmations: bool,
+    list: Option<&NewsletterList>,
+) -> String {
+    let single_opt_in = list.is_some_and(|l| l.opt_in_mode == Some(OptInMode::Single));
+    let list_html = match list {
+        Some(list) if single_opt_in => format!(
+            "<p>Importing into {}, which uses single opt-in: rows without a status are \
+            confirmed right away and get a welcome email. New subscribers only receive this list.</p>",
+            encode_minimal(&list.name),
+        ),
+        Some(list) => format!(
+            "<p>Importing into {}, new subscribers only receive this list.</p>",
+            encode_minimal(&list.name),
+        ),
+        None => String::new(),
+    };
+    let mut valid_html = String::new();
+    for row in &preview.valid {
+        writeln!(
+            valid_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            row.line,
+            encode_minimal(row.subscriber.email.as_ref()),
+            encode_minimal(row.subscriber.name.as_ref()),
+            row.status.as_str(),
+            encode_minimal(&row.tags.join(", ")),
+        )
+        .unwrap();
+    }
+    let confirm_html = if preview.valid.is_empty() {
+        "<p>There is nothing to import.</p>".to_owned()
+    } else {
+        format!(
+            r#"<form action="/admin/import/{import_id}" method="post">
+        <button type="submit">Import {} subscribers{}</button>
+    </form>"#,
+            preview.valid.len(),
+            if send_confirmations && !single_opt_in { " and send confirmation emails" } else { "" },
+        )
+    };
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    <h2>Dry run</h2>
+    <p>{} valid, {} invalid, {} duplicates.</p>
+    {list_html}
+    {confirm_html}
+    <h3>Valid rows</h3>
+    <table>
+        <tr><th>Line</th><th>Email</th><th>Name</th><th>Status</th><th>Tags</th></tr>
+        {valid_html}
+    </table>
+    <h3>Invalid rows</h3>
+    {}
+    <h3>Duplicates</h3>
+    {}
+    <p><a href="/admin/import">Upload another file</a></p>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#,
+        preview.valid.len(),
+        preview.invalid.len(),
+        preview.duplicates.len(),
+        rejected_rows_table(&preview.invalid),
+        rejected_rows_table(&preview.duplicates),
+    )
+}
+
+fn rejected_rows_table(rows: &[RejectedRow]) -> String {
+    let mut rows_html = String::new();
+    for row in rows {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            row.line,
+            encode_minimal(&row.email),
+            encode_minimal(&row.reason),
+        )
+        .unwrap();
+    }
+    format!(
+        r#"<table>
+        <tr><th>Line</th><th>Email</th><th>Reason</th></tr>
+        {rows_html}
+    </table>"#
+    )
+}
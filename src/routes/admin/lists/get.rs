@@ -0,0 +1,84 @@
+//! src/routes/admin/lists/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::OptInMode;
+use crate::newsletter_lists::get_lists;
+use crate::startup::DefaultOptInMode;
+use crate::utils::e500;
+
+pub async fn newsletter_lists(
+    pool: web::Data<PgPool>,
+    default_opt_in_mode: web::Data<DefaultOptInMode>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for list in &lists {
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            list.key,
+            encode_minimal(&list.name),
+            encode_minimal(&list.description),
+            match list.opt_in_mode {
+                Some(mode) => mode.label().to_owned(),
+                None => format!("Default ({})", default_opt_in_mode.0.label()),
+            },
+        )
+        .unwrap();
+    }
+
+    let mut mode_options_html = format!(
+        r#"<option value="">Default ({})</option>"#,
+        default_opt_in_mode.0.label(),
+    );
+    for mode in OptInMode::ALL {
+        write!(mode_options_html, r#"<option value="{}">{}</option>"#, mode.as_str(), mode.label()).unwrap();
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter lists</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Add a list</h2>
+    <form action="/admin/lists" method="post">
+        <label>Key
+            <input type="text" placeholder="product_updates" name="key" required>
+        </label>
+        <label>Name
+            <input type="text" placeholder="Product updates" name="name" required>
+        </label>
+        <label>Description
+            <input type="text" placeholder="What changed in the product" name="description" required>
+        </label>
+        <label>Opt-in mode
+            <select name="opt_in_mode">{mode_options_html}</select>
+        </label>
+        <button type="submit">Add</button>
+    </form>
+    <h2>Lists</h2>
+    <table>
+        <tr><th>Key</th><th>Name</th><th>Description</th><th>Opt-in mode</th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
@@ -0,0 +1,7 @@
+//! src/routes/admin/lists/mod.rs
+
+mod get;
+mod post;
+
+pub use get::newsletter_lists;
+pub use post::add_newsletter_list;
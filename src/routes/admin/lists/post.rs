@@ -0,0 +1,55 @@
+//! src/routes/admin/lists/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::domain::OptInMode;
+use crate::newsletter_lists::{add_list, NewsletterList};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct ListFormData {
+    key: String,
+    name: String,
+    description: String,
+    /// Empty means the list follows the global setting.
+    #[serde(default)]
+    opt_in_mode: String,
+}
+
+pub async fn add_newsletter_list(
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let key = match NewsletterList::parse_key(&form.key) {
+        Ok(key) => key,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+    let opt_in_mode = match form.opt_in_mode.as_str() {
+        "" => None,
+        mode => match OptInMode::try_from(mode.to_owned()) {
+            Ok(mode) => Some(mode),
+            Err(e) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other("/admin/lists"));
+            }
+        },
+    };
+    let list = NewsletterList {
+        key,
+        name: form.name.trim().to_owned(),
+        description: form.description.trim().to_owned(),
+        opt_in_mode,
+    };
+
+    if add_list(&pool, &list).await.map_err(e500)? {
+        FlashMessage::info(format!("The list {} has been added.", list.key)).send();
+    } else {
+        FlashMessage::error(format!("A list named {} already exists.", list.key)).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
@@ -3,7 +3,27 @@
 mod dashboard;
 mod password;
 mod logout;
+mod suppressions;
+mod templates;
+mod lists;
+mod domains;
+mod data_requests;
+mod consent;
+mod import;
+mod export;
+mod subscribers;
+mod attributes;
 
 pub use dashboard::admin_dashboard;
 pub use password::*;
-pub use logout::*;
\ No newline at end of file
+pub use logout::*;
+pub use suppressions::*;
+pub use templates::*;
+pub use lists::*;
+pub use domains::*;
+pub use data_requests::*;
+pub use consent::consent_history;
+pub use import::*;
+pub use export::{export_form, export_subscribers};
+pub use subscribers::*;
+pub use attributes::*;
\ No newline at end of file
@@ -0,0 +1,355 @@
+//! src/routes/admin/subscribers/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::admin_actions::get_admin_actions;
+use crate::consent::get_consent_events;
+use crate::subscriber_attributes::{display_value, get_attribute_definitions};
+use crate::subscribers::{
+    get_delivery_records, get_subscriber, get_subscriber_tokens, search_subscribers,
+    SubscriberCursor, SubscriberFilterForm, SUBSCRIBER_STATUSES,
+};
+use crate::utils::{e500, see_other};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct ListQuery {
+    #[serde(flatten)]
+    filter: SubscriberFilterForm,
+    /// Set by the next page link.
+    cursor: Option<String>,
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M").to_string()).unwrap_or_else(|| "-".into())
+}
+
+pub async fn subscriber_list(
+    query: web::Query<ListQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ListQuery { filter: filter_form, cursor } = query.into_inner();
+    let parsed = filter_form.parse().and_then(|filter| {
+        let cursor = cursor.as_deref().filter(|c| !c.is_empty()).map(SubscriberCursor::parse).transpose()?;
+        Ok((filter, cursor))
+    });
+    let (filter, cursor) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    let page = search_subscribers(&pool, &filter, cursor.as_ref(), PAGE_SIZE).await.map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut status_options_html = String::new();
+    for (status, label) in SUBSCRIBER_STATUSES {
+        let selected = if filter.status.as_deref() == Some(*status) { " selected" } else { "" };
+        writeln!(status_options_html, r#"<option value="{}"{}>{}</option>"#, status, selected, label).unwrap();
+    }
+    let mut rows_html = String::new();
+    for subscriber in &page.subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td><a href="/admin/subscribers/{}">{}</a></td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+        </tr>"#,
+            subscriber.id,
+            encode_minimal(&subscriber.email),
+            encode_minimal(&subscriber.name),
+            subscriber.status,
+            encode_minimal(&subscriber.tags.join(", ")),
+            subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        )
+        .unwrap();
+    }
+    let filter_query = filter_form.to_query();
+    let mut pages_html = String::new();
+    if cursor.is_some() {
+        write!(pages_html, r#"<a href="/admin/subscribers?{}">First page</a> "#, encode_minimal(&filter_query)).unwrap();
+    }
+    if let Some(next) = &page.next {
+        write!(
+            pages_html,
+            r#"<a href="/admin/subscribers?{}&amp;cursor={}">Next page</a>"#,
+            encode_minimal(&filter_query),
+            next,
+        )
+        .unwrap();
+    }
+    let field = |value: &Option<String>| encode_minimal(value.as_deref().unwrap_or_default());
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers" method="get">
+        <label>Email or name contains
+            <input type="text" name="search" value="{}">
+        </label>
+        <label>Status
+            <select name="status">
+                <option value="">All</option>
+                {status_options_html}
+            </select>
+        </label>
+        <label>Subscribed from
+            <input type="date" name="from" value="{}">
+        </label>
+        <label>to
+            <input type="date" name="to" value="{}">
+        </label>
+        <button type="submit">Search</button>
+    </form>
+    <h2>Add a subscriber</h2>
+    <form action="/admin/subscribers" method="post">
+        <label>Email
+            <input type="email" name="email" required>
+        </label>
+        <label>Name
+            <input type="text" name="name" required>
+        </label>
+        <label>
+            <input type="checkbox" name="confirmed" value="on">
+            They have already agreed, skip the confirmation email
+        </label>
+        <button type="submit">Add</button>
+    </form>
+    <h2>Subscribers</h2>
+    <table>
+        <tr><th>Email</th><th>Name</th><th>Status</th><th>Tags</th><th>Subscribed (UTC)</th></tr>
+        {rows_html}
+    </table>
+    <p>{pages_html}</p>
+    <p><a href="/admin/export/subscribers?{}&amp;format=csv">Export these subscribers as CSV</a></p>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#,
+        field(&filter_form.search),
+        field(&filter_form.from),
+        field(&filter_form.to),
+        encode_minimal(&filter_query),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
+
+/// A subscriber with their consent history, confirmation links and what the
+/// email provider reported about the emails sent to them.
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let Some(subscriber) = get_subscriber(&pool, subscriber_id).await.map_err(e500)? else {
+        FlashMessage::error("The subscriber could not be found.").send();
+        return Ok(see_other("/admin/subscribers"));
+    };
+    let consent_events = get_consent_events(&pool, &[subscriber_id]).await.map_err(e500)?;
+    let tokens = get_subscriber_tokens(&pool, subscriber_id).await.map_err(e500)?;
+    let deliveries = get_delivery_records(&pool, subscriber_id).await.map_err(e500)?;
+    let admin_actions = get_admin_actions(&pool, subscriber_id).await.map_err(e500)?;
+    let definitions = get_attribute_definitions(&pool).await.map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    // values of deleted attributes are removed with them, so every key has a definition
+    let mut attributes_html = String::new();
+    for definition in &definitions {
+        if let Some(value) = subscriber.attributes.get(&definition.key) {
+            writeln!(
+                attributes_html,
+                "<li>{}: {}</li>",
+                encode_minimal(&definition.label),
+                encode_minimal(&display_value(value)),
+            )
+            .unwrap();
+        }
+    }
+    let mut actions_html = String::new();
+    if subscriber.status != "erased" {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{subscriber_id}/name" method="post">
+        <label>Name
+            <input type="text" name="name" value="{}" required>
+        </label>
+        <button type="submit">Rename</button>
+    </form>"#,
+            encode_minimal(&subscriber.name),
+        )
+        .unwrap();
+    }
+    if subscriber.status == "pending_confirmation" {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{subscriber_id}/confirm" method="post">
+        <button type="submit">Confirm without the confirmation link</button>
+    </form>"#,
+        )
+        .unwrap();
+    }
+    if subscriber.status != "unsubscribed" && subscriber.status != "erased" {
+        writeln!(
+            actions_html,
+            r#"<form action="/admin/subscribers/{subscriber_id}/unsubscribe" method="post">
+        <label>Reason
+            <input type="text" name="reason" placeholder="Asked by phone">
+        </label>
+        <button type="submit">Unsubscribe</button>
+    </form>"#,
+        )
+        .unwrap();
+    }
+    writeln!(
+        actions_html,
+        r#"<form action="/admin/subscribers/{subscriber_id}/delete" method="post">
+        <button type="submit">Delete</button>
+    </form>"#,
+    )
+    .unwrap();
+    let mut admin_actions_html = String::new();
+    for action in &admin_actions {
+        writeln!(
+            admin_actions_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            action.performed_at.format("%Y-%m-%d %H:%M:%S"),
+            action.action,
+            encode_minimal(&action.username),
+        )
+        .unwrap();
+    }
+
+    let mut consent_html = String::new();
+    for event in &consent_events {
+        writeln!(
+            consent_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            event.occurred_at.format("%Y-%m-%d %H:%M:%S"),
+            event.event_type,
+            event.source,
+            encode_minimal(event.consent_text_version.as_deref().unwrap_or("-")),
+        )
+        .unwrap();
+    }
+    let mut tokens_html = String::new();
+    for token in &tokens {
+        writeln!(
+            tokens_html,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            token.created_at.format("%Y-%m-%d %H:%M"),
+            format_time(token.used_at),
+        )
+        .unwrap();
+    }
+    let mut deliveries_html = String::new();
+    for delivery in &deliveries {
+        writeln!(
+            deliveries_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            delivery.received_at.format("%Y-%m-%d %H:%M:%S"),
+            delivery.event_type,
+            encode_minimal(&delivery.email),
+            encode_minimal(delivery.description.as_deref().unwrap_or("-")),
+        )
+        .unwrap();
+    }
+
+    let confirmation_html = if subscriber.confirmation_requested {
+        ""
+    } else {
+        "<li>Imported without a confirmation email, not reminded or deleted</li>"
+    };
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Subscriber</title>
+</head>
+<body>
+    {msg_html}
+    <h2>{}</h2>
+    <ul>
+        <li>Typed as: {}</li>
+        <li>Name: {}</li>
+        <li>Status: {}</li>
+        <li>Locale: {}</li>
+        <li>Frequency: {}</li>
+        <li>Tags: {}</li>
+        <li>Source: {}</li>
+        <li>Campaign: {}</li>
+        <li>Subscribed (UTC): {}</li>
+        {confirmation_html}
+        <li>Reminded (UTC): {}</li>
+        <li>Unsubscribed (UTC): {}</li>
+        <li>Unsubscribe reason: {}</li>
+        {attributes_html}
+    </ul>
+    {actions_html}
+    <h3>Consent history</h3>
+    <table>
+        <tr><th>When (UTC)</th><th>Event</th><th>Source</th><th>Consent text</th></tr>
+        {consent_html}
+    </table>
+    <p><a href="/admin/consent?email={}">Full consent details</a></p>
+    <h3>Confirmation links</h3>
+    <table>
+        <tr><th>Sent (UTC)</th><th>Used (UTC)</th></tr>
+        {tokens_html}
+    </table>
+    <h3>Delivery records</h3>
+    <table>
+        <tr><th>When (UTC)</th><th>Event</th><th>Address</th><th>Description</th></tr>
+        {deliveries_html}
+    </table>
+    <h3>Changes by admins</h3>
+    <table>
+        <tr><th>When (UTC)</th><th>Change</th><th>By</th></tr>
+        {admin_actions_html}
+    </table>
+    <p><a href="/admin/subscribers"><- Back</a></p>
+</body>
+</html>"#,
+        encode_minimal(&subscriber.email),
+        encode_minimal(subscriber.email_display.as_deref().unwrap_or(&subscriber.email)),
+        encode_minimal(&subscriber.name),
+        subscriber.status,
+        subscriber.locale,
+        subscriber.frequency,
+        encode_minimal(&subscriber.tags.join(", ")),
+        encode_minimal(subscriber.utm_source.as_deref().or(subscriber.referrer.as_deref()).unwrap_or("(direct)")),
+        encode_minimal(subscriber.utm_campaign.as_deref().unwrap_or("-")),
+        subscriber.subscribed_at.format("%Y-%m-%d %H:%M"),
+        format_time(subscriber.reminder_sent_at),
+        format_time(subscriber.unsubscribed_at),
+        encode_minimal(subscriber.unsubscribe_reason.as_deref().unwrap_or("-")),
+        urlencoding::encode(&subscriber.email),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
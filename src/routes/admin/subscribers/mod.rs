@@ -0,0 +1,9 @@
+//! src/routes/admin/subscribers/mod.rs
+
+mod get;
+mod post;
+
+pub use get::{subscriber_details, subscriber_list};
+pub use post::{
+    add_subscriber, admin_unsubscribe_subscriber, delete_subscriber, force_confirm_subscriber, rename_subscriber,
+};
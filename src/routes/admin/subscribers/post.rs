@@ -0,0 +1,272 @@
+//! src/routes/admin/subscribers/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::admin_actions::{record_admin_action, AdminAction};
+use crate::authentication::UserId;
+use crate::consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentSource};
+use crate::domain::{Locale, NewSubscriber, OptInMode, SubscriberEmail, SubscriberName};
+use crate::domain_policy::{DomainPolicy, DomainPolicyError};
+use crate::email_client::EmailClient;
+use crate::routes::{
+    delete_subscription_tokens, generate_subscription_token, get_subscriber_by_email, insert_subscriber,
+    send_confirmation_email, store_token,
+};
+use crate::startup::ApplicationBaseUrl;
+use crate::subscribers::delete_subscribers;
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    email: String,
+    name: String,
+    /// Set when the subscriber has already agreed, e.g. on paper.
+    confirmed: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RenameFormData {
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeFormData {
+    reason: Option<String>,
+}
+
+fn details_page(subscriber_id: Uuid) -> HttpResponse {
+    see_other(&format!("/admin/subscribers/{}", subscriber_id))
+}
+
+fn subscriber_not_found() -> HttpResponse {
+    FlashMessage::error("The subscriber could not be found.").send();
+    see_other("/admin/subscribers")
+}
+
+#[tracing::instrument(
+    name = "Adding a subscriber by hand",
+    skip(form, pool, email_client, base_url, domain_policy),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn add_subscriber(
+    form: web::Form<AddFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    domain_policy: web::Data<DomainPolicy>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AddFormData { email, name, confirmed } = form.into_inner();
+    let new_subscriber = match SubscriberEmail::parse(email)
+        .and_then(|email| Ok(NewSubscriber { email, name: SubscriberName::parse(name)?, locale: Locale::default() }))
+    {
+        Ok(new_subscriber) => new_subscriber,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    match domain_policy.check(&new_subscriber.email).await {
+        Ok(()) => {}
+        Err(DomainPolicyError::Rejected(e)) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+        Err(DomainPolicyError::UnexpectedError(e)) => return Err(e500(e)),
+    }
+    // without prior consent the subscriber confirms by email as usual
+    let opt_in_mode = if confirmed.is_some() { OptInMode::Single } else { OptInMode::Double };
+    let consent = ConsentContext::without_request(ConsentSource::Admin);
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")
+        .map_err(e500)?;
+    if let Some(existing) = get_subscriber_by_email(&mut transaction, &new_subscriber.email).await.map_err(e500)? {
+        FlashMessage::error(format!("{} is already a subscriber.", new_subscriber.email)).send();
+        return Ok(details_page(existing.id));
+    }
+    let Some(subscriber_id) = insert_subscriber(&mut transaction, &new_subscriber, opt_in_mode).await.map_err(e500)? else {
+        FlashMessage::error(format!("{} is already a subscriber.", new_subscriber.email)).send();
+        return Ok(see_other("/admin/subscribers"));
+    };
+    record_consent_event(&mut transaction, subscriber_id, ConsentEvent::Subscribed, &consent).await.map_err(e500)?;
+    let subscription_token = match opt_in_mode {
+        OptInMode::Single => {
+            record_consent_event(&mut transaction, subscriber_id, ConsentEvent::Confirmed, &consent).await.map_err(e500)?;
+            None
+        }
+        OptInMode::Double => {
+            let subscription_token = generate_subscription_token();
+            store_token(&mut transaction, subscriber_id, &subscription_token).await.map_err(e500)?;
+            Some(subscription_token)
+        }
+    };
+    record_admin_action(&mut transaction, **user_id, subscriber_id, AdminAction::Added).await.map_err(e500)?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to add a subscriber")
+        .map_err(e500)?;
+
+    let email = new_subscriber.email.to_string();
+    if let Some(subscription_token) = subscription_token {
+        if let Err(e) = send_confirmation_email(&pool, &email_client, new_subscriber, &base_url.0, &subscription_token).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to send a confirmation email to a subscriber added by hand");
+            FlashMessage::error(format!(
+                "The confirmation email to {} could not be sent, they will get a reminder later.",
+                email,
+            )).send();
+        }
+    }
+    FlashMessage::info(format!("{} has been added.", email)).send();
+    Ok(details_page(subscriber_id))
+}
+
+#[tracing::instrument(name = "Renaming a subscriber", skip(form, pool))]
+pub async fn rename_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<RenameFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let name = match SubscriberName::parse(form.0.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(details_page(subscriber_id));
+        }
+    };
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")
+        .map_err(e500)?;
+    let updated = sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2 WHERE id = $1 AND status <> 'erased'"#,
+        subscriber_id,
+        name.as_ref(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to rename the subscriber")
+    .map_err(e500)?
+    .rows_affected();
+    if updated == 0 {
+        return Ok(subscriber_not_found());
+    }
+    record_admin_action(&mut transaction, **user_id, subscriber_id, AdminAction::Renamed).await.map_err(e500)?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to rename a subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The name has been changed.").send();
+    Ok(details_page(subscriber_id))
+}
+
+/// Confirms a pending subscriber who could not use their confirmation link.
+#[tracing::instrument(name = "Confirming a subscriber by hand", skip(pool))]
+pub async fn force_confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")
+        .map_err(e500)?;
+    let updated = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status = 'pending_confirmation'"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to confirm the subscriber")
+    .map_err(e500)?
+    .rows_affected();
+    if updated == 0 {
+        FlashMessage::error("Only subscribers pending confirmation can be confirmed.").send();
+        return Ok(details_page(subscriber_id));
+    }
+    let consent = ConsentContext::without_request(ConsentSource::Admin);
+    record_consent_event(&mut transaction, subscriber_id, ConsentEvent::Confirmed, &consent).await.map_err(e500)?;
+    record_admin_action(&mut transaction, **user_id, subscriber_id, AdminAction::Confirmed).await.map_err(e500)?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to confirm a subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been confirmed.").send();
+    Ok(details_page(subscriber_id))
+}
+
+#[tracing::instrument(name = "Unsubscribing a subscriber by hand", skip(form, pool))]
+pub async fn admin_unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<UnsubscribeFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let reason = form.0.reason.map(|r| r.trim().to_owned()).filter(|r| !r.is_empty());
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")
+        .map_err(e500)?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = $2, unsubscribe_reason = $3
+        WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+        "#,
+        subscriber_id,
+        Utc::now(),
+        reason,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to unsubscribe the subscriber")
+    .map_err(e500)?
+    .rows_affected();
+    if updated == 0 {
+        // a bounce or complaint is kept, it already stops the newsletters
+        FlashMessage::error("Only pending and confirmed subscribers can be unsubscribed.").send();
+        return Ok(details_page(subscriber_id));
+    }
+    delete_subscription_tokens(&mut transaction, subscriber_id).await.map_err(e500)?;
+    let consent = ConsentContext::without_request(ConsentSource::Admin);
+    record_consent_event(&mut transaction, subscriber_id, ConsentEvent::Unsubscribed, &consent).await.map_err(e500)?;
+    record_admin_action(&mut transaction, **user_id, subscriber_id, AdminAction::Unsubscribed).await.map_err(e500)?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been unsubscribed.").send();
+    Ok(details_page(subscriber_id))
+}
+
+/// Deletes the subscriber outright. Unlike a GDPR erasure nothing is left of
+/// the subscription but its consent and delivery history.
+#[tracing::instrument(name = "Deleting a subscriber by hand", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")
+        .map_err(e500)?;
+    let (deleted, _) = delete_subscribers(&mut transaction, &[subscriber_id]).await.map_err(e500)?;
+    if deleted == 0 {
+        return Ok(subscriber_not_found());
+    }
+    record_admin_action(&mut transaction, **user_id, subscriber_id, AdminAction::Deleted).await.map_err(e500)?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to delete a subscriber")
+        .map_err(e500)?;
+
+    FlashMessage::info("The subscriber has been deleted.").send();
+    Ok(see_other("/admin/subscribers"))
+}
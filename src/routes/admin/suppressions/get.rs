@@ -0,0 +1,97 @@
+//! src/routes/admin/suppressions/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::suppression::get_suppressions;
+use crate::utils::e500;
+
+pub async fn suppression_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let entries = get_suppressions(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for entry in &entries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>{}</td>
+            <td>
+                <form action="/admin/suppressions/remove" method="post">
+                    <input type="hidden" name="id" value="{}">
+                    <button type="submit">Remove</button>
+                </form>
+            </td>
+        </tr>"#,
+            encode_minimal(&entry.value),
+            entry.kind,
+            encode_minimal(&entry.reason),
+            entry.source,
+            entry.created_at.format("%Y-%m-%d %H:%M"),
+            entry.id,
+        )
+        .unwrap();
+    }
+
+    let count = entries.len();
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Suppression list</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Add an entry</h2>
+    <form action="/admin/suppressions" method="post">
+        <label>Address or domain
+            <input type="text" placeholder="someone@example.com" name="value" required>
+        </label>
+        <label>Kind
+            <select name="kind">
+                <option value="address">Address</option>
+                <option value="domain">Domain</option>
+            </select>
+        </label>
+        <label>Reason
+            <input type="text" placeholder="Requested by the recipient" name="reason" required>
+        </label>
+        <button type="submit">Suppress</button>
+    </form>
+    <h2>Import entries</h2>
+    <form action="/admin/suppressions/import" method="post">
+        <label>One address or domain per line
+            <br>
+            <textarea name="entries" rows="10" cols="50" required></textarea>
+        </label>
+        <br>
+        <label>Reason
+            <input type="text" placeholder="Imported from the previous provider" name="reason" required>
+        </label>
+        <button type="submit">Import</button>
+    </form>
+    <h2>Suppressed ({count})</h2>
+    <table>
+        <tr><th>Value</th><th>Kind</th><th>Reason</th><th>Source</th><th>Added</th><th></th></tr>
+        {rows_html}
+    </table>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
@@ -0,0 +1,7 @@
+//! src/routes/admin/suppressions/mod.rs
+
+mod get;
+mod post;
+
+pub use get::suppression_list;
+pub use post::{add_to_suppression_list, import_suppression_list, remove_from_suppression_list};
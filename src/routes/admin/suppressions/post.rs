@@ -0,0 +1,106 @@
+//! src/routes/admin/suppressions/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::suppression::{add_suppression, remove_suppression, NewSuppression, SuppressionKind};
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    kind: String,
+    value: String,
+    reason: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    id: Uuid,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportFormData {
+    entries: String,
+    reason: String,
+}
+
+pub async fn add_to_suppression_list(
+    form: web::Form<AddFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let suppression = match SuppressionKind::try_from(form.kind.clone())
+        .and_then(|kind| NewSuppression::parse(kind, &form.value))
+    {
+        Ok(suppression) => suppression,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+
+    let inserted = add_suppression(pool.get_ref(), &suppression, form.reason.trim(), "admin")
+        .await
+        .map_err(e500)?;
+    if inserted {
+        FlashMessage::info(format!("{} has been added to the suppression list.", suppression.value)).send();
+    } else {
+        FlashMessage::info(format!("{} is already on the suppression list.", suppression.value)).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
+
+pub async fn remove_from_suppression_list(
+    form: web::Form<RemoveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    remove_suppression(&pool, form.id).await.map_err(e500)?;
+    FlashMessage::info("The entry has been removed from the suppression list.").send();
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[tracing::instrument(
+    name = "Importing suppression list entries",
+    skip(form, pool)
+)]
+pub async fn import_suppression_list(
+    form: web::Form<ImportFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut invalid = vec![];
+    let mut suppressions = vec![];
+    for line in form.entries.lines().filter(|l| !l.trim().is_empty()) {
+        match NewSuppression::parse_line(line) {
+            Ok(suppression) => suppressions.push(suppression),
+            Err(_) => invalid.push(line.trim().to_owned()),
+        }
+    }
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")
+        .map_err(e500)?;
+    let mut imported = 0;
+    for suppression in &suppressions {
+        if add_suppression(&mut *transaction, suppression, form.reason.trim(), "import")
+            .await
+            .map_err(e500)?
+        {
+            imported += 1;
+        }
+    }
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to import suppression list entries")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "Imported {} entries, {} were already suppressed.",
+        imported,
+        suppressions.len() - imported,
+    )).send();
+    if !invalid.is_empty() {
+        FlashMessage::error(format!("Skipped invalid entries: {}", invalid.join(", "))).send();
+    }
+    Ok(see_other("/admin/suppressions"))
+}
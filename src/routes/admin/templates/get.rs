@@ -0,0 +1,127 @@
+//! src/routes/admin/templates/get.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::domain::Locale;
+use crate::email_templates::{get_template, EmailTemplateKind};
+use crate::utils::e500;
+
+use super::{parse_template, template_location, TemplateQuery};
+
+pub async fn email_templates(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for kind in EmailTemplateKind::ALL {
+        let mut locales_html = vec![];
+        for locale in Locale::ALL {
+            let customized = get_template(&pool, kind, locale).await.map_err(e500)?.is_some();
+            locales_html.push(format!(
+                r#"<a href="{}">{}</a> ({})"#,
+                template_location(kind, locale),
+                locale.label(),
+                if customized { "customized" } else { "default" },
+            ));
+        }
+        writeln!(rows_html, "<li>{}: {}</li>", kind.label(), locales_html.join(", ")).unwrap();
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email templates</title>
+</head>
+<body>
+    {msg_html}
+    <h2>Email templates</h2>
+    <ul>
+        {rows_html}
+    </ul>
+    <p><a href="/admin/dashboard"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
+
+pub async fn edit_email_template_form(
+    name: web::Path<String>,
+    query: web::Query<TemplateQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some((kind, locale)) = parse_template(name.into_inner(), &query) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+
+    let template = get_template(&pool, kind, locale)
+        .await
+        .map_err(e500)?
+        .unwrap_or_else(|| kind.default_template(locale));
+    let variables = kind
+        .variables()
+        .iter()
+        .map(|v| format!("<code>{{{{{}}}}}</code>", v))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let name = kind.as_str();
+    let lang = locale.as_str();
+    let location = template_location(kind, locale);
+    let label = kind.label();
+    let language = locale.label();
+    let subject = encode_minimal(&template.subject);
+    let html_body = encode_minimal(&template.html_body);
+    let text_body = encode_minimal(&template.text_body);
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{label} email ({language})</title>
+</head>
+<body>
+    {msg_html}
+    <h2>{label} email ({language})</h2>
+    <p>Available variables: {variables}</p>
+    <form action="{location}" method="post">
+        <label>Subject
+            <input type="text" name="subject" value="{subject}" required>
+        </label>
+        <br>
+        <label>HTML body
+            <br>
+            <textarea name="html_body" rows="10" cols="80" required>{html_body}</textarea>
+        </label>
+        <br>
+        <label>Plain text body
+            <br>
+            <textarea name="text_body" rows="10" cols="80" required>{text_body}</textarea>
+        </label>
+        <br>
+        <button type="submit">Save</button>
+    </form>
+    <form action="/admin/templates/{name}/reset?locale={lang}" method="post">
+        <button type="submit">Reset to default</button>
+    </form>
+    <p><a href="/admin/templates"><- Back</a></p>
+</body>
+</html>"#);
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
@@ -0,0 +1,30 @@
+//! src/routes/admin/templates/mod.rs
+
+mod get;
+mod post;
+
+pub use get::{edit_email_template_form, email_templates};
+pub use post::{reset_email_template, save_email_template};
+
+use crate::domain::Locale;
+use crate::email_templates::EmailTemplateKind;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct TemplateQuery {
+    locale: Option<String>,
+}
+
+/// `None` if the template name or locale are unknown, English is the
+/// default locale.
+fn parse_template(name: String, query: &TemplateQuery) -> Option<(EmailTemplateKind, Locale)> {
+    let kind = EmailTemplateKind::try_from(name).ok()?;
+    let locale = match &query.locale {
+        Some(locale) => Locale::parse(locale).ok()?,
+        None => Locale::default(),
+    };
+    Some((kind, locale))
+}
+
+fn template_location(kind: EmailTemplateKind, locale: Locale) -> String {
+    format!("/admin/templates/{}?locale={}", kind.as_str(), locale.as_str())
+}
@@ -0,0 +1,64 @@
+//! src/routes/admin/templates/post.rs
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::email_templates::{reset_template, save_template, EmailTemplate};
+use crate::utils::{e500, see_other};
+
+use super::{parse_template, template_location, TemplateQuery};
+
+#[derive(serde::Deserialize)]
+pub struct TemplateFormData {
+    subject: String,
+    html_body: String,
+    text_body: String,
+}
+
+#[tracing::instrument(
+    name = "Saving an email template",
+    skip(form, pool)
+)]
+pub async fn save_email_template(
+    name: web::Path<String>,
+    query: web::Query<TemplateQuery>,
+    form: web::Form<TemplateFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some((kind, locale)) = parse_template(name.into_inner(), &query) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let form = form.into_inner();
+    let template = EmailTemplate {
+        subject: form.subject.trim().to_owned(),
+        html_body: form.html_body,
+        text_body: form.text_body,
+    };
+    let location = template_location(kind, locale);
+
+    // Rendering with placeholder values catches typos in variable names
+    // before the template is used for a real email.
+    let variables: Vec<_> = kind.variables().iter().map(|v| (*v, "")).collect();
+    if let Err(e) = template.render(&variables) {
+        FlashMessage::error(format!("The template was not saved. {}", e)).send();
+        return Ok(see_other(&location));
+    }
+
+    save_template(&pool, kind, locale, &template).await.map_err(e500)?;
+    FlashMessage::info("The template has been saved.").send();
+    Ok(see_other(&location))
+}
+
+pub async fn reset_email_template(
+    name: web::Path<String>,
+    query: web::Query<TemplateQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some((kind, locale)) = parse_template(name.into_inner(), &query) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    reset_template(&pool, kind, locale).await.map_err(e500)?;
+    FlashMessage::info("The template has been reset to its default.").send();
+    Ok(see_other(&template_location(kind, locale)))
+}
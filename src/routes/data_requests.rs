@@ -0,0 +1,271 @@
+//! src/routes/data_requests.rs
+
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+
+use crate::bot_protection::BotProtection;
+use crate::data_requests::{
+    erase_subscriber_data, export_subscriber_data, has_subscriber_data, DataRequestKind, DataRequestLink,
+    SubscriberDataExport,
+};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::{render_email, EmailTemplateKind};
+use crate::i18n::{request_locale, translate};
+use crate::routes::error_chain_fmt;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::utils::client_ip;
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestFormData {
+    email: String,
+    request: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DataRequestParameters {
+    request: String,
+    email: String,
+    expires_at: i64,
+    signature: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum DataRequestError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The data request link is invalid or has expired")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error("Too many requests, please try again later.")]
+    RateLimited,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for DataRequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for DataRequestError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// how the request is named in the confirmation email
+fn request_label(kind: DataRequestKind) -> &'static str {
+    match kind {
+        DataRequestKind::Export => "data.email_request.export",
+        DataRequestKind::Erasure => "data.email_request.erasure",
+    }
+}
+
+/// The export as a JSON file download, shared with the admin export.
+pub fn data_export_response(export: &SubscriberDataExport) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber-data.json".into())],
+        })
+        .json(export)
+}
+
+fn simple_page(request: &HttpRequest, message_id: &'static str) -> HttpResponse {
+    let locale = request_locale(request);
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <p>{}</p>
+</body>
+</html>"#,
+        locale.as_str(),
+        translate(locale, "data.title"),
+        translate(locale, message_id),
+    );
+    HttpResponse::Ok().content_type(ContentType::html()).body(html)
+}
+
+pub async fn data_request_form(request: HttpRequest) -> HttpResponse {
+    let locale = request_locale(&request);
+    let lang = locale.as_str();
+    let title = translate(locale, "data.title");
+    let email = translate(locale, "data.email");
+    let request_label_text = translate(locale, "data.request");
+    let export = translate(locale, "data.request.export");
+    let erasure = translate(locale, "data.request.erasure");
+    let submit = translate(locale, "data.submit");
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <form action="/subscriptions/data" method="post">
+        <label>{email}
+            <input type="email" name="email" required>
+        </label>
+        <label>{request_label_text}
+            <select name="request">
+                <option value="export">{export}</option>
+                <option value="erasure">{erasure}</option>
+            </select>
+        </label>
+        <button type="submit">{submit}</button>
+    </form>
+</body>
+</html>"#);
+    HttpResponse::Ok().content_type(ContentType::html()).body(html)
+}
+
+/// Emails a confirmation link to the address, nothing is exported or erased
+/// before it is clicked. The response is the same whether or not we hold
+/// data about the address.
+#[tracing::instrument(
+    name = "Requesting a subscriber data export or erasure",
+    skip(form, request, pool, email_client, base_url, secret, bot_protection)
+)]
+pub async fn request_subscriber_data(
+    form: web::Form<DataRequestFormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+    bot_protection: web::Data<BotProtection>,
+) -> Result<HttpResponse, DataRequestError> {
+    // every accepted request may send an email, like a subscription
+    if let Some(ip) = client_ip(&request) {
+        if !bot_protection.allow_ip(&ip) {
+            return Err(DataRequestError::RateLimited);
+        }
+    }
+    let form = form.into_inner();
+    let kind = DataRequestKind::try_from(form.request).map_err(DataRequestError::ValidationError)?;
+    let email = SubscriberEmail::parse(form.email).map_err(DataRequestError::ValidationError)?;
+    // counted whether we know the address or not, so the limit does not reveal it
+    if !bot_protection.allow_email(email.as_ref()) {
+        return Err(DataRequestError::RateLimited);
+    }
+
+    let known = has_subscriber_data(&pool, email.as_ref()).await
+        .context("Failed to check whether we hold data about an address")?;
+    if known {
+        let locale = request_locale(&request);
+        let link = DataRequestLink::new(kind, email.as_ref(), Utc::now()).url(&base_url.0, &secret);
+        let message = render_email(
+            &pool,
+            EmailTemplateKind::DataRequest,
+            locale,
+            &[
+                ("request", translate(locale, request_label(kind))),
+                ("confirmation_link", &link),
+            ],
+        ).await;
+        email_client.send_email(&email, &message.subject, &message.html_body, &message.text_body)
+            .await
+            .context("Failed to send the data request confirmation email")?;
+    }
+
+    Ok(simple_page(&request, "data.sent"))
+}
+
+fn verify_link(
+    parameters: &DataRequestParameters,
+    secret: &HmacSecret,
+) -> Result<DataRequestLink, DataRequestError> {
+    let kind = DataRequestKind::try_from(parameters.request.clone())
+        .map_err(|e| DataRequestError::InvalidLink(anyhow::anyhow!(e)))?;
+    let link = DataRequestLink {
+        kind,
+        email: parameters.email.clone(),
+        expires_at: parameters.expires_at,
+    };
+    link.verify(&parameters.signature, secret, Utc::now())
+        .map_err(DataRequestError::InvalidLink)?;
+    Ok(link)
+}
+
+/// Only shows a confirmation form: link scanners and previews issue GET
+/// requests, so the request is carried out on the POST.
+pub async fn confirm_data_request_form(
+    parameters: web::Query<DataRequestParameters>,
+    request: HttpRequest,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, DataRequestError> {
+    let link = verify_link(&parameters, &secret)?;
+
+    let locale = request_locale(&request);
+    let question = match link.kind {
+        DataRequestKind::Export => "data.confirm_export",
+        DataRequestKind::Erasure => "data.confirm_erasure",
+    };
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <p>{}</p>
+    <form action="/subscriptions/data/confirm" method="post">
+        <input type="hidden" name="request" value="{}">
+        <input type="hidden" name="email" value="{}">
+        <input type="hidden" name="expires_at" value="{}">
+        <input type="hidden" name="signature" value="{}">
+        <button type="submit">{}</button>
+    </form>
+</body>
+</html>"#,
+        locale.as_str(),
+        translate(locale, "data.title"),
+        translate(locale, question),
+        link.kind.as_str(),
+        encode_minimal(&link.email),
+        link.expires_at,
+        encode_minimal(&parameters.signature),
+        translate(locale, "data.confirm_submit"),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
+
+#[tracing::instrument(
+    name = "Carrying out a subscriber data request",
+    skip(form, request, pool, secret)
+)]
+pub async fn confirm_data_request(
+    form: web::Form<DataRequestParameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, DataRequestError> {
+    let link = verify_link(&form, &secret)?;
+    match link.kind {
+        DataRequestKind::Export => {
+            let export = export_subscriber_data(&pool, &link.email).await?;
+            Ok(data_export_response(&export))
+        }
+        DataRequestKind::Erasure => {
+            erase_subscriber_data(&pool, &link.email).await?;
+            Ok(simple_page(&request, "data.erased"))
+        }
+    }
+}
@@ -1,7 +1,105 @@
 //! src/routes/home/mod.rs
 
-use actix_web::HttpResponse;
+use actix_web::http::header::{ContentType, REFERER};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok().body(include_str!("home.html"))
-}
\ No newline at end of file
+use crate::acquisition::UTM_PARAMETERS;
+use crate::bot_protection::form_stamp;
+use crate::consent::CONSENT_TEXT_VERSION;
+use crate::i18n::{request_locale, translate};
+use crate::startup::HmacSecret;
+use crate::subscriber_attributes::{get_attribute_definitions, AttributeDefinition, AttributeKind, MAX_TEXT_LENGTH};
+use crate::utils::e500;
+
+pub async fn home(
+    request: HttpRequest,
+    hmac_secret: web::Data<HmacSecret>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let locale = request_locale(&request);
+    let definitions = get_attribute_definitions(&pool).await.map_err(e500)?;
+    let html = include_str!("home.html")
+        .replace("{{lang}}", locale.as_str())
+        .replace("{{title}}", translate(locale, "home.title"))
+        .replace("{{welcome}}", translate(locale, "home.welcome"))
+        .replace("{{name}}", translate(locale, "home.name"))
+        .replace("{{email}}", translate(locale, "home.email"))
+        .replace("{{attributes}}", &attribute_inputs(&definitions))
+        .replace("{{subscribe}}", translate(locale, "home.subscribe"))
+        .replace("{{consent}}", translate(locale, "home.consent"))
+        .replace("{{consent_version}}", CONSENT_TEXT_VERSION)
+        .replace("{{acquisition}}", &acquisition_inputs(&request))
+        .replace("{{form_stamp}}", &form_stamp(&hmac_secret, Utc::now()));
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+/// One input per admin-defined attribute, named `attributes[<key>]`.
+fn attribute_inputs(definitions: &[AttributeDefinition]) -> String {
+    let mut html = String::new();
+    for definition in definitions {
+        let name = format!("attributes[{}]", definition.key);
+        let required = if definition.required { " required" } else { "" };
+        let input = match definition.kind {
+            AttributeKind::Text => format!(r#"<input type="text" name="{}" maxlength="{}"{}>"#, name, MAX_TEXT_LENGTH, required),
+            AttributeKind::Number => format!(r#"<input type="number" name="{}" step="any"{}>"#, name, required),
+            AttributeKind::Boolean => format!(r#"<input type="checkbox" name="{}" value="true"{}>"#, name, required),
+            AttributeKind::Date => format!(r#"<input type="date" name="{}"{}>"#, name, required),
+            AttributeKind::Enum => {
+                let mut options = String::from(r#"<option value=""></option>"#);
+                for option in &definition.options {
+                    let option = encode_minimal(option);
+                    write!(options, r#"<option value="{}">{}</option>"#, option, option).unwrap();
+                }
+                format!(r#"<select name="{}"{}>{}</select>"#, name, required, options)
+            }
+        };
+        writeln!(
+            html,
+            "            <label>{}\n                {}\n            </label>",
+            encode_minimal(&definition.label),
+            input,
+        )
+        .unwrap();
+    }
+    html
+}
+
+/// Passes the campaign the visitor came from on to the subscription: the
+/// `utm_*` parameters of the page and the referring page, unless it is one of ours.
+fn acquisition_inputs(request: &HttpRequest) -> String {
+    let mut html = String::new();
+    let parameters: Vec<(String, String)> = serde_urlencoded::from_str(request.query_string()).unwrap_or_default();
+    for (name, value) in parameters.iter().filter(|(n, _)| UTM_PARAMETERS.contains(&n.as_str())) {
+        writeln!(
+            html,
+            r#"            <input type="hidden" name="{}" value="{}">"#,
+            name,
+            encode_minimal(value),
+        )
+        .unwrap();
+    }
+    let referrer = request
+        .headers()
+        .get(REFERER)
+        .and_then(|h| h.to_str().ok())
+        .filter(|r| {
+            let host = |url: &str| reqwest::Url::parse(url).ok().and_then(|u| u.host_str().map(ToOwned::to_owned));
+            // the Host header may carry a port, the referrer's host is compared without it
+            host(r) != host(&format!("http://{}", request.connection_info().host()))
+        });
+    if let Some(referrer) = referrer {
+        writeln!(
+            html,
+            r#"            <input type="hidden" name="referrer" value="{}">"#,
+            encode_minimal(referrer),
+        )
+        .unwrap();
+    }
+    html
+}
@@ -1,10 +1,13 @@
 //! src/routes/login/get.rs
 
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, HttpRequest, HttpResponse};
 use actix_web_flash_messages::{IncomingFlashMessages, Level};
 use std::fmt::Write;
 
+use crate::i18n::{request_locale, translate};
+
 pub async fn login_form(
+    request: HttpRequest,
     flash_messages: IncomingFlashMessages,
 ) -> HttpResponse {
     // let error_html = match request.cookie("_flash") {
@@ -18,25 +21,33 @@ pub async fn login_form(
         writeln!(error_msg, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let locale = request_locale(&request);
+    let lang = locale.as_str();
+    let title = translate(locale, "login.title");
+    let username = translate(locale, "login.username");
+    let username_placeholder = translate(locale, "login.username_placeholder");
+    let password = translate(locale, "login.password");
+    let password_placeholder = translate(locale, "login.password_placeholder");
+    let submit = translate(locale, "login.submit");
     let html = format!(
         r#"
         <!DOCTYPE html>
-        <html lang="en">
+        <html lang="{lang}">
             <head>
                 <meta http-equiv="content-type" content="text/html"; charset="UTF-8">
-                <title>Login</title>
+                <title>{title}</title>
             </head>
             <body>
                 {error_msg}
                 <form action="/login" method="POST">
-                    <label>Username
-                        <input type="text" placeholder="Enter username" name="username" required>
+                    <label>{username}
+                        <input type="text" placeholder="{username_placeholder}" name="username" required>
                     </label>
                     
-                    <label>Password
-                        <input type="password" placeholder="Enter password" name="password" required>
+                    <label>{password}
+                        <input type="password" placeholder="{password_placeholder}" name="password" required>
                     </label>
-                    <button type="submit">Login</button>
+                    <button type="submit">{submit}</button>
                 </form>
             </body>
         </html>
@@ -4,16 +4,28 @@ mod health_check;
 mod subscriptions;
 mod greet;
 mod subscriptions_confirm;
+mod subscriptions_resend;
 mod newsletters;
 mod home;
 mod login;
 mod admin;
+mod webhooks;
+mod unsubscribe;
+mod preferences;
+mod data_requests;
+mod pages;
 
 pub use health_check::*;
 pub use subscriptions::*;
 pub use greet::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_resend::*;
 pub use newsletters::*;
 pub use home::*;
 pub use login::*;
-pub use admin::*;
\ No newline at end of file
+pub use admin::*;
+pub use webhooks::*;
+pub use unsubscribe::*;
+pub use preferences::*;
+pub use data_requests::*;
+pub use pages::*;
\ No newline at end of file
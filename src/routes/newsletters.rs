@@ -4,10 +4,19 @@ use actix_web::{web, HttpRequest};
 use actix_web::http::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
 use secrecy::Secret;
 use sqlx::PgPool;
-use crate::domain::SubscriberEmail;
+use uuid::Uuid;
+use crate::domain::{Locale, SubscriberEmail};
+use crate::i18n::translate;
+use crate::newsletter_lists::{get_lists, GENERAL_LIST};
 use crate::routes::error_chain_fmt;
+use crate::subscriber_links::SubscriberLink;
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
 use crate::email_client::EmailClient;
+use crate::email_templates::render_placeholders;
+use crate::subscriber_attributes::{get_attribute_definitions, personalization_variables, Segment, SegmentCondition};
+use serde_json::{Map, Value};
 use anyhow::Context;
+use chrono::Utc;
 use base64::Engine;
 
 use crate::authentication::AuthError;
@@ -17,6 +26,10 @@ use crate::authentication::{Credentails, validate_credentials};
 pub struct NewsletterBody {
     title: String,
     content: NewsletterContent,
+    /// Only send to the subscribers of this list, the general list if `None`.
+    list: Option<String>,
+    /// Only send to the subscribers whose attributes match all of these.
+    segment: Option<Vec<SegmentCondition>>,
 }
 
 #[derive(serde::Deserialize)]
@@ -26,9 +39,12 @@ pub struct NewsletterContent {
 }
 
 struct ConfirmedSubscriber {
-    // id: String,
+    id: Uuid,
     // email: String,
     email: SubscriberEmail,
+    name: String,
+    locale: Locale,
+    attributes: Map<String, Value>,
 }
 
 #[derive(thiserror::Error)]
@@ -37,6 +53,8 @@ pub enum PublishError {
     UnexpectedError(#[from] anyhow::Error),
     #[error("Authorization failed")]
     AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
 }
 
 impl std::fmt::Debug for PublishError {
@@ -58,6 +76,9 @@ impl ResponseError for PublishError {
             Self::UnexpectedError(_) => {
                 HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
             }
+            Self::ValidationError(e) => {
+                HttpResponse::BadRequest().body(e.clone())
+            }
             Self::AuthError(_) => {
                 let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
                 let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
@@ -107,16 +128,18 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentails, anyhow::Erro
 
 #[tracing::instrument(
     name = "Publishing a newsletter",
-    skip(newsletter_body, pool, email_client, request),
+    skip(newsletter_body, pool, email_client, base_url, hmac_secret, request),
     fields(
         username = tracing::field::Empty,
         user_id = tracing::field::Empty,
     )
 )]
 pub async fn publish_newsletter(
-    newsletter_body: web::Json<NewsletterBody>,
+    mut newsletter_body: web::Json<NewsletterBody>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
     let credentials = basic_authentication(request.headers())
@@ -129,20 +152,85 @@ pub async fn publish_newsletter(
             AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
         })?;
     tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-    let subscribers = get_confirmed_subscribers(&pool)
+    if let Some(list) = &newsletter_body.list {
+        let exists = get_lists(&pool)
+            .await
+            .context("Failed to retrieve the newsletter lists")?
+            .iter()
+            .any(|l| &l.key == list);
+        if !exists {
+            return Err(PublishError::ValidationError(format!("There is no list named {}.", list)));
+        }
+    }
+    let definitions = get_attribute_definitions(&pool)
+        .await
+        .context("Failed to retrieve the subscriber attribute definitions")?;
+    let segment = newsletter_body.segment
+        .take()
+        .map(|conditions| Segment::parse(&definitions, conditions))
+        .transpose()
+        .map_err(PublishError::ValidationError)?;
+    // a mistyped placeholder is reported before anything is sent
+    let variables = |subscriber_name: &str, attributes: &Map<String, Value>| {
+        let mut variables = vec![("subscriber_name".to_string(), subscriber_name.to_string())];
+        variables.extend(personalization_variables(&definitions, attributes));
+        variables
+    };
+    personalize(&newsletter_body, &variables("", &Map::new())).map_err(PublishError::ValidationError)?;
+
+    let list = newsletter_body.list.as_deref().unwrap_or(GENERAL_LIST);
+    let subscribers = get_confirmed_subscribers(&pool, list)
         .await?;
         // .expect("Failed to retrieve confirmed subscribers");
     for subscriber in subscribers {
         match subscriber {
             Ok(subscriber) => {
+                if segment.as_ref().is_some_and(|s| !s.matches(&subscriber.attributes)) {
+                    continue;
+                }
+                let (title, html, text) = personalize(&newsletter_body, &variables(&subscriber.name, &subscriber.attributes))
+                    .map_err(anyhow::Error::msg)?;
+                let unsubscribe_link = SubscriberLink::Unsubscribe.url(&base_url.0, subscriber.id, &hmac_secret);
+                let preferences_link = SubscriberLink::Preferences.url(&base_url.0, subscriber.id, &hmac_secret);
+                let unsubscribe_label = translate(subscriber.locale, "newsletter.unsubscribe");
+                let preferences_label = translate(subscriber.locale, "newsletter.preferences");
+                let html_body = format!(
+                    "{}<p><a href=\"{}\">{}</a> | <a href=\"{}\">{}</a></p>",
+                    html,
+                    preferences_link,
+                    preferences_label,
+                    unsubscribe_link,
+                    unsubscribe_label,
+                );
+                let text_body = format!(
+                    "{}\n\n{}: {}\n{}: {}",
+                    text,
+                    preferences_label,
+                    preferences_link,
+                    unsubscribe_label,
+                    unsubscribe_link,
+                );
                 email_client.send_email(
                     &subscriber.email,
-                    &newsletter_body.title,
-                    &newsletter_body.content.html,
-                    &newsletter_body.content.text,
+                    &title,
+                    &html_body,
+                    &text_body,
                 )
                 .await
                 .with_context(|| format!("Failed to send newsletter to {}", subscriber.email))?;
+                sqlx::query!(
+                    r#"
+                    INSERT INTO list_deliveries (subscriber_id, list_key, last_sent_at)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (subscriber_id, list_key) DO UPDATE SET last_sent_at = EXCLUDED.last_sent_at
+                    "#,
+                    subscriber.id,
+                    list,
+                    Utc::now(),
+                )
+                .execute(pool.get_ref())
+                .await
+                .context("Failed to record the newsletter delivery")?;
             }
             Err(error) => {
                 tracing::warn!(
@@ -156,23 +244,60 @@ pub async fn publish_newsletter(
     Ok(HttpResponse::Ok().finish())
 }
 
+/// The title and bodies with `{{subscriber_name}}` and `{{attributes.<key>}}`
+/// substituted, escaped in the HTML body.
+fn personalize(newsletter: &NewsletterBody, variables: &[(String, String)]) -> Result<(String, String, String), String> {
+    let variables: Vec<(&str, &str)> = variables.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+    Ok((
+        render_placeholders(&newsletter.title, &variables, false)?,
+        render_placeholders(&newsletter.content.html, &variables, true)?,
+        render_placeholders(&newsletter.content.text, &variables, false)?,
+    ))
+}
+
 #[tracing::instrument(
     name = "Get confirmed subscribers",
     skip(pool)
 )]
-async fn get_confirmed_subscribers(pool: &PgPool) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    list: &str,
+) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
     let confirmed_subscribers = sqlx::query!(
         r#"
-        SELECT email
+        SELECT id, email, name, locale, attributes
         FROM subscriptions
         WHERE status = 'confirmed'
+        AND NOT EXISTS (
+            SELECT 1 FROM list_opt_outs
+            WHERE subscriber_id = subscriptions.id AND list_key = $1
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM list_deliveries
+            WHERE subscriber_id = subscriptions.id AND list_key = $1
+            AND (
+                (frequency = 'weekly' AND last_sent_at > $2::timestamptz - interval '7 days')
+                OR (frequency = 'monthly' AND last_sent_at > $2::timestamptz - interval '1 month')
+            )
+        )
         "#,
+        list,
+        Utc::now(),
     )
     .fetch_all(pool)
     .await?
     .into_iter()
     .map(|row| match SubscriberEmail::parse(row.email) {
-        Ok(email) => Ok(ConfirmedSubscriber { email }),
+        Ok(email) => Ok(ConfirmedSubscriber {
+            id: row.id,
+            email,
+            name: row.name,
+            locale: Locale::parse(&row.locale).unwrap_or_default(),
+            attributes: match row.attributes {
+                Value::Object(attributes) => attributes,
+                _ => Map::new(),
+            },
+        }),
         Err(error) => Err(anyhow::anyhow!(error))
     })
     .collect();    
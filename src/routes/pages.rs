@@ -0,0 +1,29 @@
+//! src/routes/pages.rs
+
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+
+use crate::domain::Locale;
+use crate::i18n::translate;
+
+/// A minimal page telling a subscriber how their request went. `body_html`
+/// is inserted as is, escape anything user provided.
+pub fn message_page(status: StatusCode, locale: Locale, title_id: &'static str, body_html: &str) -> HttpResponse {
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    {}
+</body>
+</html>"#,
+        locale.as_str(),
+        translate(locale, title_id),
+        body_html,
+    );
+    HttpResponse::build(status).content_type(ContentType::html()).body(html)
+}
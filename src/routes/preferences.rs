@@ -0,0 +1,392 @@
+//! src/routes/preferences.rs
+
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentSource};
+use crate::domain::{DeliveryFrequency, Locale, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::domain_policy::{DomainPolicy, DomainPolicyError};
+use crate::email_client::EmailClient;
+use crate::i18n::{request_locale, translate};
+use crate::newsletter_lists::get_lists;
+use crate::routes::{error_chain_fmt, generate_subscription_token, restart_double_opt_in, send_confirmation_email, store_token};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_links::SubscriberLink;
+use crate::utils::see_other;
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscriber_id: Uuid,
+    signature: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("The preferences link is invalid")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error("{0}")]
+    ValidationError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+            Self::ValidationError(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+struct StoredPreferences {
+    name: String,
+    email: String,
+    frequency: String,
+}
+
+pub async fn preferences_form(
+    parameters: web::Query<PreferencesParameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    secret: web::Data<HmacSecret>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, PreferencesError> {
+    SubscriberLink::Preferences
+        .verify(parameters.subscriber_id, &parameters.signature, &secret)
+        .map_err(PreferencesError::InvalidLink)?;
+    let preferences = sqlx::query_as!(
+        StoredPreferences,
+        r#"SELECT name, email, frequency FROM subscriptions WHERE id = $1 AND status <> 'erased'"#,
+        parameters.subscriber_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the preferences of a subscriber")?
+    .ok_or_else(|| PreferencesError::InvalidLink(anyhow::anyhow!("Unknown subscriber")))?;
+    let opted_out = sqlx::query!(
+        r#"SELECT list_key FROM list_opt_outs WHERE subscriber_id = $1"#,
+        parameters.subscriber_id,
+    )
+    .fetch_all(pool.get_ref())
+    .await
+    .context("Failed to retrieve the list opt-outs of a subscriber")?
+    .into_iter()
+    .map(|r| r.list_key)
+    .collect::<Vec<_>>();
+    let lists = get_lists(&pool).await
+        .context("Failed to retrieve the newsletter lists")?;
+
+    let locale = request_locale(&request);
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", encode_minimal(m.content())).unwrap();
+    }
+    let mut lists_html = String::new();
+    for list in &lists {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="list" value="{}"{}> {} <small>{}</small></label><br>"#,
+            list.key,
+            if opted_out.contains(&list.key) { "" } else { " checked" },
+            encode_minimal(&list.name),
+            encode_minimal(&list.description),
+        )
+        .unwrap();
+    }
+    let mut frequency_html = String::new();
+    for frequency in DeliveryFrequency::ALL {
+        let label_id = match frequency {
+            DeliveryFrequency::EveryIssue => "preferences.frequency.every_issue",
+            DeliveryFrequency::Weekly => "preferences.frequency.weekly",
+            DeliveryFrequency::Monthly => "preferences.frequency.monthly",
+        };
+        writeln!(
+            frequency_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            frequency.as_str(),
+            if preferences.frequency == frequency.as_str() { " selected" } else { "" },
+            translate(locale, label_id),
+        )
+        .unwrap();
+    }
+
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <h2>{title}</h2>
+    <form action="/subscriptions/preferences" method="post">
+        <input type="hidden" name="subscriber_id" value="{subscriber_id}">
+        <input type="hidden" name="signature" value="{signature}">
+        <label>{name_label}
+            <input type="text" name="name" value="{name}" required>
+        </label>
+        <br>
+        <label>{email_label}
+            <input type="email" name="email" value="{email}" required>
+        </label>
+        <br>
+        <p>{lists_label}</p>
+        {lists_html}
+        <label>{frequency_label}
+            <select name="frequency">
+                {frequency_html}
+            </select>
+        </label>
+        <br>
+        <button type="submit">{submit}</button>
+    </form>
+    <p><a href="{unsubscribe_link}">{unsubscribe_label}</a></p>
+</body>
+</html>"#,
+        lang = locale.as_str(),
+        title = translate(locale, "preferences.title"),
+        subscriber_id = parameters.subscriber_id,
+        signature = encode_minimal(&parameters.signature),
+        name_label = translate(locale, "preferences.name"),
+        name = encode_minimal(&preferences.name),
+        email_label = translate(locale, "preferences.email"),
+        email = encode_minimal(&preferences.email),
+        lists_label = translate(locale, "preferences.lists"),
+        frequency_label = translate(locale, "preferences.frequency"),
+        submit = translate(locale, "preferences.submit"),
+        unsubscribe_link = SubscriberLink::Unsubscribe.url("", parameters.subscriber_id, &secret),
+        unsubscribe_label = translate(locale, "newsletter.unsubscribe"),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
+
+/// The submitted form: checkboxes are sent as repeated `list` fields, which
+/// is why the form is deserialized as a sequence of key-value pairs.
+struct PreferencesFormData {
+    subscriber_id: Uuid,
+    signature: String,
+    name: String,
+    email: String,
+    frequency: String,
+    lists: Vec<String>,
+}
+
+impl TryFrom<Vec<(String, String)>> for PreferencesFormData {
+    type Error = String;
+
+    fn try_from(pairs: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut subscriber_id = None;
+        let (mut signature, mut name, mut email, mut frequency) = (None, None, None, None);
+        let mut lists = vec![];
+        for (key, value) in pairs {
+            match key.as_str() {
+                "subscriber_id" => subscriber_id = Some(value),
+                "signature" => signature = Some(value),
+                "name" => name = Some(value),
+                "email" => email = Some(value),
+                "frequency" => frequency = Some(value),
+                "list" => lists.push(value),
+                _ => {}
+            }
+        }
+        let missing = |field: &str| format!("Missing field `{}`.", field);
+        Ok(Self {
+            subscriber_id: subscriber_id
+                .ok_or_else(|| missing("subscriber_id"))?
+                .parse()
+                .map_err(|_| "Invalid subscriber id.".to_string())?,
+            signature: signature.ok_or_else(|| missing("signature"))?,
+            name: name.ok_or_else(|| missing("name"))?,
+            email: email.ok_or_else(|| missing("email"))?,
+            frequency: frequency.ok_or_else(|| missing("frequency"))?,
+            lists,
+        })
+    }
+}
+
+#[tracing::instrument(
+    name = "Updating subscriber preferences",
+    skip(form, request, pool, email_client, base_url, secret, domain_policy)
+)]
+pub async fn update_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+    domain_policy: web::Data<DomainPolicy>,
+) -> Result<HttpResponse, PreferencesError> {
+    let form = PreferencesFormData::try_from(form.into_inner())
+        .map_err(PreferencesError::ValidationError)?;
+    SubscriberLink::Preferences
+        .verify(form.subscriber_id, &form.signature, &secret)
+        .map_err(PreferencesError::InvalidLink)?;
+    let location = SubscriberLink::Preferences.url("", form.subscriber_id, &secret);
+    let locale = request_locale(&request);
+
+    let validated = SubscriberName::parse(form.name)
+        .and_then(|name| Ok((name, SubscriberEmail::parse(form.email)?)))
+        .and_then(|(name, email)| Ok((name, email, DeliveryFrequency::try_from(form.frequency)?)));
+    let (name, email, frequency) = match validated {
+        Ok(validated) => validated,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&location));
+        }
+    };
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let current = sqlx::query!(
+        r#"SELECT email, locale, status FROM subscriptions WHERE id = $1 AND status <> 'erased' FOR UPDATE"#,
+        form.subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscriber")?
+    .ok_or_else(|| PreferencesError::InvalidLink(anyhow::anyhow!("Unknown subscriber")))?;
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2, frequency = $3 WHERE id = $1"#,
+        form.subscriber_id,
+        name.as_ref(),
+        frequency.as_str(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to update the subscriber preferences")?;
+    save_list_opt_outs(&mut transaction, form.subscriber_id, &form.lists).await
+        .context("Failed to update the list opt-outs of a subscriber")?;
+
+    // a new address has to be confirmed before it receives newsletters, a
+    // different case (`Alice@` instead of `alice@`) is the same address
+    let email_changed = email.as_ref().to_lowercase() != current.email.to_lowercase();
+    // whoever left, bounced or complained is not sent a confirmation email,
+    // an old preferences link cannot sign them up again
+    let email_locked = email_changed
+        && !matches!(current.status.as_str(), "pending_confirmation" | "confirmed");
+    if !email_changed && email.as_ref() != current.email {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET email = $2, email_display = $3 WHERE id = $1"#,
+            form.subscriber_id,
+            email.as_ref(),
+            email.display_form(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the subscriber email")?;
+    }
+    let subscription_token = if email_changed && !email_locked {
+        let taken = sqlx::query!(
+            r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1) AND id <> $2"#,
+            email.as_ref(),
+            form.subscriber_id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to check whether the new email address is in use")?
+        .is_some();
+        if taken {
+            FlashMessage::error(translate(locale, "preferences.email_taken")).send();
+            return Ok(see_other(&location));
+        }
+        match domain_policy.check(&email).await {
+            Ok(()) => {}
+            Err(DomainPolicyError::Rejected(e)) => {
+                FlashMessage::error(e).send();
+                return Ok(see_other(&location));
+            }
+            Err(DomainPolicyError::UnexpectedError(e)) => return Err(e.into()),
+        }
+        let updated = sqlx::query!(
+            r#"UPDATE subscriptions SET email = $2, email_display = $3 WHERE id = $1"#,
+            form.subscriber_id,
+            email.as_ref(),
+            email.display_form(),
+        )
+        .execute(&mut *transaction)
+        .await;
+        match updated {
+            Ok(_) => {}
+            // a concurrent change to the same address committed after the check above
+            Err(sqlx::Error::Database(e)) if e.constraint() == Some("subscriptions_email_lower_key") => {
+                FlashMessage::error(translate(locale, "preferences.email_taken")).send();
+                return Ok(see_other(&location));
+            }
+            Err(e) => return Err(anyhow::Error::new(e).context("Failed to update the subscriber email").into()),
+        }
+        let subscriber = NewSubscriber {
+            name,
+            email,
+            locale: Locale::parse(&current.locale).unwrap_or_default(),
+        };
+        restart_double_opt_in(&mut transaction, form.subscriber_id).await
+            .context("Failed to restart the double opt-in after an email change")?;
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, form.subscriber_id, &subscription_token).await
+            .context("Failed to store the confirmation token after an email change")?;
+        Some((subscriber, subscription_token))
+    } else {
+        None
+    };
+    let consent = ConsentContext::from_request(&request, ConsentSource::Form);
+    record_consent_event(&mut transaction, form.subscriber_id, ConsentEvent::PreferencesUpdated, &consent).await
+        .context("Failed to record the preference change")?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to update subscriber preferences")?;
+
+    if let Some((subscriber, subscription_token)) = subscription_token {
+        send_confirmation_email(&pool, &email_client, subscriber, &base_url.0, &subscription_token)
+            .await
+            .context("Failed to send a confirmation email to the new address")?;
+        FlashMessage::info(translate(locale, "preferences.saved_confirm_email")).send();
+    } else if email_locked {
+        FlashMessage::info(translate(locale, "preferences.saved_email_unchanged")).send();
+    } else {
+        FlashMessage::info(translate(locale, "preferences.saved")).send();
+    }
+    Ok(see_other(&location))
+}
+
+#[tracing::instrument(
+    name = "Saving list opt-outs",
+    skip(transaction, selected_lists)
+)]
+async fn save_list_opt_outs(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    selected_lists: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM list_opt_outs WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_opt_outs (subscriber_id, list_key)
+        SELECT $1, key FROM newsletter_lists WHERE NOT (key = ANY($2))
+        "#,
+        subscriber_id,
+        selected_lists,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
@@ -1,56 +1,390 @@
 //! src/routes/subscriptions.rs
 
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web::error::InternalError;
 use actix_web::http::StatusCode;
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE};
 use anyhow::Context;
 use sqlx::{Executor, PgPool};
 use chrono::Utc;
 use uuid::Uuid;
-use crate::{domain::{NewSubscriber, SubscriberEmail, SubscriberName}, email_client::EmailClient, startup::ApplicationBaseUrl};
+use crate::{domain::{Locale, NewSubscriber, OptInMode, SubscriberEmail, SubscriberName}, email_client::{EmailClient, SendEmailError}, startup::{ApplicationBaseUrl, DefaultOptInMode, HmacSecret}};
+use crate::configurations::SubscriberRedirects;
+use crate::acquisition::{save_acquisition, Acquisition};
+use crate::bot_protection::{form_stamp, BotProtection};
+use crate::consent::{is_known_consent_text_version, record_consent_event, ConsentContext, ConsentEvent, ConsentSource};
+use crate::domain_policy::{DomainPolicy, DomainPolicyError};
+use crate::email_templates::{render_email, EmailTemplateKind};
+use crate::i18n::{request_locale, translate};
+use crate::newsletter_lists::{get_list, restrict_to_list};
+use crate::subscriber_attributes::{get_attribute_definitions, save_subscriber_attributes, validate_attributes};
+use crate::routes::message_page;
+use crate::utils::{client_ip, see_other};
+use htmlescape::encode_minimal;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlx::{Postgres, Transaction};
 use actix_web::ResponseError;
+use std::collections::HashMap;
 
 #[derive(serde::Deserialize)]    // 该处的属性宏#[derive()]用于自动为 FormData 结构体实现来自serde库的 trait: serde::Deserialize
 pub struct FormData {
-    email: String,
-    name: String,
+    // missing fields are reported together with invalid ones, see `ValidationErrors`
+    email: Option<String>,
+    name: Option<String>,
+    locale: Option<String>,
+    /// The list the form belongs to, new subscribers only receive that list.
+    /// Its opt-in mode can require double opt-in but not waive it.
+    list: Option<String>,
+    /// Signed time the form was rendered at, see `BotProtection::check_form_stamp`.
+    form_stamp: Option<String>,
+    /// Honeypot: hidden on the home page form, so only bots fill it in.
+    website: Option<String>,
+    challenge_response: Option<String>,
+    /// Version of the consent text the subscriber saw, the current one if not given.
+    consent_version: Option<String>,
+    /// Values of the admin-defined attributes, by key. HTML forms send them
+    /// as `attributes[<key>]` fields instead, see `FormData::take_attributes`.
+    attributes: Option<HashMap<String, serde_json::Value>>,
+    /// The `utm_*` parameters and referrer of the campaign that brought the subscriber.
+    #[serde(flatten)]
+    acquisition: Acquisition,
+    #[serde(flatten)]
+    other_fields: HashMap<String, serde_json::Value>,
+}
+
+impl FormData {
+    fn take_attributes(&mut self) -> HashMap<String, serde_json::Value> {
+        let mut attributes = self.attributes.take().unwrap_or_default();
+        for (field, value) in self.other_fields.drain() {
+            if let Some(key) = field.strip_prefix("attributes[").and_then(|f| f.strip_suffix(']')) {
+                attributes.insert(key.to_owned(), value);
+            }
+        }
+        attributes
+    }
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = ValidationErrors;
 
     fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(form.name)?;
-        let email = SubscriberEmail::parse(form.email)?;
-        Ok(NewSubscriber{name, email})
+        let mut errors = vec![];
+        let name = match form.name {
+            Some(name) => SubscriberName::parse(name)
+                .map_err(|e| errors.push(FieldError::new("name", e)))
+                .ok(),
+            None => {
+                errors.push(FieldError::new("name", "The name is missing."));
+                None
+            }
+        };
+        let email = match form.email {
+            Some(email) => SubscriberEmail::parse(email)
+                .map_err(|e| errors.push(FieldError::new("email", e)))
+                .ok(),
+            None => {
+                errors.push(FieldError::new("email", "The email address is missing."));
+                None
+            }
+        };
+        // unsupported languages fall back to English rather than failing the subscription
+        let locale = form.locale
+            .and_then(|l| Locale::parse(&l).ok())
+            .unwrap_or_default();
+        match (name, email) {
+            (Some(name), Some(email)) => Ok(NewSubscriber{name, email, locale}),
+            _ => Err(ValidationErrors(errors)),
+        }
     }
 }
 
+/// A form stamp for clients posting to `/subscriptions` without the home page
+/// form, it is subject to the same minimum fill time.
+pub async fn subscription_form_stamp(hmac_secret: web::Data<HmacSecret>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "form_stamp": form_stamp(&hmac_secret, Utc::now()),
+    }))
+}
+
+/// Accepts both `application/x-www-form-urlencoded` (the HTML form) and
+/// `application/json` bodies. Clients asking for JSON, or sending it, get
+/// JSON responses with field-level validation errors, everybody else gets an
+/// HTML page or the configured redirect.
 #[tracing::instrument(
     name = "Adding a new subscriber...",
-    skip(form, pool, email_client, base_url),
+    skip(body, request, pool, email_client, base_url, default_opt_in_mode, redirects, bot_protection, domain_policy, hmac_secret),
     fields(
         // request_id = %Uuid::new_v4(),
-        subscriber_name = %form.name,
-        subscriber_email = %form.email
+        subscriber_name = tracing::field::Empty,
+        subscriber_email = tracing::field::Empty,
     )
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
-    form: web::Form<FormData>, 
+    body: web::Bytes,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;  
-    // web::Form<T> 实际上是一个包含一泛型的元祖结构体，即 struct Form<T>(T)， 使用.0访问其第一个字段 T
+    default_opt_in_mode: web::Data<DefaultOptInMode>,
+    redirects: web::Data<SubscriberRedirects>,
+    bot_protection: web::Data<BotProtection>,
+    domain_policy: web::Data<DomainPolicy>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, InternalError<SubscribeError>> {
+    let wants_json = wants_json(&request);
+    let result = add_subscriber(
+        &body,
+        &request,
+        &pool,
+        &email_client,
+        &base_url,
+        default_opt_in_mode.0,
+        &bot_protection,
+        &domain_policy,
+        &hmac_secret,
+    ).await;
+    match result {
+        Ok(()) if wants_json => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Please check your inbox to confirm your subscription.",
+        }))),
+        Ok(()) => match &redirects.subscribed_url {
+            Some(url) => Ok(see_other(url)),
+            None => {
+                let locale = request_locale(&request);
+                Ok(message_page(
+                    StatusCode::OK,
+                    locale,
+                    "subscribe.title",
+                    &format!("<p>{}</p>", translate(locale, "subscribe.check_inbox")),
+                ))
+            }
+        },
+        Err(e) => {
+            let response = if wants_json {
+                e.json_response()
+            } else {
+                e.html_response(request_locale(&request))
+            };
+            Err(InternalError::from_response(e, response))
+        }
+    }
+}
+
+fn is_json(request: &HttpRequest) -> bool {
+    request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| {
+            let mime = h.split(';').next().unwrap_or_default().trim();
+            mime == "application/json" || mime.ends_with("+json")
+        })
+        .unwrap_or(false)
+}
+
+fn wants_json(request: &HttpRequest) -> bool {
+    let accepts_json = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.contains("application/json"))
+        .unwrap_or(false);
+    accepts_json || is_json(request)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn add_subscriber(
+    body: &[u8],
+    request: &HttpRequest,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    default_opt_in_mode: OptInMode,
+    bot_protection: &BotProtection,
+    domain_policy: &DomainPolicy,
+    hmac_secret: &HmacSecret,
+) -> Result<(), SubscribeError> {
+    let remote_ip = client_ip(request);
+    if let Some(ip) = &remote_ip {
+        if !bot_protection.allow_ip(ip) {
+            return Err(SubscribeError::RateLimited);
+        }
+    }
+    let mut form: FormData = if is_json(request) {
+        serde_json::from_slice(body)
+            .map_err(|e| SubscribeError::InvalidPayload(e.to_string()))?
+    } else {
+        serde_urlencoded::from_bytes(body)
+            .map_err(|e| SubscribeError::InvalidPayload(e.to_string()))?
+    };
+    let span = tracing::Span::current();
+    if let Some(name) = &form.name {
+        span.record("subscriber_name", tracing::field::display(name));
+    }
+    if let Some(email) = &form.email {
+        span.record("subscriber_email", tracing::field::display(email));
+    }
+    // bots get the same answer as everybody else, so they do not learn they have been caught
+    if form.website.as_deref().is_some_and(|w| !w.is_empty()) {
+        tracing::info!("Ignoring a subscription with a filled in honeypot field");
+        return Ok(());
+    }
+    // API clients get their stamp from `GET /subscriptions/form_stamp`
+    bot_protection
+        .check_form_stamp(form.form_stamp.as_deref(), hmac_secret, Utc::now())
+        .map_err(|e| SubscribeError::ValidationError(ValidationErrors(vec![FieldError::new("form_stamp", e)])))?;
+    if form.locale.is_none() {
+        form.locale = Some(request_locale(request).as_str().into());
+    }
+    let list = form.list.take();
+    let source = if is_json(request) { ConsentSource::Api } else { ConsentSource::Form };
+    let mut consent = ConsentContext::from_request(request, source);
+    // a subscription without a version did not say which text was agreed to
+    if let Some(version) = form.consent_version.take() {
+        if !is_known_consent_text_version(&version) {
+            return Err(SubscribeError::ValidationError(ValidationErrors(vec![
+                FieldError::new("consent_version", format!("Unknown consent text version {}.", version)),
+            ])));
+        }
+        consent = consent.with_consent_text_version(version);
+    }
+    let challenge_response = form.challenge_response.take();
+    let submitted_attributes = form.take_attributes();
+    let acquisition = std::mem::take(&mut form.acquisition).normalize();
+    let definitions = get_attribute_definitions(pool).await
+        .context("Failed to retrieve the subscriber attribute definitions")?;
+    // attribute errors are reported together with the other invalid fields
+    let (mut new_subscriber, attributes) = match (
+        NewSubscriber::try_from(form),
+        validate_attributes(&definitions, &submitted_attributes),
+    ) {
+        (Ok(new_subscriber), Ok(attributes)) => (new_subscriber, attributes),
+        (new_subscriber, attributes) => {
+            let mut errors = new_subscriber.err().map(|e| e.0).unwrap_or_default();
+            errors.extend(attributes.err().unwrap_or_default().into_iter().map(|e| {
+                FieldError::new(format!("attributes.{}", e.key), e.message)
+            }));
+            return Err(SubscribeError::ValidationError(ValidationErrors(errors)));
+        }
+    };
+    domain_policy.check(&new_subscriber.email).await.map_err(|e| match e {
+        DomainPolicyError::Rejected(e) => {
+            SubscribeError::ValidationError(ValidationErrors(vec![FieldError::new("email", e)]))
+        }
+        DomainPolicyError::UnexpectedError(e) => SubscribeError::UnexpectedError(e),
+    })?;
+    let challenge_passed = bot_protection
+        .verify_challenge(challenge_response.as_deref(), remote_ip.as_deref())
+        .await
+        .context("Failed to verify the challenge response")?;
+    if !challenge_passed {
+        return Err(SubscribeError::ValidationError(ValidationErrors(vec![
+            FieldError::new("challenge_response", "The challenge was not solved, please try again."),
+        ])));
+    }
+    if !bot_protection.allow_email_domain(new_subscriber.email.domain()) {
+        return Err(SubscribeError::RateLimited);
+    }
+    // a pending subscriber is mailed a new link every time, every address
+    // counts so the limit does not reveal who is on the list
+    if !bot_protection.allow_email(new_subscriber.email.as_ref()) {
+        return Err(SubscribeError::RateLimited);
+    }
+    let list = match list {
+        None => None,
+        Some(key) => Some(get_list(pool, &key).await
+            .context("Failed to retrieve the newsletter list")?
+            .ok_or_else(|| SubscribeError::ValidationError(ValidationErrors(vec![
+                FieldError::new("list", format!("There is no list named {}.", key)),
+            ])))?),
+    };
+    // anybody can pick the list, so here it can require double opt-in but
+    // never waive it, a single opt-in list only applies to admin imports
+    let opt_in_mode = match list.as_ref().and_then(|l| l.opt_in_mode) {
+        Some(OptInMode::Double) => OptInMode::Double,
+        _ => default_opt_in_mode,
+    };
     
     let mut transaction= pool.begin().await
         .context("Failed to acquire a pg connection from the pg pool")?;
     
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber).await
-        .context("Failed to insert a new subscriber into database")?;
+    let existing_subscriber = get_subscriber_by_email(&mut transaction, &new_subscriber.email).await
+        .context("Failed to look up an existing subscriber with the same email")?;
+    // Confirmed subscribers get the same response as new ones, so the endpoint
+    // does not reveal who is on the list. Addresses that bounced or complained
+    // are not emailed again.
+    if existing_subscriber.as_ref().is_some_and(|existing| {
+        matches!(existing.status.as_str(), "confirmed" | "bounced" | "complained")
+    }) {
+        return Ok(());
+    }
+    let is_new = existing_subscriber.is_none();
+    // somebody who left has to give their consent again
+    let opt_in_mode = match &existing_subscriber {
+        Some(existing) if existing.status != "pending_confirmation" => OptInMode::Double,
+        _ => opt_in_mode,
+    };
+    let subscriber_id = match existing_subscriber {
+        None => match insert_subscriber(&mut transaction, &new_subscriber, opt_in_mode).await
+            .context("Failed to insert a new subscriber into database")?
+        {
+            Some(subscriber_id) => subscriber_id,
+            // a concurrent sign-up with the same address won, it sends the email
+            None => return Ok(()),
+        },
+        // Whoever submits the form may not own the address, so the stored
+        // details are kept and used for the email.
+        Some(existing) => {
+            new_subscriber.name = SubscriberName::parse(existing.name).map_err(anyhow::Error::msg)?;
+            new_subscriber.locale = Locale::parse(&existing.locale).unwrap_or_default();
+            match opt_in_mode {
+                OptInMode::Single => {
+                    let confirmed = confirm_without_double_opt_in(&mut transaction, existing.id).await
+                        .context("Failed to confirm an existing subscriber")?;
+                    if !confirmed {
+                        return Err(anyhow::anyhow!("Only pending subscribers can be confirmed without double opt-in").into());
+                    }
+                }
+                // pending subscribers get a fresh confirmation email, anybody
+                // else (e.g. after unsubscribing) goes through double opt-in again
+                OptInMode::Double => {
+                    restart_double_opt_in(&mut transaction, existing.id).await
+                        .context("Failed to restart the double opt-in of an existing subscriber")?;
+                }
+            }
+            existing.id
+        }
+    };
+    if is_new {
+        // the consent was given for the list of the form only
+        if let Some(list) = &list {
+            restrict_to_list(&mut transaction, subscriber_id, &list.key).await
+                .context("Failed to restrict a new subscriber to their list")?;
+        }
+        save_subscriber_attributes(&mut transaction, subscriber_id, &attributes).await
+            .context("Failed to save the attributes of a new subscriber")?;
+        if !acquisition.is_empty() {
+            save_acquisition(&mut transaction, subscriber_id, &acquisition).await
+                .context("Failed to save the acquisition source of a new subscriber")?;
+        }
+    }
+
+    if opt_in_mode == OptInMode::Single {
+        for event in [ConsentEvent::Subscribed, ConsentEvent::Confirmed] {
+            record_consent_event(&mut transaction, subscriber_id, event, &consent).await
+                .context("Failed to record the consent of a new subscriber")?;
+        }
+        transaction.commit().await
+            .context("Failed to commit SQL transaction to store new subscriber")?;
+        send_welcome_email(pool, email_client, new_subscriber).await
+            .context("Failed to send a welcome email to new subscriber")?;
+        return Ok(());
+    }
+
+    record_consent_event(&mut transaction, subscriber_id, ConsentEvent::Subscribed, &consent).await
+        .context("Failed to record the consent of a new subscriber")?;
     let subscription_token = generate_subscription_token();
     store_token(&mut transaction, subscriber_id, &subscription_token).await
         .context("Failed to store the confirmation token for new subscriber")?;
@@ -59,88 +393,202 @@ pub async fn subscribe(
         .context("Failed to commit SQL transaction to store new subscriber")?;
 
     send_confirmation_email(
-        &email_client, 
+        pool,
+        email_client, 
         new_subscriber, 
         &base_url.0,
         &subscription_token,
     ).await
     .context("Failed to send a confirmation email to new subscriber")?;
     
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
 #[tracing::instrument(
     name = "Saving new subscriber details into database",
     skip(new_subscriber, transaction)
 )]
+/// Returns `None` if the address is taken, e.g. by a concurrent sign-up that
+/// committed after we looked it up.
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
-    new_subscriber: &NewSubscriber
-) -> Result<Uuid, sqlx::Error> {
+    new_subscriber: &NewSubscriber,
+    opt_in_mode: OptInMode,
+) -> Result<Option<Uuid>, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
-    let query = sqlx::query!(
+    let status = match opt_in_mode {
+        OptInMode::Double => "pending_confirmation",
+        OptInMode::Single => "confirmed",
+    };
+    let inserted = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, email_display, name, subscribed_at, status, locale)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT ((lower(email))) DO NOTHING
+        RETURNING id
         "#,   //使用 r#"..."# 包裹SQL查询，即使用原始字符串字面量定义查询语句，这样在SQL命令中不需要进行特殊字符的转义
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.display_form(),
         new_subscriber.name.as_ref(),
-        Utc::now()
-    );
-    transaction
-        .execute(query)    
-        // 若 subscribe 函数保留 PgConnection 作为参数，则不满足此处execute方法要求参数实现 Executor trait，
-        // PgConnection类型的可变引用实现了该 trait（可变引用的唯一性保证同时只能存在一个在该Postgres连接上的查询），但 web::Data 无法提供对原类型的可变引用
-        // 使用PgPool类型通过内部可变性实现共享引用
-        .await
-        .map_err(|e| {    // 此处闭包捕获 sqlx::query!(...).await 返回的 Err(e) 并将其所有权转移至闭包内（基于FnOnce trait实现）（若结果是Err的话）
-            tracing::error!("Failed to execute query: {:?}", e);
-            e
-        })?;
-    Ok(subscriber_id)
+        Utc::now(),
+        status,
+        new_subscriber.locale.as_str(),
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {    // 此处闭包捕获 sqlx::query!(...).await 返回的 Err(e) 并将其所有权转移至闭包内（基于FnOnce trait实现）（若结果是Err的话）
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(inserted.map(|r| r.id))
+}
+
+pub struct ExistingSubscriber {
+    pub id: Uuid,
+    pub status: String,
+    pub name: String,
+    pub locale: String,
+}
+
+#[tracing::instrument(
+    name = "Looking up an existing subscriber by email",
+    skip(transaction, email)
+)]
+pub async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<ExistingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        ExistingSubscriber,
+        r#"SELECT id, status, name, locale FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE"#,
+        email.as_ref(),
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+}
+
+/// Moves an existing subscriber back to `pending_confirmation` and
+/// invalidates their previous confirmation links. Their name and locale are
+/// left as they are.
+#[tracing::instrument(
+    name = "Restarting the double opt-in of an existing subscriber",
+    skip(transaction)
+)]
+pub async fn restart_double_opt_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'pending_confirmation' WHERE id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Single opt-in counterpart of `restart_double_opt_in`: confirms a pending
+/// subscriber right away and invalidates their confirmation links. Anybody
+/// else is left alone and `false` returned, they have to opt in again.
+#[tracing::instrument(
+    name = "Confirming an existing subscriber without double opt-in",
+    skip(transaction)
+)]
+pub async fn confirm_without_double_opt_in(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let confirmed = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status = 'pending_confirmation'"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?
+    .rows_affected();
+    if confirmed == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(true)
 }
 
 #[tracing::instrument(
     name = "Sending new subscriber a confirmation email",
-    skip(email_client, 
+    skip(pool,
+        email_client, 
         new_subscriber, 
         base_url, 
         subscription_token
     )
 )]
 pub async fn send_confirmation_email(
+    pool: &PgPool,
     email_client: &EmailClient,
     new_subscriber: NewSubscriber,
     base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), SendEmailError> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}", 
         base_url, 
         subscription_token
     );
 
-    let plain_body = format!(
-        "Welcome to our newsletter!\n
-        Visit {} to confirm your subscription.", 
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.", 
-        confirmation_link
-    );
+    let email = render_email(
+        pool,
+        EmailTemplateKind::Confirmation,
+        new_subscriber.locale,
+        &[
+            ("subscriber_name", new_subscriber.name.as_ref()),
+            ("confirmation_link", &confirmation_link),
+        ],
+    ).await;
 
     email_client.send_email(
         &new_subscriber.email,
-        "Welcome",
-        &html_body,
-        &plain_body,
+        &email.subject,
+        &email.html_body,
+        &email.text_body,
     ).await
 }
 
-fn generate_subscription_token() -> String {
+#[tracing::instrument(
+    name = "Sending new subscriber a welcome email",
+    skip(pool, email_client, new_subscriber)
+)]
+pub async fn send_welcome_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    new_subscriber: NewSubscriber,
+) -> Result<(), SendEmailError> {
+    let email = render_email(
+        pool,
+        EmailTemplateKind::Welcome,
+        new_subscriber.locale,
+        &[("subscriber_name", new_subscriber.name.as_ref())],
+    ).await;
+
+    email_client.send_email(
+        &new_subscriber.email,
+        &email.subject,
+        &email.html_body,
+        &email.text_body,
+    ).await
+}
+
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -159,10 +607,24 @@ pub async fn store_token(
 ) -> Result<(), StoreTokenError> {
     let query = sqlx::query!(
         r#"INSERT INTO subscription_tokens 
-        (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        (subscription_token, subscriber_id, created_at)
+        VALUES ($1, $2, $3)"#,
         subscription_token,
-        subscriber_id
+        subscriber_id,
+        Utc::now(),
+    );
+    transaction
+        .execute(query)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            StoreTokenError(e)
+        })?;
+    // an imported subscriber gets reminded and expires like anybody else
+    // once they have been sent a link
+    let query = sqlx::query!(
+        r#"UPDATE subscriptions SET confirmation_requested = TRUE WHERE id = $1 AND NOT confirmation_requested"#,
+        subscriber_id,
     );
     transaction
         .execute(query)
@@ -174,6 +636,22 @@ pub async fn store_token(
         Ok(())
 }
 
+/// Invalidates the confirmation links of a subscriber, for whenever they stop
+/// being pending: an old link must not bring back somebody who left.
+#[tracing::instrument(name = "Deleting the subscription tokens of a subscriber", skip(transaction))]
+pub async fn delete_subscription_tokens(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
 // #[derive(Debug)]
 pub struct StoreTokenError(sqlx::Error);
 
@@ -214,11 +692,38 @@ pub fn error_chain_fmt(
     Ok(())
 }
 
+#[derive(Debug, serde::Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { field: field.into(), message: message.into() }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl std::fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages: Vec<_> = self.0.iter().map(|e| e.message.as_str()).collect();
+        write!(f, "{}", messages.join(" "))
+    }
+}
+
 // #[derive(Debug)]
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
-    ValidationError(String),
+    ValidationError(ValidationErrors),
+    #[error("The request body could not be parsed: {0}")]
+    InvalidPayload(String),
+    #[error("Too many subscription attempts, please try again later.")]
+    RateLimited,
     // DatabaseError(sqlx::Error),
     // #[error("Failed to acquire a pg connection from pg pool")]
     // PoolError(#[source] sqlx::Error),
@@ -234,6 +739,48 @@ pub enum SubscribeError {
     UnexpectedError(#[from] anyhow::Error),
 }
 
+impl SubscribeError {
+    /// The error body for JSON clients, internal details are not exposed.
+    pub fn json_response(&self) -> HttpResponse {
+        let (message, errors) = match self {
+            SubscribeError::ValidationError(errors) => (self.to_string(), errors.0.as_slice()),
+            SubscribeError::InvalidPayload(_) |
+            SubscribeError::RateLimited => (self.to_string(), [].as_slice()),
+            SubscribeError::UnexpectedError(_) => (
+                "Something went wrong, please try again later.".to_string(),
+                [].as_slice(),
+            ),
+        };
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "message": message,
+            "errors": errors,
+        }))
+    }
+
+    /// The error page for the HTML form, internal details are not exposed.
+    pub fn html_response(&self, locale: Locale) -> HttpResponse {
+        let message_html = match self {
+            SubscribeError::UnexpectedError(_) => format!("<p>{}</p>", translate(locale, "subscribe.unexpected")),
+            _ => format!(
+                "<p>{}</p>\n    <p>{}</p>",
+                translate(locale, "subscribe.failed"),
+                encode_minimal(&self.to_string()),
+            ),
+        };
+        message_page(
+            self.status_code(),
+            locale,
+            "subscribe.title",
+            &format!(
+                r#"{}
+    <p><a href="/">{}</a></p>"#,
+                message_html,
+                translate(locale, "subscribe.back"),
+            ),
+        )
+    }
+}
+
 impl std::fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         error_chain_fmt(self, f)
@@ -271,7 +818,9 @@ impl std::fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
-            SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::ValidationError(_) |
+            SubscribeError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
 
             // SubscribeError::DatabaseError(_) |
             // SubscribeError::PoolError(_) |
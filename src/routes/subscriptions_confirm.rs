@@ -1,9 +1,17 @@
 //! src/routes/subscriptions_confirm.rs
 
-use actix_web::{HttpResponse, web, ResponseError, http::StatusCode};
-use sqlx::PgPool;
+use actix_web::{HttpRequest, HttpResponse, web, ResponseError, http::StatusCode};
+use actix_web::error::InternalError;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
-use crate::routes::subscriptions;
+use crate::configurations::SubscriberRedirects;
+use crate::consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentSource};
+use crate::domain::Locale;
+use crate::i18n::{request_locale, translate};
+use crate::routes::{message_page, subscriptions};
+use crate::startup::SubscriptionTokenTtl;
+use crate::utils::see_other;
 use anyhow::Context;
 
 #[derive(serde::Deserialize)]
@@ -15,6 +23,8 @@ pub struct Parameters {
 pub enum ConfirmationError {
     #[error("The provided subscription token was not found")]
     TokenNotFound,
+    #[error("This confirmation link has expired. Please request a new one at /subscriptions/resend")]
+    TokenExpired,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -23,6 +33,7 @@ impl ResponseError for ConfirmationError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::TokenNotFound => StatusCode::UNAUTHORIZED,
+            Self::TokenExpired => StatusCode::GONE,
             Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -34,63 +45,180 @@ impl std::fmt::Debug for ConfirmationError {
     }
 }
 
+impl ConfirmationError {
+    /// The page shown to people clicking the link, internal details are not exposed.
+    pub fn html_response(&self, locale: Locale) -> HttpResponse {
+        let resend_link = format!(
+            r#"<p><a href="/subscriptions/resend">{}</a></p>"#,
+            translate(locale, "confirm.resend"),
+        );
+        let body_html = match self {
+            Self::TokenNotFound => format!("<p>{}</p>\n    {}", translate(locale, "confirm.invalid"), resend_link),
+            Self::TokenExpired => format!("<p>{}</p>\n    {}", translate(locale, "confirm.expired"), resend_link),
+            Self::UnexpectedError(_) => format!("<p>{}</p>", translate(locale, "subscribe.unexpected")),
+        };
+        message_page(self.status_code(), locale, "confirm.title", &body_html)
+    }
+}
+
+enum ConfirmationOutcome {
+    Confirmed,
+    /// The link was clicked again, e.g. from another device.
+    AlreadyConfirmed,
+}
+
+/// Renders an HTML page for every outcome, unless a redirect is configured
+/// for successful confirmations.
 #[tracing::instrument(
     name = "Confirming a pending subscriber",
-    skip(parameters, pool)
+    skip(parameters, request, pool, token_ttl, redirects)
 )]
 pub async fn confirm(
     parameters: web::Query<Parameters>, 
-    pool: web::Data<PgPool>
-) -> Result<HttpResponse, ConfirmationError> {
-    let id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    token_ttl: web::Data<SubscriptionTokenTtl>,
+    redirects: web::Data<SubscriberRedirects>,
+) -> Result<HttpResponse, InternalError<ConfirmationError>> {
+    let (outcome, locale) = match confirm_token(&parameters.subscription_token, &request, &pool, token_ttl.0).await {
+        Ok(confirmed) => confirmed,
+        Err(e) => {
+            let response = e.html_response(request_locale(&request));
+            return Err(InternalError::from_response(e, response));
+        }
+    };
+    if let Some(url) = &redirects.confirmed_url {
+        return Ok(see_other(url));
+    }
+    let message_id = match outcome {
+        ConfirmationOutcome::Confirmed => "confirm.done",
+        ConfirmationOutcome::AlreadyConfirmed => "confirm.already",
+    };
+    Ok(message_page(
+        StatusCode::OK,
+        locale,
+        "confirm.title",
+        &format!("<p>{}</p>", translate(locale, message_id)),
+    ))
+}
+
+/// Returns the locale of the subscriber along with the outcome.
+async fn confirm_token(
+    subscription_token: &str,
+    request: &HttpRequest,
+    pool: &PgPool,
+    token_ttl: chrono::Duration,
+) -> Result<(ConfirmationOutcome, Locale), ConfirmationError> {
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let token = get_subscription_token(&mut transaction, subscription_token)
         .await
         .context("Failed to retrieve the subscriber id")?
         .ok_or(ConfirmationError::TokenNotFound)?;
-    confirm_subscriber(&pool, id)
+    let subscriber = sqlx::query!(
+        r#"SELECT status, locale FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        token.subscriber_id,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to retrieve the subscriber")?;
+    let locale = Locale::parse(&subscriber.locale).unwrap_or_default();
+    if subscriber.status == "confirmed" {
+        return Ok((ConfirmationOutcome::AlreadyConfirmed, locale));
+    }
+    // somebody who left, or whose address bounced, has to sign up again
+    if subscriber.status != "pending_confirmation" {
+        return Err(ConfirmationError::TokenNotFound);
+    }
+    // a token can only be used once
+    if token.used_at.is_some() {
+        return Err(ConfirmationError::TokenNotFound);
+    }
+    if token.created_at + token_ttl < Utc::now() {
+        return Err(ConfirmationError::TokenExpired);
+    }
+    let confirmed = confirm_subscriber(&mut transaction, token.subscriber_id, subscription_token)
         .await
         .context("Failed to confirm the subscriber")?;
+    if !confirmed {
+        return Err(ConfirmationError::TokenNotFound);
+    }
+    let consent = ConsentContext::from_request(request, ConsentSource::EmailLink);
+    record_consent_event(&mut transaction, token.subscriber_id, ConsentEvent::Confirmed, &consent)
+        .await
+        .context("Failed to record the confirmation of a subscriber")?;
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to confirm a subscriber")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok((ConfirmationOutcome::Confirmed, locale))
 }
 
 #[tracing::instrument(
     name = "Marking subscriber as confirmed",
-    skip(subscriber_id, pool)
+    skip(subscriber_id, transaction, subscription_token)
 )]
+/// Returns `false`, changing nothing, unless the subscriber is pending.
 pub async fn confirm_subscriber(
-    pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+    subscription_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let confirmed = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1 AND status = 'pending_confirmation'"#,
         subscriber_id
         )
-        .execute(pool)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?
+        .rows_affected();
+    if confirmed == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"UPDATE subscription_tokens SET used_at = $1 WHERE subscription_token = $2"#,
+        Utc::now(),
+        subscription_token,
+        )
+        .execute(&mut **transaction)
         .await
         .map_err(|e| {
             tracing::error!("Failed to execute query: {:?}", e);
             e
         })?;
-    Ok(())
+    Ok(true)
+}
+
+pub struct SubscriptionToken {
+    pub subscriber_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
 }
 
 #[tracing::instrument(
     name = "Getting subscriber id from token...",
-    skip(subscription_token, pool)
+    skip(subscription_token, transaction)
 )]
-pub async fn get_subscriber_id_from_token(
-    pool: &PgPool,
+pub async fn get_subscription_token(
+    transaction: &mut Transaction<'_, Postgres>,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error>{
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<Option<SubscriptionToken>, sqlx::Error>{
+    sqlx::query_as!(
+        SubscriptionToken,
+        r#"
+        SELECT subscriber_id, created_at, used_at
+        FROM subscription_tokens
+        WHERE subscription_token = $1
+        FOR UPDATE
+        "#,
         subscription_token,
         )
-        .fetch_optional(pool)
+        .fetch_optional(&mut **transaction)
         .await
         .map_err(|e| {
             tracing::error!("Failed to execute query: {:?}", e);
             e
-        })?;
-    Ok(result.map(|r| r.subscriber_id))
-}
\ No newline at end of file
+        })
+}
@@ -0,0 +1,128 @@
+//! src/routes/subscriptions_resend.rs
+
+use actix_web::{http::header::ContentType, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::bot_protection::BotProtection;
+use crate::domain::{Locale, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::i18n::{request_locale, translate};
+use crate::routes::{generate_subscription_token, restart_double_opt_in, send_confirmation_email, store_token, FieldError, SubscribeError, ValidationErrors};
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::client_ip;
+
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    email: String,
+}
+
+pub async fn resend_confirmation_form(request: HttpRequest) -> HttpResponse {
+    let locale = request_locale(&request);
+    let lang = locale.as_str();
+    let title = translate(locale, "resend.title");
+    let email = translate(locale, "resend.email");
+    let submit = translate(locale, "resend.submit");
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <form action="/subscriptions/resend" method="post">
+        <label>{email}
+            <input type="email" name="email" required>
+        </label>
+        <button type="submit">{submit}</button>
+    </form>
+</body>
+</html>"#);
+    HttpResponse::Ok().content_type(ContentType::html()).body(html)
+}
+
+/// Sends a fresh confirmation link to a pending subscriber, invalidating the
+/// previous ones. The response is the same whether or not the address is
+/// pending, so the endpoint does not reveal who is on the list.
+#[tracing::instrument(
+    name = "Resending a confirmation email",
+    skip(form, request, pool, email_client, base_url, bot_protection)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    bot_protection: web::Data<BotProtection>,
+) -> Result<HttpResponse, SubscribeError> {
+    if let Some(ip) = client_ip(&request) {
+        if !bot_protection.allow_ip(&ip) {
+            return Err(SubscribeError::RateLimited);
+        }
+    }
+    let email = SubscriberEmail::parse(form.0.email)
+        .map_err(|e| SubscribeError::ValidationError(ValidationErrors(vec![FieldError::new("email", e)])))?;
+    // every address counts, pending or not, so the limit does not reveal who is on the list
+    if !bot_protection.allow_email(email.as_ref()) {
+        return Err(SubscribeError::RateLimited);
+    }
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let pending_subscriber = sqlx::query!(
+        r#"
+        SELECT id, name, locale
+        FROM subscriptions
+        WHERE lower(email) = lower($1) AND status = 'pending_confirmation'
+        FOR UPDATE
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up a pending subscriber")?;
+
+    if let Some(row) = pending_subscriber {
+        let subscriber = NewSubscriber {
+            name: SubscriberName::parse(row.name).map_err(anyhow::Error::msg)?,
+            email,
+            locale: Locale::parse(&row.locale).unwrap_or_default(),
+        };
+        restart_double_opt_in(&mut transaction, row.id).await
+            .context("Failed to invalidate the previous confirmation tokens")?;
+        let subscription_token = generate_subscription_token();
+        store_token(&mut transaction, row.id, &subscription_token).await
+            .context("Failed to store the confirmation token for a pending subscriber")?;
+        transaction.commit().await
+            .context("Failed to commit SQL transaction to store a new confirmation token")?;
+
+        send_confirmation_email(
+            &pool,
+            &email_client,
+            subscriber,
+            &base_url.0,
+            &subscription_token,
+        ).await
+        .context("Failed to resend a confirmation email")?;
+    }
+
+    let locale = request_locale(&request);
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <p>{}</p>
+</body>
+</html>"#,
+        locale.as_str(),
+        translate(locale, "resend.title"),
+        translate(locale, "resend.sent"),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
@@ -0,0 +1,206 @@
+//! src/routes/unsubscribe.rs
+
+use actix_web::{http::header::ContentType, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentSource};
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_templates::{render_email, EmailTemplateKind};
+use crate::i18n::{request_locale, translate};
+use crate::routes::{delete_subscription_tokens, error_chain_fmt};
+use crate::startup::{ApplicationBaseUrl, HmacSecret};
+use crate::subscriber_links::SubscriberLink;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    signature: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeFormData {
+    subscriber_id: Uuid,
+    signature: String,
+    reason: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is invalid")]
+    InvalidLink(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidLink(_) => StatusCode::UNAUTHORIZED,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Only shows a confirmation form: link scanners and previews issue GET
+/// requests, so unsubscribing happens on the POST.
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    request: HttpRequest,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    SubscriberLink::Unsubscribe
+        .verify(parameters.subscriber_id, &parameters.signature, &secret)
+        .map_err(UnsubscribeError::InvalidLink)?;
+
+    let locale = request_locale(&request);
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <p>{}</p>
+    <form action="/subscriptions/unsubscribe" method="post">
+        <input type="hidden" name="subscriber_id" value="{}">
+        <input type="hidden" name="signature" value="{}">
+        <label>{}
+            <br>
+            <textarea name="reason" rows="4" cols="50"></textarea>
+        </label>
+        <br>
+        <button type="submit">{}</button>
+    </form>
+</body>
+</html>"#,
+        locale.as_str(),
+        translate(locale, "unsubscribe.title"),
+        translate(locale, "unsubscribe.question"),
+        parameters.subscriber_id,
+        encode_minimal(&parameters.signature),
+        translate(locale, "unsubscribe.reason"),
+        translate(locale, "unsubscribe.submit"),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
+
+#[tracing::instrument(
+    name = "Unsubscribing a subscriber",
+    skip(form, request, pool, email_client, base_url, secret),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn unsubscribe(
+    form: web::Form<UnsubscribeFormData>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    SubscriberLink::Unsubscribe
+        .verify(form.subscriber_id, &form.signature, &secret)
+        .map_err(UnsubscribeError::InvalidLink)?;
+
+    let reason = form.reason.as_deref()
+        .map(str::trim)
+        .filter(|r| !r.is_empty());
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let unsubscribed = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed', unsubscribed_at = $2, unsubscribe_reason = $3
+        WHERE id = $1 AND status IN ('pending_confirmation', 'confirmed')
+        RETURNING email, name, locale
+        "#,
+        form.subscriber_id,
+        Utc::now(),
+        reason,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to mark the subscriber as unsubscribed")?;
+    // unsubscribing twice changes nothing, so it is recorded and confirmed once.
+    // A bounce or complaint is kept, those addresses are not emailed again.
+    if unsubscribed.is_some() {
+        delete_subscription_tokens(&mut transaction, form.subscriber_id).await
+            .context("Failed to invalidate the confirmation links")?;
+        let consent = ConsentContext::from_request(&request, ConsentSource::Form);
+        record_consent_event(&mut transaction, form.subscriber_id, ConsentEvent::Unsubscribed, &consent).await
+            .context("Failed to record the unsubscription")?;
+    }
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")?;
+
+    if let Some(subscriber) = unsubscribed {
+        // the subscriber is gone either way, a failed email only gets logged
+        if let Err(e) = send_unsubscribe_confirmation_email(
+            &pool,
+            &email_client,
+            &base_url.0,
+            subscriber.email,
+            &subscriber.name,
+            &subscriber.locale,
+        ).await {
+            tracing::error!(error.cause_chain = ?e, "Failed to send the unsubscribe confirmation email");
+        }
+    }
+
+    let locale = request_locale(&request);
+    let html = format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{}</title>
+</head>
+<body>
+    <p>{}</p>
+</body>
+</html>"#,
+        locale.as_str(),
+        translate(locale, "unsubscribe.title"),
+        translate(locale, "unsubscribe.done"),
+    );
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(html))
+}
+
+#[tracing::instrument(
+    name = "Sending an unsubscribe confirmation email",
+    skip(pool, email_client, base_url, email, name)
+)]
+async fn send_unsubscribe_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    email: String,
+    name: &str,
+    locale: &str,
+) -> Result<(), anyhow::Error> {
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    // the home page carries the subscription form
+    let resubscribe_link = format!("{}/", base_url);
+    let message = render_email(
+        pool,
+        EmailTemplateKind::UnsubscribeConfirmation,
+        Locale::parse(locale).unwrap_or_default(),
+        &[
+            ("subscriber_name", name),
+            ("resubscribe_link", &resubscribe_link),
+        ],
+    ).await;
+    email_client.send_email(&email, &message.subject, &message.html_body, &message.text_body).await?;
+    Ok(())
+}
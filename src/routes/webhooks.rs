@@ -0,0 +1,216 @@
+//! src/routes/webhooks.rs
+
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError, http::StatusCode};
+use anyhow::Context;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::{delete_subscription_tokens, error_chain_fmt};
+use crate::startup::WebhookSecret;
+use crate::suppression::{add_suppression, NewSuppression, SuppressionKind};
+
+/// Postmark webhook payload, only the fields we act upon are deserialized.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce(BounceEvent),
+    SpamComplaint(SpamComplaintEvent),
+    // deliveries, opens, clicks, ... are acknowledged and ignored
+    #[serde(other)]
+    Other,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct BounceEvent {
+    #[serde(rename = "Type")]
+    bounce_type: String,
+    email: String,
+    description: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+pub struct SpamComplaintEvent {
+    email: String,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum EmailEventType {
+    HardBounce,
+    SoftBounce,
+    SpamComplaint,
+}
+
+impl EmailEventType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::HardBounce => "hard_bounce",
+            Self::SoftBounce => "soft_bounce",
+            Self::SpamComplaint => "spam_complaint",
+        }
+    }
+
+    /// The status a subscriber is moved to, soft bounces are only recorded.
+    fn subscriber_status(&self) -> Option<&'static str> {
+        match self {
+            Self::HardBounce => Some("bounced"),
+            Self::SoftBounce => None,
+            Self::SpamComplaint => Some("complained"),
+        }
+    }
+
+    fn from_bounce_type(bounce_type: &str) -> Self {
+        match bounce_type {
+            "HardBounce" | "BadEmailAddress" | "ManuallyDeactivated" => Self::HardBounce,
+            "SpamComplaint" => Self::SpamComplaint,
+            _ => Self::SoftBounce,
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("The webhook signature is missing or invalid")]
+    InvalidSignature(#[source] anyhow::Error),
+    #[error("The webhook payload could not be parsed")]
+    InvalidPayload(#[source] serde_json::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            Self::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+#[tracing::instrument(
+    name = "Receiving an email provider event",
+    skip(body, request, pool, secret),
+    fields(
+        event_type = tracing::field::Empty,
+    )
+)]
+pub async fn email_provider_webhook(
+    body: web::Bytes,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    secret: web::Data<WebhookSecret>,
+) -> Result<HttpResponse, WebhookError> {
+    verify_signature(&request, &body, &secret)
+        .map_err(WebhookError::InvalidSignature)?;
+    let event: PostmarkEvent = serde_json::from_slice(&body)
+        .map_err(WebhookError::InvalidPayload)?;
+
+    let (event_type, email, description) = match event {
+        PostmarkEvent::Bounce(bounce) => (
+            EmailEventType::from_bounce_type(&bounce.bounce_type),
+            bounce.email,
+            bounce.description,
+        ),
+        PostmarkEvent::SpamComplaint(complaint) => (
+            EmailEventType::SpamComplaint,
+            complaint.email,
+            complaint.description,
+        ),
+        PostmarkEvent::Other => return Ok(HttpResponse::Ok().finish()),
+    };
+    tracing::Span::current().record("event_type", tracing::field::display(event_type.as_str()));
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    let subscriber_id = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1)"#,
+        email,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to look up the subscriber of an email event")?
+    .map(|r| r.id);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (id, subscriber_id, email, event_type, description, received_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        email,
+        event_type.as_str(),
+        description,
+        Utc::now(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store an email event")?;
+
+    if let (Some(subscriber_id), Some(status)) = (subscriber_id, event_type.subscriber_status()) {
+        sqlx::query!(
+            r#"UPDATE subscriptions SET status = $1 WHERE id = $2"#,
+            status,
+            subscriber_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to update the subscriber status after an email event")?;
+        delete_subscription_tokens(&mut transaction, subscriber_id).await
+            .context("Failed to invalidate the confirmation links after an email event")?;
+    }
+
+    if event_type.subscriber_status().is_some() {
+        match NewSuppression::parse(SuppressionKind::Address, &email) {
+            Ok(suppression) => {
+                add_suppression(&mut *transaction, &suppression, event_type.as_str(), "webhook")
+                    .await
+                    .context("Failed to suppress an address after an email event")?;
+            }
+            Err(e) => tracing::warn!(error = %e, "Not suppressing an invalid address"),
+        }
+    }
+
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to store an email event")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// The provider signs the raw request body with HMAC-SHA256 using the shared
+/// secret and sends the hex-encoded tag in the `X-Webhook-Signature` header.
+fn verify_signature(
+    request: &HttpRequest,
+    body: &[u8],
+    secret: &WebhookSecret,
+) -> Result<(), anyhow::Error> {
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .context("Missing signature header")?
+        .to_str()
+        .context("The signature header is not a valid UTF-8 string")?;
+    let signature = hex::decode(signature)
+        .context("The signature is not hex-encoded")?;
+
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+        .context("Invalid webhook secret")?;
+    mac.update(body);
+    mac.verify_slice(&signature)
+        .context("The signature does not match the payload")?;
+    Ok(())
+}
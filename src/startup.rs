@@ -1,11 +1,12 @@
 //! src/startup.rs
 
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
+use anyhow::Context;
 use actix_web::{
     cookie::Key, dev::Server, web, App, HttpServer
 };
 use crate::{
-    configurations::{DatabaseSettings, Settings}, 
+    configurations::{DatabaseSettings, EmailClientSettings, Settings, SubscriberRedirects}, 
     routes::{
         admin_dashboard, 
         confirm, 
@@ -14,15 +15,64 @@ use crate::{
         login_form, 
         publish_newsletter, 
         subscribe,
+        subscription_form_stamp,
+        resend_confirmation,
+        data_request_form,
+        request_subscriber_data,
+        confirm_data_request_form,
+        confirm_data_request,
+        data_requests_form,
+        admin_export_subscriber_data,
+        admin_erase_subscriber_data,
+        consent_history,
+        import_subscribers_form,
+        preview_subscriber_import,
+        confirm_subscriber_import,
+        export_form,
+        export_subscribers,
+        subscriber_list,
+        subscriber_details,
+        add_subscriber,
+        rename_subscriber,
+        force_confirm_subscriber,
+        admin_unsubscribe_subscriber,
+        delete_subscriber,
+        subscriber_attributes,
+        add_subscriber_attribute,
+        delete_subscriber_attribute,
+        resend_confirmation_form,
         change_password,
         change_password_form,
         log_out,
+        email_provider_webhook,
+        unsubscribe,
+        unsubscribe_form,
+        preferences_form,
+        update_preferences,
+        suppression_list,
+        add_to_suppression_list,
+        remove_from_suppression_list,
+        import_suppression_list,
+        email_templates,
+        edit_email_template_form,
+        save_email_template,
+        reset_email_template,
+        newsletter_lists,
+        add_newsletter_list,
+        email_domain_rules,
+        set_email_domain_rule,
+        remove_email_domain_rule,
     },
     authentication::reject_anonymous_users,
 };
 use sqlx::{postgres::PgPoolOptions, PgPool};
 use tracing_actix_web::TracingLogger;
 use crate::email_client::EmailClient;
+use crate::suppression::{normalize_stored_suppressions, SuppressionList};
+use crate::subscribers::normalize_stored_emails;
+use crate::domain::OptInMode;
+use crate::bot_protection::BotProtection;
+use crate::domain_policy::DomainPolicy;
 use secrecy::{Secret, ExposeSecret};
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
 use actix_session::{storage::RedisSessionStore, SessionMiddleware};
@@ -35,18 +85,17 @@ pub struct Application {
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {   // why the build func is an async func??
-        let sender_email = configuration.email_client.sender().expect("Invalid sender email");
-        
-        let timeout = configuration.email_client.timeout();
-        
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url, 
-            sender_email, 
-            configuration.email_client.authorization_token, 
-            timeout
-        );
-        
+        // the signer is only used by the file backend, it is built here
+        // anyway so a broken key stops a deploy instead of the first send
+        if let Some(dkim) = &configuration.email_client.dkim {
+            dkim.signer().context("Invalid DKIM settings")?;
+        }
         let connection_pool = get_connection_pool(&configuration.database);
+        // lookups by address would miss the rows that are not converted yet
+        normalize_stored_addresses(&connection_pool).await
+            .context("Failed to normalize the stored addresses")?;
+
+        let email_client = get_email_client(configuration.email_client.clone(), &connection_pool);
         
         let addr = format!(
             "{}:{}", 
@@ -57,13 +106,23 @@ impl Application {
         let listener = TcpListener::bind(addr)?;
 
         let port = listener.local_addr().unwrap().port();
+
+        let subscription_token_ttl = configuration.application.subscription_token_ttl();
+        let domain_policy = DomainPolicy::new(connection_pool.clone(), &configuration.email_domain_policy)?;
         
         let server = run(
             listener, 
             connection_pool, 
             email_client,
             configuration.application.base_url,
+            subscription_token_ttl,
+            configuration.application.opt_in_mode,
+            configuration.application.redirects,
+            configuration.application.trusted_proxies,
+            BotProtection::new(&configuration.bot_protection),
+            domain_policy,
             configuration.application.hmac_secret,
+            configuration.email_client.webhook_secret,
             configuration.redis_uri
         ).await?;
 
@@ -79,6 +138,53 @@ impl Application {
     }
 }
 
+/// Converts what was stored before addresses were normalized. It needs the
+/// application's own IDNA mapping, so it runs before the server binds rather
+/// than in a migration, and has nothing left to do after the first time.
+pub async fn normalize_stored_addresses(pool: &PgPool) -> Result<(), anyhow::Error> {
+    let emails_converted = normalize_stored_emails(pool).await?;
+    if emails_converted > 0 {
+        tracing::info!(emails_converted, "Converted stored subscriber addresses to punycode");
+    }
+    let suppressions_converted = normalize_stored_suppressions(pool).await?;
+    if suppressions_converted > 0 {
+        tracing::info!(suppressions_converted, "Converted stored suppressions to punycode");
+    }
+    Ok(())
+}
+
+/// Shared by the API and the background workers.
+pub fn get_email_client(configuration: EmailClientSettings, pool: &PgPool) -> EmailClient {
+    let sender_email = configuration.sender().expect("Invalid sender email");
+    
+    let timeout = configuration.timeout();
+    
+    let mut email_client = EmailClient::new(
+        configuration.base_url, 
+        sender_email, 
+        configuration.authorization_token, 
+        timeout
+    )
+    .with_circuit_breaker(
+        configuration.circuit_breaker.failure_threshold,
+        configuration.circuit_breaker.cooldown(),
+    )
+    .with_suppression_list(SuppressionList::new(pool.clone()));
+    for provider in configuration.fallback_providers {
+        email_client = email_client.with_fallback_provider(
+            provider.base_url,
+            provider.authorization_token,
+        );
+    }
+    if let Some(directory) = configuration.file_backend_directory {
+        let dkim_signer = configuration
+            .dkim
+            .map(|dkim| dkim.signer().expect("Invalid DKIM settings"));
+        email_client = email_client.with_file_backend(directory.into(), dkim_signer);
+    }
+    email_client
+}
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
         .acquire_timeout(std::time::Duration::from_secs(2))
@@ -87,21 +193,43 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub String);
 
-// #[derive(Clone)]
-// pub struct HmacSecret(pub Secret<String>);
+pub struct WebhookSecret(pub Secret<String>);
+
+pub struct SubscriptionTokenTtl(pub chrono::Duration);
+
+pub struct HmacSecret(pub Secret<String>);
+
+pub struct DefaultOptInMode(pub OptInMode);
+
+pub struct TrustedProxies(pub Vec<IpAddr>);
 
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener, 
     db_pool: PgPool, 
     email_client: EmailClient, 
     base_url: String,
+    subscription_token_ttl: chrono::Duration,
+    default_opt_in_mode: OptInMode,
+    redirects: SubscriberRedirects,
+    trusted_proxies: Vec<IpAddr>,
+    bot_protection: BotProtection,
+    domain_policy: DomainPolicy,
     hmac_secret: Secret<String>,
+    webhook_secret: Secret<String>,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     // 此处使用智能指针（计数指针Arc）包装connection，这使得原本不具有clone trait的PgPool（PgConnection）类型通过Arc计数指针实现可克隆性质，每次克隆使得Arc计数+1
     let db_pool = web::Data::new(db_pool);    
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let webhook_secret = web::Data::new(WebhookSecret(webhook_secret));
+    let subscription_token_ttl = web::Data::new(SubscriptionTokenTtl(subscription_token_ttl));
+    let default_opt_in_mode = web::Data::new(DefaultOptInMode(default_opt_in_mode));
+    let redirects = web::Data::new(redirects);
+    let trusted_proxies = web::Data::new(TrustedProxies(trusted_proxies));
+    let bot_protection = web::Data::new(bot_protection);
+    let domain_policy = web::Data::new(domain_policy);
     // 此处 HttpServer::new(|| {...}) 中使用闭包进行参数传递，|...| 表示闭包的参数列表，该处没有传入闭包的参数，故参数列表为空（ || )，
     // {...}表示闭包的实现体，包含闭包的执行逻辑，该闭包返回一个配置了路由的App实例
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
@@ -121,23 +249,74 @@ async fn run(
             // 并通过web::get().to(greet)将对该路由的http get请求映射到处理函数greet实现参数传递，该参数传递由actix web通过函数签名自动推断完成，因此greet也没有显式的参数列表
             .route("/health_check", web::get().to(health_check))
             .route("/subscriptions", web::post().to(subscribe))
+            .route("/subscriptions/form_stamp", web::get().to(subscription_form_stamp))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/resend", web::get().to(resend_confirmation_form))
+            .route("/subscriptions/resend", web::post().to(resend_confirmation))
+            .route("/subscriptions/data", web::get().to(data_request_form))
+            .route("/subscriptions/data", web::post().to(request_subscriber_data))
+            .route("/subscriptions/data/confirm", web::get().to(confirm_data_request_form))
+            .route("/subscriptions/data/confirm", web::post().to(confirm_data_request))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe_form))
+            .route("/subscriptions/unsubscribe", web::post().to(unsubscribe))
+            .route("/subscriptions/preferences", web::get().to(preferences_form))
+            .route("/subscriptions/preferences", web::post().to(update_preferences))
             .route("/newsletters", web::post().to(publish_newsletter))
+            .route("/webhooks/postmark", web::post().to(email_provider_webhook))
             .route("/", web::get().to(home))
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .service(web::scope("/admin")
                 .wrap(from_fn(reject_anonymous_users))
                 .route("/dashboard", web::get().to(admin_dashboard))
+                .route("/subscribers", web::get().to(subscriber_list))
+                .route("/subscribers", web::post().to(add_subscriber))
+                .route("/subscribers/{subscriber_id}", web::get().to(subscriber_details))
+                .route("/subscribers/{subscriber_id}/name", web::post().to(rename_subscriber))
+                .route("/subscribers/{subscriber_id}/confirm", web::post().to(force_confirm_subscriber))
+                .route("/subscribers/{subscriber_id}/unsubscribe", web::post().to(admin_unsubscribe_subscriber))
+                .route("/subscribers/{subscriber_id}/delete", web::post().to(delete_subscriber))
+                .route("/attributes", web::get().to(subscriber_attributes))
+                .route("/attributes", web::post().to(add_subscriber_attribute))
+                .route("/attributes/{key}/delete", web::post().to(delete_subscriber_attribute))
                 .route("/password", web::get().to(change_password_form))
                 .route("/password", web::post().to(change_password))
-                .route("/logout", web::post().to(log_out)),
+                .route("/logout", web::post().to(log_out))
+                .route("/suppressions", web::get().to(suppression_list))
+                .route("/suppressions", web::post().to(add_to_suppression_list))
+                .route("/suppressions/remove", web::post().to(remove_from_suppression_list))
+                .route("/suppressions/import", web::post().to(import_suppression_list))
+                .route("/templates", web::get().to(email_templates))
+                .route("/templates/{name}", web::get().to(edit_email_template_form))
+                .route("/templates/{name}", web::post().to(save_email_template))
+                .route("/templates/{name}/reset", web::post().to(reset_email_template))
+                .route("/lists", web::get().to(newsletter_lists))
+                .route("/lists", web::post().to(add_newsletter_list))
+                .route("/domains", web::get().to(email_domain_rules))
+                .route("/domains", web::post().to(set_email_domain_rule))
+                .route("/domains/remove", web::post().to(remove_email_domain_rule))
+                .route("/data-requests", web::get().to(data_requests_form))
+                .route("/data-requests/export", web::post().to(admin_export_subscriber_data))
+                .route("/data-requests/erase", web::post().to(admin_erase_subscriber_data))
+                .route("/consent", web::get().to(consent_history))
+                .route("/import", web::get().to(import_subscribers_form))
+                .route("/import", web::post().to(preview_subscriber_import))
+                .route("/import/{import_id}", web::post().to(confirm_subscriber_import))
+                .route("/export", web::get().to(export_form))
+                .route("/export/subscribers", web::get().to(export_subscribers)),
             )          
             // 使用app_data方法将PgPool(PgConnection)连接对象注册为该App实例的一部分，这里使用Arc实现clone trait，以使连接对每一个App实例可克隆
             .app_data(db_pool.clone())    
             .app_data(email_client.clone())
             .app_data(base_url.clone())
-            .app_data(web::Data::new(hmac_secret.clone()))
+            .app_data(webhook_secret.clone())
+            .app_data(subscription_token_ttl.clone())
+            .app_data(default_opt_in_mode.clone())
+            .app_data(redirects.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(bot_protection.clone())
+            .app_data(domain_policy.clone())
+            .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })    
     .listen(listener)?    
     // 此处 ? 运算的对象是由bind函数运行返回的 Result<Self> 即 Result<HttpServer, E>，绑定成功则 Result<Self> 会是 Ok(HttpServer)，则该链式调用继续执行run方法；
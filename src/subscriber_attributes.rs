@@ -0,0 +1,567 @@
+//! src/subscriber_attributes.rs
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, Utc};
+use serde_json::{Map, Value};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const MAX_KEY_LENGTH: usize = 40;
+const MAX_LABEL_LENGTH: usize = 100;
+pub const MAX_TEXT_LENGTH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeKind {
+    Text,
+    Number,
+    Boolean,
+    /// One of the options of the definition.
+    Enum,
+    /// `YYYY-MM-DD`.
+    Date,
+}
+
+impl AttributeKind {
+    pub const ALL: [AttributeKind; 5] = [
+        AttributeKind::Text,
+        AttributeKind::Number,
+        AttributeKind::Boolean,
+        AttributeKind::Enum,
+        AttributeKind::Date,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttributeKind::Text => "text",
+            AttributeKind::Number => "number",
+            AttributeKind::Boolean => "boolean",
+            AttributeKind::Enum => "enum",
+            AttributeKind::Date => "date",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AttributeKind::Text => "Text",
+            AttributeKind::Number => "Number",
+            AttributeKind::Boolean => "Yes/no",
+            AttributeKind::Enum => "One of a list",
+            AttributeKind::Date => "Date",
+        }
+    }
+}
+
+impl TryFrom<&str> for AttributeKind {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == value)
+            .ok_or_else(|| format!("{} is not a supported attribute type.", value))
+    }
+}
+
+impl TryFrom<String> for AttributeKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::try_from(value.as_str())
+    }
+}
+
+/// An extra field subscribers fill in, stored in the `attributes` of their
+/// subscription under `key`.
+#[derive(Debug, Clone)]
+pub struct AttributeDefinition {
+    pub key: String,
+    pub label: String,
+    pub kind: AttributeKind,
+    /// A required boolean has to be checked, e.g. to accept terms.
+    pub required: bool,
+    pub options: Vec<String>,
+}
+
+impl AttributeDefinition {
+    /// Checks a definition entered by an admin. `options` is comma-separated
+    /// and only kept for enum attributes.
+    pub fn parse(key: &str, label: &str, kind: &str, required: bool, options: &str) -> Result<Self, String> {
+        let key = key.trim();
+        let valid_key = key.chars().next().is_some_and(|c| c.is_ascii_lowercase())
+            && key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            && key.len() <= MAX_KEY_LENGTH;
+        if !valid_key {
+            return Err(format!(
+                "{} is not a valid key, use up to {} lowercase letters, digits and underscores, starting with a letter.",
+                key, MAX_KEY_LENGTH,
+            ));
+        }
+        let label = label.trim();
+        if label.is_empty() || label.chars().count() > MAX_LABEL_LENGTH {
+            return Err(format!("The label must be between 1 and {} characters long.", MAX_LABEL_LENGTH));
+        }
+        let kind = AttributeKind::try_from(kind)?;
+        let mut parsed_options: Vec<String> = vec![];
+        if kind == AttributeKind::Enum {
+            for option in options.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+                if !parsed_options.iter().any(|o| o == option) {
+                    parsed_options.push(option.to_owned());
+                }
+            }
+            if parsed_options.is_empty() {
+                return Err("A list attribute needs at least one option.".into());
+            }
+        }
+        Ok(Self { key: key.to_owned(), label: label.to_owned(), kind, required, options: parsed_options })
+    }
+
+    /// Converts a submitted value to the stored one, `None` when it is left
+    /// empty. Form submissions send strings only, so those are accepted for
+    /// every type.
+    pub fn parse_value(&self, value: &Value) -> Result<Option<Value>, String> {
+        if value.is_null() || value.as_str().is_some_and(|s| s.trim().is_empty()) {
+            return Ok(None);
+        }
+        let text = value.as_str().map(str::trim);
+        let parsed = match (self.kind, text) {
+            (AttributeKind::Text, Some(text)) if text.chars().count() <= MAX_TEXT_LENGTH => Value::from(text),
+            (AttributeKind::Text, Some(_)) => {
+                return Err(format!("{} must be at most {} characters long.", self.label, MAX_TEXT_LENGTH));
+            }
+            (AttributeKind::Number, None) if value.is_number() => value.clone(),
+            (AttributeKind::Number, Some(text)) => match text.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => text
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(Value::Number)
+                    .ok_or_else(|| format!("{} must be a number.", self.label))?,
+            },
+            (AttributeKind::Boolean, None) if value.is_boolean() => value.clone(),
+            (AttributeKind::Boolean, Some(text)) => match text.to_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => Value::from(true),
+                "false" | "off" | "no" | "0" => Value::from(false),
+                _ => return Err(format!("{} must be yes or no.", self.label)),
+            },
+            (AttributeKind::Enum, Some(text)) if self.options.iter().any(|o| o == text) => Value::from(text),
+            (AttributeKind::Enum, Some(_)) => {
+                return Err(format!("{} must be one of: {}.", self.label, self.options.join(", ")));
+            }
+            (AttributeKind::Date, Some(text)) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .map(|d| Value::from(d.format("%Y-%m-%d").to_string()))
+                .map_err(|_| format!("{} must be a date, use YYYY-MM-DD.", self.label))?,
+            (AttributeKind::Number, None) => return Err(format!("{} must be a number.", self.label)),
+            (AttributeKind::Boolean, None) => return Err(format!("{} must be yes or no.", self.label)),
+            (_, None) => return Err(format!("{} must be text.", self.label)),
+        };
+        Ok(Some(parsed))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AttributeError {
+    pub key: String,
+    pub message: String,
+}
+
+/// Validates submitted attributes against the definitions, reporting every
+/// problem at once. An unchecked checkbox is not submitted, so a missing
+/// boolean is stored as `false`.
+pub fn validate_attributes(
+    definitions: &[AttributeDefinition],
+    submitted: &HashMap<String, Value>,
+) -> Result<Map<String, Value>, Vec<AttributeError>> {
+    let mut errors = vec![];
+    let mut attributes = Map::new();
+    for key in submitted.keys() {
+        if !definitions.iter().any(|d| &d.key == key) {
+            errors.push(AttributeError { key: key.clone(), message: format!("{} is not a known attribute.", key) });
+        }
+    }
+    for definition in definitions {
+        let value = match submitted.get(&definition.key).map(|v| definition.parse_value(v)).transpose() {
+            Ok(value) => value.flatten(),
+            Err(message) => {
+                errors.push(AttributeError { key: definition.key.clone(), message });
+                continue;
+            }
+        };
+        let value = match (value, definition.kind) {
+            (None, AttributeKind::Boolean) => Some(Value::from(false)),
+            (value, _) => value,
+        };
+        let missing = match &value {
+            None => true,
+            Some(value) => definition.kind == AttributeKind::Boolean && value == &Value::from(false),
+        };
+        if definition.required && missing {
+            errors.push(AttributeError {
+                key: definition.key.clone(),
+                message: format!("{} is required.", definition.label),
+            });
+            continue;
+        }
+        if let Some(value) = value {
+            attributes.insert(definition.key.clone(), value);
+        }
+    }
+    if errors.is_empty() {
+        Ok(attributes)
+    } else {
+        Err(errors)
+    }
+}
+
+/// How an attribute value is shown in emails and admin pages.
+pub fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Bool(true) => "yes".into(),
+        Value::Bool(false) => "no".into(),
+        other => other.to_string(),
+    }
+}
+
+/// The `{{attributes.<key>}}` placeholders of a newsletter, empty for the
+/// attributes a subscriber did not fill in.
+pub fn personalization_variables(
+    definitions: &[AttributeDefinition],
+    attributes: &Map<String, Value>,
+) -> Vec<(String, String)> {
+    definitions
+        .iter()
+        .map(|d| {
+            let value = attributes.get(&d.key).map(display_value).unwrap_or_default();
+            (format!("attributes.{}", d.key), value)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentOperator {
+    Equals,
+    NotEquals,
+    /// Case-insensitive, text attributes only.
+    Contains,
+    /// Numbers and dates only.
+    GreaterThan,
+    LessThan,
+    IsSet,
+    IsNotSet,
+}
+
+/// A condition on an attribute as sent with a newsletter.
+#[derive(Debug, serde::Deserialize)]
+pub struct SegmentCondition {
+    pub attribute: String,
+    pub operator: SegmentOperator,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug)]
+struct ParsedCondition {
+    key: String,
+    kind: AttributeKind,
+    operator: SegmentOperator,
+    value: Option<Value>,
+}
+
+/// The subscribers matching all of its conditions.
+#[derive(Debug)]
+pub struct Segment {
+    conditions: Vec<ParsedCondition>,
+}
+
+impl Segment {
+    pub fn parse(definitions: &[AttributeDefinition], conditions: Vec<SegmentCondition>) -> Result<Self, String> {
+        let conditions = conditions
+            .into_iter()
+            .map(|c| {
+                let definition = definitions
+                    .iter()
+                    .find(|d| d.key == c.attribute)
+                    .ok_or_else(|| format!("{} is not a known attribute.", c.attribute))?;
+                let supported = match c.operator {
+                    SegmentOperator::Contains => definition.kind == AttributeKind::Text,
+                    SegmentOperator::GreaterThan | SegmentOperator::LessThan => {
+                        matches!(definition.kind, AttributeKind::Number | AttributeKind::Date)
+                    }
+                    _ => true,
+                };
+                if !supported {
+                    return Err(format!(
+                        "{:?} cannot be used with {}, a {} attribute.",
+                        c.operator, c.attribute, definition.kind.as_str(),
+                    ));
+                }
+                let value = match c.operator {
+                    SegmentOperator::IsSet | SegmentOperator::IsNotSet => None,
+                    _ => Some(
+                        c.value
+                            .as_ref()
+                            .map(|v| definition.parse_value(v))
+                            .transpose()?
+                            .flatten()
+                            .ok_or_else(|| format!("The condition on {} needs a value.", c.attribute))?,
+                    ),
+                };
+                Ok(ParsedCondition { key: c.attribute, kind: definition.kind, operator: c.operator, value })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self { conditions })
+    }
+
+    pub fn matches(&self, attributes: &Map<String, Value>) -> bool {
+        self.conditions.iter().all(|c| {
+            let actual = attributes.get(&c.key);
+            let (actual, expected) = match (c.operator, actual, &c.value) {
+                (SegmentOperator::IsSet, actual, _) => return actual.is_some(),
+                (SegmentOperator::IsNotSet, actual, _) => return actual.is_none(),
+                // somebody without the attribute does not have that value either
+                (SegmentOperator::NotEquals, None, _) => return true,
+                (_, Some(actual), Some(expected)) => (actual, expected),
+                _ => return false,
+            };
+            match c.operator {
+                SegmentOperator::Equals => values_equal(actual, expected),
+                SegmentOperator::NotEquals => !values_equal(actual, expected),
+                SegmentOperator::Contains => match (actual.as_str(), expected.as_str()) {
+                    (Some(actual), Some(expected)) => actual.to_lowercase().contains(&expected.to_lowercase()),
+                    _ => false,
+                },
+                SegmentOperator::GreaterThan => compare(c.kind, actual, expected) == Some(std::cmp::Ordering::Greater),
+                SegmentOperator::LessThan => compare(c.kind, actual, expected) == Some(std::cmp::Ordering::Less),
+                SegmentOperator::IsSet | SegmentOperator::IsNotSet => unreachable!(),
+            }
+        })
+    }
+}
+
+fn values_equal(actual: &Value, expected: &Value) -> bool {
+    match (actual.as_f64(), expected.as_f64()) {
+        // 3 and 3.0 are the same number
+        (Some(actual), Some(expected)) => actual == expected,
+        _ => actual == expected,
+    }
+}
+
+fn compare(kind: AttributeKind, actual: &Value, expected: &Value) -> Option<std::cmp::Ordering> {
+    match kind {
+        AttributeKind::Number => actual.as_f64()?.partial_cmp(&expected.as_f64()?),
+        // dates are stored as YYYY-MM-DD, which sorts chronologically
+        AttributeKind::Date => Some(actual.as_str()?.cmp(expected.as_str()?)),
+        _ => None,
+    }
+}
+
+#[tracing::instrument(name = "Get the subscriber attribute definitions", skip(pool))]
+pub async fn get_attribute_definitions(pool: &PgPool) -> Result<Vec<AttributeDefinition>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT key, label, kind, required, options
+        FROM subscriber_attributes
+        ORDER BY created_at, key
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(AttributeDefinition {
+                key: row.key,
+                label: row.label,
+                kind: AttributeKind::try_from(row.kind).map_err(anyhow::Error::msg)?,
+                required: row.required,
+                options: row.options,
+            })
+        })
+        .collect()
+}
+
+/// Returns `false` if there already is an attribute with the same key.
+#[tracing::instrument(name = "Adding a subscriber attribute definition", skip(pool))]
+pub async fn add_attribute_definition(pool: &PgPool, definition: &AttributeDefinition) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attributes (key, label, kind, required, options, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        definition.key,
+        definition.label,
+        definition.kind.as_str(),
+        definition.required,
+        &definition.options,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(inserted == 1)
+}
+
+/// Deletes the definition together with the values subscribers gave for it.
+/// Returns `false` if there is no such attribute.
+#[tracing::instrument(name = "Deleting a subscriber attribute definition", skip(pool))]
+pub async fn delete_attribute_definition(pool: &PgPool, key: &str) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let deleted = sqlx::query!(r#"DELETE FROM subscriber_attributes WHERE key = $1"#, key)
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = attributes - $1 WHERE attributes ? $1"#,
+        key,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(deleted == 1)
+}
+
+/// Stores the attributes a subscriber submitted, keeping the ones they left out.
+#[tracing::instrument(name = "Saving the attributes of a subscriber", skip(transaction, attributes))]
+pub async fn save_subscriber_attributes(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    attributes: &Map<String, Value>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET attributes = attributes || $2 WHERE id = $1"#,
+        subscriber_id,
+        Value::Object(attributes.clone()),
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscriber_attributes::{
+        validate_attributes, AttributeDefinition, AttributeKind, Segment, SegmentCondition, SegmentOperator,
+    };
+    use serde_json::{json, Map, Value};
+    use std::collections::HashMap;
+
+    fn definitions() -> Vec<AttributeDefinition> {
+        vec![
+            AttributeDefinition::parse("company", "Company", "text", false, "").unwrap(),
+            AttributeDefinition::parse("employees", "Employees", "number", false, "").unwrap(),
+            AttributeDefinition::parse("country", "Country", "enum", true, "DE, FR,DE").unwrap(),
+            AttributeDefinition::parse("beta", "Beta tester", "boolean", false, "").unwrap(),
+            AttributeDefinition::parse("customer_since", "Customer since", "date", false, "").unwrap(),
+        ]
+    }
+
+    fn submitted(values: Value) -> HashMap<String, Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    fn attributes(values: Value) -> Map<String, Value> {
+        serde_json::from_value(values).unwrap()
+    }
+
+    #[test]
+    fn definitions_are_checked() {
+        let country = AttributeDefinition::parse(" country ", " Country ", "enum", true, "DE, FR,DE,").unwrap();
+
+        assert_eq!(country.key, "country");
+        assert_eq!(country.kind, AttributeKind::Enum);
+        assert_eq!(country.options, vec!["DE", "FR"]);
+        assert!(AttributeDefinition::parse("Country", "Country", "text", false, "").is_err());
+        assert!(AttributeDefinition::parse("1st", "First", "text", false, "").is_err());
+        assert!(AttributeDefinition::parse("country", "", "text", false, "").is_err());
+        assert!(AttributeDefinition::parse("country", "Country", "enum", false, " , ").is_err());
+        assert!(AttributeDefinition::parse("country", "Country", "list", false, "").is_err());
+    }
+
+    #[test]
+    fn submitted_strings_are_converted_to_their_type() {
+        let values = submitted(json!({
+            "company": " Acme ",
+            "employees": "12",
+            "country": "FR",
+            "beta": "on",
+            "customer_since": "2024-03-01",
+        }));
+
+        let attributes = validate_attributes(&definitions(), &values).unwrap();
+
+        assert_eq!(
+            Value::Object(attributes),
+            json!({"company": "Acme", "employees": 12, "country": "FR", "beta": true, "customer_since": "2024-03-01"}),
+        );
+    }
+
+    #[test]
+    fn json_values_are_accepted_and_empty_ones_left_out() {
+        let values = submitted(json!({"company": "", "employees": 2.5, "country": "DE"}));
+
+        let attributes = validate_attributes(&definitions(), &values).unwrap();
+
+        assert_eq!(Value::Object(attributes), json!({"employees": 2.5, "country": "DE", "beta": false}));
+    }
+
+    #[test]
+    fn every_invalid_attribute_is_reported() {
+        let values = submitted(json!({
+            "employees": "a dozen",
+            "beta": "maybe",
+            "customer_since": "01/03/2024",
+            "shoe_size": "44",
+        }));
+
+        let errors = validate_attributes(&definitions(), &values).unwrap_err();
+
+        let mut keys: Vec<_> = errors.iter().map(|e| e.key.as_str()).collect();
+        keys.sort();
+        assert_eq!(keys, vec!["beta", "country", "customer_since", "employees", "shoe_size"]);
+    }
+
+    #[test]
+    fn segments_match_all_their_conditions() {
+        let conditions: Vec<SegmentCondition> = serde_json::from_value(json!([
+            {"attribute": "country", "operator": "equals", "value": "DE"},
+            {"attribute": "employees", "operator": "greater_than", "value": 10},
+            {"attribute": "company", "operator": "contains", "value": "acme"},
+        ]))
+        .unwrap();
+        let segment = Segment::parse(&definitions(), conditions).unwrap();
+
+        assert!(segment.matches(&attributes(json!({"country": "DE", "employees": 12, "company": "ACME Corp"}))));
+        assert!(!segment.matches(&attributes(json!({"country": "DE", "employees": 10, "company": "ACME Corp"}))));
+        assert!(!segment.matches(&attributes(json!({"country": "FR", "employees": 12, "company": "ACME Corp"}))));
+        assert!(!segment.matches(&attributes(json!({"country": "DE", "employees": 12}))));
+    }
+
+    #[test]
+    fn missing_attributes_only_match_negative_conditions() {
+        let segment = |operator, value: Option<Value>| {
+            Segment::parse(&definitions(), vec![SegmentCondition { attribute: "customer_since".into(), operator, value }])
+                .unwrap()
+        };
+        let without = attributes(json!({}));
+
+        assert!(segment(SegmentOperator::NotEquals, Some(json!("2024-03-01"))).matches(&without));
+        assert!(segment(SegmentOperator::IsNotSet, None).matches(&without));
+        assert!(!segment(SegmentOperator::IsSet, None).matches(&without));
+        assert!(!segment(SegmentOperator::LessThan, Some(json!("2024-03-01"))).matches(&without));
+        assert!(segment(SegmentOperator::LessThan, Some(json!("2024-03-01")))
+            .matches(&attributes(json!({"customer_since": "2023-12-31"}))));
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        let parse = |condition: Value| Segment::parse(&definitions(), vec![serde_json::from_value(condition).unwrap()]);
+
+        assert!(parse(json!({"attribute": "shoe_size", "operator": "equals", "value": "44"})).is_err());
+        assert!(parse(json!({"attribute": "country", "operator": "greater_than", "value": "DE"})).is_err());
+        assert!(parse(json!({"attribute": "country", "operator": "equals", "value": "IT"})).is_err());
+        assert!(parse(json!({"attribute": "employees", "operator": "equals"})).is_err());
+        assert!(parse(json!({"attribute": "company", "operator": "is_set"})).is_ok());
+    }
+}
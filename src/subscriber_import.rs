@@ -0,0 +1,427 @@
+//! src/subscriber_import.rs
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::consent::{record_consent_event, ConsentContext, ConsentEvent, ConsentSource};
+use crate::domain::{Locale, NewSubscriber, OptInMode, SubscriberEmail, SubscriberName};
+use crate::domain_policy::{DomainPolicy, DomainPolicyError};
+use crate::email_client::EmailClient;
+use crate::newsletter_lists::{restrict_to_list, NewsletterList};
+use crate::routes::{generate_subscription_token, send_confirmation_email, send_welcome_email, store_token};
+
+/// The status a row is imported with, `pending` (the default) means there is
+/// no prior consent and the address still has to be confirmed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportStatus {
+    Pending,
+    Confirmed,
+    Unsubscribed,
+}
+
+impl ImportStatus {
+    /// The value stored in `subscriptions.status`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportStatus::Pending => "pending_confirmation",
+            ImportStatus::Confirmed => "confirmed",
+            ImportStatus::Unsubscribed => "unsubscribed",
+        }
+    }
+}
+
+impl TryFrom<&str> for ImportStatus {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_lowercase().as_str() {
+            "" | "pending" | "pending_confirmation" => Ok(Self::Pending),
+            "confirmed" | "subscribed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            other => Err(format!(
+                "{} is not a supported status. Use 'pending', 'confirmed' or 'unsubscribed'.",
+                other,
+            )),
+        }
+    }
+}
+
+pub struct ImportRow {
+    /// Line in the uploaded file, for the summary.
+    pub line: u64,
+    pub subscriber: NewSubscriber,
+    pub status: ImportStatus,
+    pub tags: Vec<String>,
+}
+
+pub struct RejectedRow {
+    pub line: u64,
+    pub email: String,
+    pub reason: String,
+}
+
+/// The dry run of an import: nothing is stored until the admin confirms it.
+#[derive(Default)]
+pub struct ImportPreview {
+    pub valid: Vec<ImportRow>,
+    pub invalid: Vec<RejectedRow>,
+    pub duplicates: Vec<RejectedRow>,
+}
+
+/// An uploaded file waiting for the admin to confirm its dry run.
+pub struct StoredImport {
+    pub csv: String,
+    pub send_confirmations: bool,
+    pub list_key: Option<String>,
+    pub imported_at: Option<DateTime<Utc>>,
+}
+
+pub struct ImportOutcome {
+    pub imported: usize,
+    /// Rows subscribed by somebody else since the dry run.
+    pub skipped: usize,
+    pub confirmations_sent: usize,
+    pub confirmations_failed: usize,
+    pub welcomes_sent: usize,
+    pub welcomes_failed: usize,
+}
+
+/// Parses a CSV with a header row. `email` and `name` columns are required,
+/// `tags` (separated by `;`) and `status` are optional, other columns are
+/// ignored. Fails only if the file cannot be read as CSV at all.
+pub fn parse_import(csv: &str) -> Result<ImportPreview, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("The file is not a valid CSV file: {}", e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let (email_column, name_column) = match (column("email"), column("name")) {
+        (Some(email), Some(name)) => (email, name),
+        _ => return Err("The header row must have an 'email' and a 'name' column.".into()),
+    };
+    let (tags_column, status_column) = (column("tags"), column("status"));
+
+    let mut preview = ImportPreview::default();
+    let mut first_lines: HashMap<String, u64> = HashMap::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("The file is not a valid CSV file: {}", e))?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let field = |column: Option<usize>| column.and_then(|c| record.get(c)).unwrap_or_default();
+        let raw_email = field(Some(email_column)).to_owned();
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+
+        let parsed = SubscriberEmail::parse(raw_email.clone())
+            .and_then(|email| Ok((email, SubscriberName::parse(field(Some(name_column)).to_owned())?)))
+            .and_then(|(email, name)| Ok((email, name, ImportStatus::try_from(field(status_column))?)));
+        let (email, name, status) = match parsed {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                preview.invalid.push(RejectedRow { line, email: raw_email, reason });
+                continue;
+            }
+        };
+        if let Some(first_line) = first_lines.get(&email.as_ref().to_lowercase()) {
+            preview.duplicates.push(RejectedRow {
+                line,
+                email: email.to_string(),
+                reason: format!("Repeats line {}.", first_line),
+            });
+            continue;
+        }
+        first_lines.insert(email.as_ref().to_lowercase(), line);
+        let tags = field(tags_column)
+            .split(';')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+        preview.valid.push(ImportRow {
+            line,
+            subscriber: NewSubscriber { email, name, locale: Locale::default() },
+            status,
+            tags,
+        });
+    }
+    Ok(preview)
+}
+
+/// Moves rows whose address is already known to the duplicates and rows the
+/// email domain policy rejects to the invalid rows.
+#[tracing::instrument(name = "Checking an import against existing subscribers", skip_all)]
+pub async fn check_import(
+    pool: &PgPool,
+    domain_policy: &DomainPolicy,
+    preview: &mut ImportPreview,
+) -> Result<(), anyhow::Error> {
+    let emails: Vec<String> = preview.valid.iter().map(|r| r.subscriber.email.as_ref().to_lowercase()).collect();
+    let existing: HashSet<String> = sqlx::query!(
+        r#"SELECT lower(email) AS "email!" FROM subscriptions WHERE lower(email) = ANY($1)"#,
+        &emails,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to look up existing subscribers")?
+    .into_iter()
+    .map(|r| r.email)
+    .collect();
+
+    // one policy check per domain, an import is usually dominated by a few providers
+    let mut domain_checks: HashMap<String, Option<String>> = HashMap::new();
+    let mut valid = vec![];
+    for row in std::mem::take(&mut preview.valid) {
+        let email = row.subscriber.email.to_string();
+        if existing.contains(&email.to_lowercase()) {
+            preview.duplicates.push(RejectedRow { line: row.line, email, reason: "Already a subscriber.".into() });
+            continue;
+        }
+        let domain = row.subscriber.email.domain().to_lowercase();
+        let rejection = match domain_checks.get(&domain) {
+            Some(rejection) => rejection.clone(),
+            None => {
+                let rejection = match domain_policy.check(&row.subscriber.email).await {
+                    Ok(()) => None,
+                    Err(DomainPolicyError::Rejected(reason)) => Some(reason),
+                    Err(DomainPolicyError::UnexpectedError(e)) => return Err(e),
+                };
+                domain_checks.insert(domain, rejection.clone());
+                rejection
+            }
+        };
+        match rejection {
+            Some(reason) => preview.invalid.push(RejectedRow { line: row.line, email, reason }),
+            None => valid.push(row),
+        }
+    }
+    preview.valid = valid;
+    preview.invalid.sort_by_key(|r| r.line);
+    preview.duplicates.sort_by_key(|r| r.line);
+    Ok(())
+}
+
+#[tracing::instrument(name = "Storing an uploaded import", skip(pool, csv))]
+pub async fn store_import(
+    pool: &PgPool,
+    csv: &str,
+    send_confirmations: bool,
+    list_key: Option<&str>,
+) -> Result<Uuid, sqlx::Error> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_imports (id, csv, send_confirmations, list_key, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        import_id,
+        csv,
+        send_confirmations,
+        list_key,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await?;
+    Ok(import_id)
+}
+
+#[tracing::instrument(name = "Get an uploaded import", skip(pool))]
+pub async fn get_import(pool: &PgPool, import_id: Uuid) -> Result<Option<StoredImport>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredImport,
+        r#"SELECT csv, send_confirmations, list_key, imported_at FROM subscriber_imports WHERE id = $1"#,
+        import_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Deletes the uploads that were never imported and were stored before
+/// `cutoff`, they hold the data of people who are not subscribers.
+#[tracing::instrument(name = "Deleting expired imports", skip(pool))]
+pub async fn delete_expired_imports(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+    let deleted = sqlx::query!(
+        r#"DELETE FROM subscriber_imports WHERE imported_at IS NULL AND created_at < $1"#,
+        cutoff,
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(deleted)
+}
+
+/// Inserts all rows in one transaction, then sends confirmation emails to the
+/// pending ones if asked to. With single opt-in, the list the addresses were
+/// collected for vouches for their consent: rows without a status are
+/// confirmed and welcomed instead. A failed email does not undo the import,
+/// the maintenance job reminds pending subscribers later on; those imported
+/// without a confirmation email are never reminded. Returns `None` if the
+/// upload has already been imported.
+#[tracing::instrument(name = "Importing subscribers", skip(pool, email_client, base_url, rows, list), fields(rows = rows.len(), list = list.map(|l| l.key.as_str())))]
+pub async fn import_subscribers(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    import_id: Uuid,
+    rows: Vec<ImportRow>,
+    send_confirmations: bool,
+    list: Option<&NewsletterList>,
+) -> Result<Option<ImportOutcome>, anyhow::Error> {
+    let opt_in_mode = list.and_then(|l| l.opt_in_mode).unwrap_or(OptInMode::Double);
+    let consent = ConsentContext::without_request(ConsentSource::Import);
+    let mut outcome = ImportOutcome {
+        imported: 0,
+        skipped: 0,
+        confirmations_sent: 0,
+        confirmations_failed: 0,
+        welcomes_sent: 0,
+        welcomes_failed: 0,
+    };
+    let mut confirmations = vec![];
+    let mut welcomes = vec![];
+
+    let mut transaction = pool.begin().await
+        .context("Failed to acquire a pg connection from the pg pool")?;
+    // claiming the upload first makes a double submit import nothing, the
+    // file itself is not needed anymore
+    let claimed = sqlx::query!(
+        r#"UPDATE subscriber_imports SET imported_at = $2, csv = '' WHERE id = $1 AND imported_at IS NULL"#,
+        import_id,
+        Utc::now(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to mark the upload as imported")?
+    .rows_affected();
+    if claimed == 0 {
+        return Ok(None);
+    }
+    for row in rows {
+        let status = match row.status {
+            ImportStatus::Pending if opt_in_mode == OptInMode::Single => ImportStatus::Confirmed,
+            status => status,
+        };
+        let now = Utc::now();
+        let inserted = sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, email_display, name, subscribed_at, status, locale, tags, unsubscribed_at, confirmation_requested)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT ((lower(email))) DO NOTHING
+            RETURNING id
+            "#,
+            Uuid::new_v4(),
+            row.subscriber.email.as_ref(),
+            row.subscriber.email.display_form(),
+            row.subscriber.name.as_ref(),
+            now,
+            status.as_str(),
+            row.subscriber.locale.as_str(),
+            &row.tags,
+            (status == ImportStatus::Unsubscribed).then_some(now),
+            // without a confirmation email there is nothing to remind of, the
+            // reminder would be the first email the subscriber gets from us
+            status != ImportStatus::Pending || send_confirmations,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to insert an imported subscriber")?;
+        let Some(inserted) = inserted else {
+            outcome.skipped += 1;
+            continue;
+        };
+        outcome.imported += 1;
+        if let Some(list) = list {
+            restrict_to_list(&mut transaction, inserted.id, &list.key).await
+                .context("Failed to restrict an imported subscriber to their list")?;
+        }
+
+        let events: &[ConsentEvent] = match status {
+            ImportStatus::Pending => &[ConsentEvent::Subscribed],
+            ImportStatus::Confirmed => &[ConsentEvent::Subscribed, ConsentEvent::Confirmed],
+            ImportStatus::Unsubscribed => &[ConsentEvent::Unsubscribed],
+        };
+        for event in events {
+            record_consent_event(&mut transaction, inserted.id, *event, &consent).await
+                .context("Failed to record the consent of an imported subscriber")?;
+        }
+        if status != row.status {
+            welcomes.push(row.subscriber);
+        } else if send_confirmations && status == ImportStatus::Pending {
+            let subscription_token = generate_subscription_token();
+            store_token(&mut transaction, inserted.id, &subscription_token).await
+                .context("Failed to store the confirmation token of an imported subscriber")?;
+            confirmations.push((row.subscriber, subscription_token));
+        }
+    }
+    transaction.commit().await
+        .context("Failed to commit SQL transaction to import subscribers")?;
+
+    for (subscriber, subscription_token) in confirmations {
+        match send_confirmation_email(pool, email_client, subscriber, base_url, &subscription_token).await {
+            Ok(()) => outcome.confirmations_sent += 1,
+            Err(e) => {
+                outcome.confirmations_failed += 1;
+                tracing::error!(error.cause_chain = ?e, "Failed to send a confirmation email to an imported subscriber");
+            }
+        }
+    }
+    for subscriber in welcomes {
+        match send_welcome_email(pool, email_client, subscriber).await {
+            Ok(()) => outcome.welcomes_sent += 1,
+            Err(e) => {
+                outcome.welcomes_failed += 1;
+                tracing::error!(error.cause_chain = ?e, "Failed to send a welcome email to an imported subscriber");
+            }
+        }
+    }
+    Ok(Some(outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscriber_import::{parse_import, ImportStatus};
+
+    #[test]
+    fn rows_are_validated_and_repeated_addresses_reported() {
+        let preview = parse_import(
+            "Email,Name,Tags,Status\n\
+            ursula@example.com,Ursula Le Guin,sf; classics,confirmed\n\
+            not-an-address,Someone,,\n\
+            \"octavia@example.com\",\"Butler, Octavia\",,\n\
+            URSULA@example.com,Ursula,,\n\
+            ,,,\n",
+        )
+        .unwrap();
+
+        assert_eq!(preview.valid.len(), 2);
+        assert_eq!(preview.valid[0].status, ImportStatus::Confirmed);
+        assert_eq!(preview.valid[0].tags, ["sf", "classics"]);
+        assert_eq!(preview.valid[1].subscriber.name.as_ref(), "Butler, Octavia");
+        assert_eq!(preview.valid[1].status, ImportStatus::Pending);
+        assert_eq!(preview.invalid.len(), 1);
+        assert_eq!(preview.invalid[0].line, 3);
+        assert_eq!(preview.duplicates.len(), 1);
+        assert_eq!(preview.duplicates[0].reason, "Repeats line 2.");
+    }
+
+    #[test]
+    fn unknown_statuses_are_invalid_rows() {
+        let preview = parse_import("email,name,status\nursula@example.com,Ursula,vip\n").unwrap();
+
+        assert!(preview.valid.is_empty());
+        assert!(preview.invalid[0].reason.contains("vip is not a supported status"));
+    }
+
+    #[test]
+    fn the_email_and_name_columns_are_required() {
+        assert!(parse_import("address,name\nursula@example.com,Ursula\n").is_err());
+        assert!(parse_import("").is_err());
+    }
+}
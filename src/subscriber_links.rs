@@ -0,0 +1,64 @@
+//! src/subscriber_links.rs
+
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use secrecy::ExposeSecret;
+use uuid::Uuid;
+
+use crate::startup::HmacSecret;
+
+/// Self-service pages a subscriber reaches from the links in our emails.
+///
+/// Links are signed with the application HMAC secret, so they cannot be
+/// forged for somebody else's subscription. They do not expire.
+#[derive(Debug, Clone, Copy)]
+pub enum SubscriberLink {
+    Unsubscribe,
+    Preferences,
+}
+
+impl SubscriberLink {
+    fn path(&self) -> &'static str {
+        match self {
+            SubscriberLink::Unsubscribe => "/subscriptions/unsubscribe",
+            SubscriberLink::Preferences => "/subscriptions/preferences",
+        }
+    }
+
+    pub fn url(&self, base_url: &str, subscriber_id: Uuid, secret: &HmacSecret) -> String {
+        format!(
+            "{}{}?subscriber_id={}&signature={}",
+            base_url,
+            self.path(),
+            subscriber_id,
+            self.sign(subscriber_id, secret),
+        )
+    }
+
+    pub fn sign(&self, subscriber_id: Uuid, secret: &HmacSecret) -> String {
+        hex::encode(self.mac(subscriber_id, secret).finalize().into_bytes())
+    }
+
+    pub fn verify(
+        &self,
+        subscriber_id: Uuid,
+        signature: &str,
+        secret: &HmacSecret,
+    ) -> Result<(), anyhow::Error> {
+        let signature = hex::decode(signature)
+            .context("The signature is not hex-encoded")?;
+        self.mac(subscriber_id, secret)
+            .verify_slice(&signature)
+            .context("The signature does not match the subscriber id")
+    }
+
+    // the path is part of the signed message, so a link for one page
+    // cannot be reused for another
+    fn mac(&self, subscriber_id: Uuid, secret: &HmacSecret) -> Hmac<sha2::Sha256> {
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.0.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(self.path().as_bytes());
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
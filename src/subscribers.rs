@@ -0,0 +1,504 @@
+//! src/subscribers.rs
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+
+/// Statuses offered by the admin filters, with their labels.
+pub const SUBSCRIBER_STATUSES: &[(&str, &str)] = &[
+    ("pending_confirmation", "Pending confirmation"),
+    ("confirmed", "Confirmed"),
+    ("unsubscribed", "Unsubscribed"),
+    ("bounced", "Bounced"),
+    ("complained", "Complained"),
+    ("erased", "Erased"),
+];
+
+/// The subscriber filters as sent by the admin forms, empty fields are sent
+/// when nothing is picked.
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct SubscriberFilterForm {
+    pub search: Option<String>,
+    pub status: Option<String>,
+    /// Subscribed on or after, `YYYY-MM-DD`.
+    pub from: Option<String>,
+    /// Subscribed on or before, `YYYY-MM-DD`.
+    pub to: Option<String>,
+}
+
+impl SubscriberFilterForm {
+    pub fn parse(&self) -> Result<SubscriberFilter, String> {
+        let date = |value: &Option<String>| {
+            non_empty(value)
+                .map(|v| {
+                    NaiveDate::parse_from_str(&v, "%Y-%m-%d")
+                        .map_err(|_| format!("{} is not a valid date, use YYYY-MM-DD.", v))
+                })
+                .transpose()
+        };
+        Ok(SubscriberFilter {
+            search: non_empty(&self.search),
+            status: non_empty(&self.status),
+            subscribed_from: date(&self.from)?.map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+            // the whole last day is included
+            subscribed_until: date(&self.to)?.map(|d| (d + Duration::days(1)).and_hms_opt(0, 0, 0).unwrap().and_utc()),
+        })
+    }
+
+    /// The filters as a query string, for links that keep them.
+    pub fn to_query(&self) -> String {
+        serde_urlencoded::to_string(self).expect("Failed to encode the subscriber filters")
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(ToOwned::to_owned)
+}
+
+/// Which subscribers to list or export. Erased subscribers are left out
+/// unless asked for by status.
+#[derive(Debug, Default)]
+pub struct SubscriberFilter {
+    /// Substring of the email address or name, case-insensitive.
+    pub search: Option<String>,
+    pub status: Option<String>,
+    pub subscribed_from: Option<DateTime<Utc>>,
+    /// Exclusive.
+    pub subscribed_until: Option<DateTime<Utc>>,
+}
+
+impl SubscriberFilter {
+    /// `search` as an `ILIKE` pattern, with its wildcards escaped.
+    pub fn search_pattern(&self) -> Option<String> {
+        self.search.as_ref().map(|s| {
+            format!("%{}%", s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"))
+        })
+    }
+}
+
+/// Where a page of the subscriber list starts: the list is ordered newest
+/// first, so the next page holds the subscribers before this one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberCursor {
+    pub subscribed_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl SubscriberCursor {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("{} is not a valid page cursor.", value);
+        let (micros, id) = value.split_once('_').ok_or_else(invalid)?;
+        let subscribed_at = micros
+            .parse()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or_else(invalid)?;
+        let id = Uuid::parse_str(id).map_err(|_| invalid())?;
+        Ok(Self { subscribed_at, id })
+    }
+}
+
+impl std::fmt::Display for SubscriberCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Postgres keeps microseconds, so the cursor matches the row exactly
+        write!(f, "{}_{}", self.subscribed_at.timestamp_micros(), self.id)
+    }
+}
+
+pub struct SubscriberSummary {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub tags: Vec<String>,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+pub struct SubscriberPage {
+    pub subscribers: Vec<SubscriberSummary>,
+    /// `None` on the last page.
+    pub next: Option<SubscriberCursor>,
+}
+
+/// A page of subscribers, newest first, using keyset pagination so deep pages
+/// cost the same as the first one.
+#[tracing::instrument(name = "Searching subscribers", skip(pool))]
+pub async fn search_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    after: Option<&SubscriberCursor>,
+    page_size: i64,
+) -> Result<SubscriberPage, sqlx::Error> {
+    let mut subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, tags, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR email ILIKE $1 OR name ILIKE $1)
+            AND (($2::text IS NULL AND status <> 'erased') OR status = $2)
+            AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+            AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+            AND ($5::timestamptz IS NULL OR (subscribed_at, id) < ($5, $6))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $7
+        "#,
+        filter.search_pattern(),
+        filter.status,
+        filter.subscribed_from,
+        filter.subscribed_until,
+        after.map(|c| c.subscribed_at),
+        after.map(|c| c.id),
+        // one more than shown tells whether there is a next page
+        page_size + 1,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let next = if subscribers.len() as i64 > page_size {
+        subscribers.truncate(page_size as usize);
+        subscribers.last().map(|s| SubscriberCursor { subscribed_at: s.subscribed_at, id: s.id })
+    } else {
+        None
+    };
+    Ok(SubscriberPage { subscribers, next })
+}
+
+pub struct SubscriberDetails {
+    pub id: Uuid,
+    pub email: String,
+    /// The address as typed, unknown for most rows saved before it was kept.
+    pub email_display: Option<String>,
+    pub name: String,
+    pub status: String,
+    pub locale: String,
+    pub frequency: String,
+    pub tags: Vec<String>,
+    /// Values of the admin-defined attributes, by key.
+    pub attributes: serde_json::Value,
+    pub utm_source: Option<String>,
+    pub utm_campaign: Option<String>,
+    pub referrer: Option<String>,
+    pub subscribed_at: DateTime<Utc>,
+    /// `false` for pending subscribers imported without a confirmation email.
+    pub confirmation_requested: bool,
+    pub reminder_sent_at: Option<DateTime<Utc>>,
+    pub unsubscribed_at: Option<DateTime<Utc>>,
+    pub unsubscribe_reason: Option<String>,
+}
+
+/// A confirmation link, without the token itself.
+pub struct TokenRecord {
+    pub created_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+/// What the email provider reported about an email sent to the subscriber.
+pub struct DeliveryRecord {
+    pub email: String,
+    pub event_type: String,
+    pub description: Option<String>,
+    pub received_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get a subscriber", skip(pool))]
+pub async fn get_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<Option<SubscriberDetails>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT id, email, email_display, name, status, locale, frequency, tags, attributes,
+            utm_source, utm_campaign, referrer, subscribed_at,
+            confirmation_requested, reminder_sent_at, unsubscribed_at, unsubscribe_reason
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get the confirmation links of a subscriber", skip(pool))]
+pub async fn get_subscriber_tokens(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<TokenRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        TokenRecord,
+        r#"
+        SELECT created_at, used_at
+        FROM subscription_tokens
+        WHERE subscriber_id = $1
+        ORDER BY created_at DESC
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get the delivery records of a subscriber", skip(pool))]
+pub async fn get_delivery_records(pool: &PgPool, subscriber_id: Uuid) -> Result<Vec<DeliveryRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryRecord,
+        r#"
+        SELECT email, event_type, description, received_at
+        FROM email_events
+        WHERE subscriber_id = $1
+        ORDER BY received_at DESC
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Deletes subscribers with their tokens and list preferences, returning how
+/// many subscribers and tokens were deleted. Their delivery and consent
+/// history is kept, detached from the deleted rows and without network details.
+pub async fn delete_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+) -> Result<(i32, i32), anyhow::Error> {
+    let tokens_deleted = sqlx::query!(r#"DELETE FROM subscription_tokens WHERE subscriber_id = ANY($1)"#, subscriber_ids)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the tokens")?
+        .rows_affected();
+    sqlx::query!(r#"DELETE FROM list_opt_outs WHERE subscriber_id = ANY($1)"#, subscriber_ids)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the list opt-outs")?;
+    sqlx::query!(r#"DELETE FROM list_deliveries WHERE subscriber_id = ANY($1)"#, subscriber_ids)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the list deliveries")?;
+    sqlx::query!(r#"UPDATE email_events SET subscriber_id = NULL WHERE subscriber_id = ANY($1)"#, subscriber_ids)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to detach the delivery history")?;
+    sqlx::query!(
+        r#"UPDATE consent_events SET ip_address = NULL, user_agent = NULL WHERE subscriber_id = ANY($1)"#,
+        subscriber_ids,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to anonymize the consent history")?;
+    let subscribers_deleted = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = ANY($1)"#, subscriber_ids)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the subscribers")?
+        .rows_affected();
+    Ok((subscribers_deleted as i32, tokens_deleted as i32))
+}
+
+/// Converts addresses stored before their domains were normalized (they
+/// still have unicode domains) to what `SubscriberEmail::parse` makes of
+/// them today, keeping the stored one as the display form. Subscribers that
+/// turn out to be duplicates are merged like in the migration that made
+/// addresses case-insensitive. Returns how many addresses were converted.
+#[tracing::instrument(name = "Normalizing stored subscriber emails", skip(pool))]
+pub async fn normalize_stored_emails(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, email FROM subscriptions
+        WHERE email !~ '^[\x01-\x7f]*$'
+        ORDER BY subscribed_at, id
+        FOR UPDATE
+        "#,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to fetch the stored unicode addresses")?;
+
+    let mut converted = 0;
+    for row in rows {
+        let email = match SubscriberEmail::parse(row.email.clone()) {
+            Ok(email) if email.as_ref() != row.email => email,
+            Ok(_) => continue,  // only the local part is not ASCII
+            Err(e) => {
+                tracing::warn!(subscriber_id = %row.id, error.message = %e, "Skipping an invalid stored address");
+                continue;
+            }
+        };
+        let duplicate = sqlx::query!(
+            r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1) AND id <> $2"#,
+            email.as_ref(),
+            row.id,
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .context("Failed to look for a duplicate subscriber")?;
+        let keep_id = match duplicate {
+            Some(duplicate) => {
+                let (keep_id, drop_id) = pick_kept_subscriber(&mut transaction, row.id, duplicate.id).await?;
+                merge_subscribers(&mut transaction, keep_id, drop_id).await?;
+                keep_id
+            }
+            None => row.id,
+        };
+        if keep_id == row.id {
+            sqlx::query!(
+                r#"UPDATE subscriptions SET email = $2, email_display = $3 WHERE id = $1"#,
+                row.id,
+                email.as_ref(),
+                row.email,
+            )
+            .execute(&mut *transaction)
+            .await
+            .context("Failed to store the normalized address")?;
+        }
+        converted += 1;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store the normalized addresses")?;
+    Ok(converted)
+}
+
+/// The subscriber with the most restrictive status is kept, or else the one
+/// who subscribed first, so an address that left or complained stays that way.
+async fn pick_kept_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    a: Uuid,
+    b: Uuid,
+) -> Result<(Uuid, Uuid), anyhow::Error> {
+    let kept = sqlx::query!(
+        r#"
+        SELECT id FROM subscriptions
+        WHERE id = ANY($1)
+        ORDER BY
+            CASE status
+                WHEN 'complained' THEN 0
+                WHEN 'bounced' THEN 1
+                WHEN 'unsubscribed' THEN 2
+                WHEN 'confirmed' THEN 3
+                ELSE 4
+            END,
+            subscribed_at,
+            id
+        LIMIT 1
+        "#,
+        &[a, b][..],
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .context("Failed to pick the subscriber to keep")?;
+    Ok(if kept.id == a { (a, b) } else { (b, a) })
+}
+
+/// Moves everything attached to `drop_id` over to `keep_id` and deletes it.
+/// Consent events cannot be moved, they are detached from the deleted row.
+async fn merge_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    keep_id: Uuid,
+    drop_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(r#"UPDATE subscription_tokens SET subscriber_id = $1 WHERE subscriber_id = $2"#, keep_id, drop_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to move the tokens")?;
+    sqlx::query!(r#"UPDATE email_events SET subscriber_id = $1 WHERE subscriber_id = $2"#, keep_id, drop_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to move the delivery history")?;
+    sqlx::query!(r#"UPDATE admin_actions SET subscriber_id = $1 WHERE subscriber_id = $2"#, keep_id, drop_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to move the admin actions")?;
+    // an opt-out of either subscriber is kept
+    sqlx::query!(
+        r#"
+        INSERT INTO list_opt_outs (subscriber_id, list_key)
+        SELECT $1, list_key FROM list_opt_outs WHERE subscriber_id = $2
+        ON CONFLICT DO NOTHING
+        "#,
+        keep_id,
+        drop_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to move the list opt-outs")?;
+    sqlx::query!(r#"DELETE FROM list_opt_outs WHERE subscriber_id = $1"#, drop_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the list opt-outs")?;
+    // the latest delivery of either subscriber counts
+    sqlx::query!(
+        r#"
+        INSERT INTO list_deliveries (subscriber_id, list_key, last_sent_at)
+        SELECT $1, list_key, last_sent_at FROM list_deliveries WHERE subscriber_id = $2
+        ON CONFLICT (subscriber_id, list_key)
+        DO UPDATE SET last_sent_at = GREATEST(list_deliveries.last_sent_at, EXCLUDED.last_sent_at)
+        "#,
+        keep_id,
+        drop_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .context("Failed to move the list deliveries")?;
+    sqlx::query!(r#"DELETE FROM list_deliveries WHERE subscriber_id = $1"#, drop_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the list deliveries")?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, drop_id)
+        .execute(&mut **transaction)
+        .await
+        .context("Failed to delete the duplicate subscriber")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::subscribers::{SubscriberCursor, SubscriberFilter, SubscriberFilterForm};
+    use chrono::{TimeZone, Utc};
+    use uuid::Uuid;
+
+    #[test]
+    fn a_cursor_round_trips_through_its_string_form() {
+        let cursor = SubscriberCursor {
+            subscribed_at: Utc.timestamp_micros(1_746_000_000_123_456).unwrap(),
+            id: Uuid::new_v4(),
+        };
+
+        assert_eq!(SubscriberCursor::parse(&cursor.to_string()), Ok(cursor));
+        assert!(SubscriberCursor::parse("yesterday_abc").is_err());
+        assert!(SubscriberCursor::parse("").is_err());
+    }
+
+    #[test]
+    fn search_wildcards_are_escaped() {
+        let filter = SubscriberFilter { search: Some("100%_off".into()), ..Default::default() };
+
+        assert_eq!(filter.search_pattern().as_deref(), Some("%100\\%\\_off%"));
+    }
+
+    #[test]
+    fn empty_form_fields_are_ignored_and_the_last_day_is_included() {
+        let form = SubscriberFilterForm {
+            search: Some(" ".into()),
+            status: Some("".into()),
+            from: Some("2025-05-01".into()),
+            to: Some("2025-05-01".into()),
+        };
+
+        let filter = form.parse().unwrap();
+
+        assert_eq!(filter.search, None);
+        assert_eq!(filter.status, None);
+        assert_eq!(filter.subscribed_from, Some(Utc.with_ymd_and_hms(2025, 5, 1, 0, 0, 0).unwrap()));
+        assert_eq!(filter.subscribed_until, Some(Utc.with_ymd_and_hms(2025, 5, 2, 0, 0, 0).unwrap()));
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        let form = SubscriberFilterForm { from: Some("01/05/2025".into()), ..Default::default() };
+
+        assert_eq!(form.parse().unwrap_err(), "01/05/2025 is not a valid date, use YYYY-MM-DD.");
+    }
+}
@@ -0,0 +1,302 @@
+//! src/suppression.rs
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::domain_policy::parent_domains;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SuppressionKind {
+    Address,
+    Domain,
+}
+
+impl SuppressionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionKind::Address => "address",
+            SuppressionKind::Domain => "domain",
+        }
+    }
+}
+
+impl TryFrom<String> for SuppressionKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            "address" => Ok(Self::Address),
+            "domain" => Ok(Self::Domain),
+            other => Err(format!("{} is not a supported suppression kind. Use either 'address' or 'domain'.", other)),
+        }
+    }
+}
+
+/// A validated, normalized suppression list entry.
+#[derive(Debug)]
+pub struct NewSuppression {
+    pub kind: SuppressionKind,
+    pub value: String,
+}
+
+impl NewSuppression {
+    /// Domains are converted to punycode the way `SubscriberEmail::parse`
+    /// does it, so entries match the addresses they are checked against.
+    pub fn parse(kind: SuppressionKind, value: &str) -> Result<Self, String> {
+        let value = value.trim().trim_start_matches('@');
+        let invalid = || format!("{} is not a valid suppressed {}.", value, kind.as_str());
+        let normalized = match kind {
+            SuppressionKind::Address => SubscriberEmail::parse(value.to_owned())
+                .map_err(|_| invalid())?
+                .as_ref()
+                .to_lowercase(),
+            SuppressionKind::Domain => {
+                let domain = idna::domain_to_ascii(value).map_err(|_| invalid())?;
+                let is_valid = !domain.is_empty()
+                    && domain.contains('.')
+                    && !domain.contains('@')
+                    && !domain.chars().any(char::is_whitespace);
+                if !is_valid {
+                    return Err(invalid());
+                }
+                domain
+            }
+        };
+        Ok(Self { kind, value: normalized })
+    }
+
+    /// Anything with an `@` followed by a local part is an address, anything
+    /// else (`example.com` or `@example.com`) a whole domain and its subdomains.
+    pub fn parse_line(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        match line.find('@') {
+            Some(i) if i > 0 => Self::parse(SuppressionKind::Address, line),
+            _ => Self::parse(SuppressionKind::Domain, line),
+        }
+    }
+}
+
+pub struct SuppressionEntry {
+    pub id: Uuid,
+    pub kind: String,
+    pub value: String,
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Consulted by the `EmailClient` before every outgoing email.
+#[derive(Clone)]
+pub struct SuppressionList {
+    pool: PgPool,
+    suppressed_sends: Arc<AtomicU64>,
+}
+
+impl SuppressionList {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            suppressed_sends: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    #[tracing::instrument(
+        name = "Checking the suppression list",
+        skip(self, recipient)
+    )]
+    pub async fn is_suppressed(&self, recipient: &SubscriberEmail) -> Result<bool, sqlx::Error> {
+        let address = recipient.as_ref().to_lowercase();
+        let domains = suppressed_domains(&address);
+        let row = sqlx::query!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM suppressions
+                WHERE (kind = 'address' AND value = $1)
+                   OR (kind = 'domain' AND value = ANY($2))
+            ) AS "suppressed!"
+            "#,
+            address,
+            &domains,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.suppressed)
+    }
+
+    /// Returns the number of suppressed sends since startup.
+    pub fn record_suppressed_send(&self) -> u64 {
+        self.suppressed_sends.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+/// A domain entry covers its subdomains too, so `ursula@mail.example.com` is
+/// suppressed by `mail.example.com` and by `example.com`.
+fn suppressed_domains(address: &str) -> Vec<String> {
+    parent_domains(address.rsplit('@').next().unwrap_or_default())
+}
+
+/// Returns `false` if the entry was already on the list.
+#[tracing::instrument(
+    name = "Adding an entry to the suppression list",
+    skip(executor, suppression, reason)
+)]
+pub async fn add_suppression(
+    executor: impl PgExecutor<'_>,
+    suppression: &NewSuppression,
+    reason: &str,
+    source: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (id, kind, value, reason, source, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (kind, value) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        suppression.kind.as_str(),
+        suppression.value,
+        reason,
+        source,
+        Utc::now(),
+    )
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Removing an entry from the suppression list", skip(pool))]
+pub async fn remove_suppression(pool: &PgPool, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM suppressions WHERE id = $1"#, id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get suppression list", skip(pool))]
+pub async fn get_suppressions(pool: &PgPool) -> Result<Vec<SuppressionEntry>, sqlx::Error> {
+    sqlx::query_as!(
+        SuppressionEntry,
+        r#"
+        SELECT id, kind, value, reason, source, created_at
+        FROM suppressions
+        ORDER BY created_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Converts entries stored before their domains were normalized (they still
+/// have unicode domains) to what `NewSuppression::parse` makes of them today.
+/// An entry that already exists in the converted form is dropped. Returns how
+/// many entries were converted or dropped.
+#[tracing::instrument(name = "Normalizing stored suppressions", skip(pool))]
+pub async fn normalize_stored_suppressions(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, kind, value FROM suppressions
+        WHERE value !~ '^[\x01-\x7f]*$'
+        ORDER BY created_at, id
+        FOR UPDATE
+        "#,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to fetch the stored unicode suppressions")?;
+
+    let mut converted = 0;
+    for row in rows {
+        let suppression = match SuppressionKind::try_from(row.kind).and_then(|kind| NewSuppression::parse(kind, &row.value)) {
+            Ok(suppression) if suppression.value != row.value => suppression,
+            Ok(_) => continue,  // only the local part is not ASCII
+            Err(e) => {
+                tracing::warn!(suppression_id = %row.id, error.message = %e, "Skipping an invalid stored suppression");
+                continue;
+            }
+        };
+        let exists = sqlx::query!(
+            r#"SELECT EXISTS (SELECT 1 FROM suppressions WHERE kind = $1 AND value = $2) AS "exists!""#,
+            suppression.kind.as_str(),
+            suppression.value,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .context("Failed to look for a duplicate suppression")?
+        .exists;
+        if exists {
+            sqlx::query!(r#"DELETE FROM suppressions WHERE id = $1"#, row.id)
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to delete the duplicate suppression")?;
+        } else {
+            sqlx::query!(r#"UPDATE suppressions SET value = $2 WHERE id = $1"#, row.id, suppression.value)
+                .execute(&mut *transaction)
+                .await
+                .context("Failed to store the normalized suppression")?;
+        }
+        converted += 1;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store the normalized suppressions")?;
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::suppression::{suppressed_domains, NewSuppression, SuppressionKind};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn lines_with_a_local_part_are_addresses() {
+        let suppression = NewSuppression::parse_line(" Ursula@Example.com ").unwrap();
+        assert_eq!(suppression.kind, SuppressionKind::Address);
+        assert_eq!(suppression.value, "ursula@example.com");
+    }
+
+    #[test]
+    fn lines_without_a_local_part_are_domains() {
+        for line in ["example.com", "@example.com"] {
+            let suppression = NewSuppression::parse_line(line).unwrap();
+            assert_eq!(suppression.kind, SuppressionKind::Domain);
+            assert_eq!(suppression.value, "example.com");
+        }
+    }
+
+    #[test]
+    fn internationalized_domains_are_converted_to_punycode() {
+        let suppression = NewSuppression::parse_line("Ursula@Bücher.de").unwrap();
+        assert_eq!(suppression.value, "ursula@xn--bcher-kva.de");
+        let suppression = NewSuppression::parse_line("@Bücher.de").unwrap();
+        assert_eq!(suppression.value, "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn invalid_entries_are_rejected() {
+        assert_err!(NewSuppression::parse(SuppressionKind::Domain, ""));
+        assert_err!(NewSuppression::parse(SuppressionKind::Domain, "localhost"));
+        assert_err!(NewSuppression::parse(SuppressionKind::Address, "not-an-email"));
+        assert_ok!(NewSuppression::parse(SuppressionKind::Domain, "mailinator.com"));
+    }
+
+    #[test]
+    fn domain_entries_cover_subdomains() {
+        assert_eq!(
+            suppressed_domains("ursula@mail.example.com"),
+            ["mail.example.com", "example.com"]
+        );
+        assert_eq!(suppressed_domains("ursula@example.com"), ["example.com"]);
+    }
+}
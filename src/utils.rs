@@ -1,7 +1,9 @@
 //! src/utils.rs
 
-use actix_web::HttpResponse;
+use std::net::{IpAddr, SocketAddr};
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web::http::header::LOCATION;
+use crate::startup::TrustedProxies;
 
 pub fn e500<T>(e: T) -> actix_web::Error
     where 
@@ -14,4 +16,40 @@ pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
-}
\ No newline at end of file
+}
+
+/// The address of the client that sent the request. Behind trusted reverse
+/// proxies the peer is a proxy, so `X-Forwarded-For` is walked from the right,
+/// the end our proxies append to, up to the first address that is not one of
+/// them. Anything further left was sent by the client and could be forged.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let Some(proxies) = request.app_data::<web::Data<TrustedProxies>>() else {
+        return Some(peer.to_string());
+    };
+    let forwarded: Vec<&str> = request
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    let mut client = peer;
+    for entry in forwarded.into_iter().rev() {
+        if !proxies.0.contains(&client) {
+            break;
+        }
+        match parse_ip(entry) {
+            Some(ip) => client = ip,
+            // a proxy of ours would not have written this, stop trusting the header
+            None => break,
+        }
+    }
+    Some(client.to_string())
+}
+
+fn parse_ip(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .or_else(|_| addr.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+}
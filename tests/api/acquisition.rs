@@ -0,0 +1,120 @@
+//! tests/api/acquisition.rs
+
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn utm_parameters_and_the_referring_host_are_stored() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    let response = app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com\
+        &utm_source=podcast&utm_medium=audio&utm_campaign=spring%20launch&utm_term=&utm_content=%20ad-1%20\
+        &referrer=https%3A%2F%2Fnews.example.com%2Fitem%3Fid%3D42".into(),
+    ).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        "SELECT utm_source, utm_medium, utm_campaign, utm_term, utm_content, referrer FROM subscriptions",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.utm_source.as_deref(), Some("podcast"));
+    assert_eq!(saved.utm_medium.as_deref(), Some("audio"));
+    assert_eq!(saved.utm_campaign.as_deref(), Some("spring launch"));
+    assert_eq!(saved.utm_term, None);
+    assert_eq!(saved.utm_content.as_deref(), Some("ad-1"));
+    assert_eq!(saved.referrer.as_deref(), Some("news.example.com"));
+}
+
+#[tokio::test]
+async fn returning_subscribers_keep_the_source_of_their_first_sign_up() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    for source in ["podcast", "twitter"] {
+        let response = app.post_subscriptions_json(&serde_json::json!({
+            "name": "Ursula",
+            "email": "ursula_le_guin@gmail.com",
+            "utm_source": source,
+        })).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let saved = sqlx::query!("SELECT utm_source FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.utm_source.as_deref(), Some("podcast"));
+}
+
+#[tokio::test]
+async fn the_home_page_passes_the_campaign_on_to_the_subscribe_form() {
+    let app = spawn_app().await;
+
+    let html = app.api_client
+        .get(&format!("{}/?utm_source=podcast&utm_campaign=%22spring%22&page=2", &app.address))
+        .header("Referer", "https://news.example.com/item?id=42")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(html.contains(r#"<input type="hidden" name="utm_source" value="podcast">"#));
+    assert!(html.contains(r#"<input type="hidden" name="utm_campaign" value="&quot;spring&quot;">"#));
+    assert!(!html.contains(r#"name="page""#));
+    assert!(html.contains(r#"<input type="hidden" name="referrer" value="https://news.example.com/item?id=42">"#));
+
+    let html = app.api_client
+        .get(&format!("{}/", &app.address))
+        .header("Referer", format!("{}/login", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(!html.contains(r#"name="referrer""#));
+}
+
+#[tokio::test]
+async fn the_dashboard_breaks_sign_ups_down_by_source() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    for (email, source) in [
+        ("ursula@example.com", "utm_source=podcast&utm_campaign=spring"),
+        ("octavia@example.com", "utm_source=podcast&utm_campaign=spring"),
+        ("ted@example.com", "referrer=https%3A%2F%2Fnews.example.com%2F"),
+        ("anne@example.com", ""),
+    ] {
+        app.post_subscriptions(format!("name=reader&email={}&{}", urlencoding::encode(email), source))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    let confirmation_link = {
+        let requests = app.email_server.received_requests().await.unwrap();
+        app.get_confirmation_links(&requests[0]).html
+    };
+    reqwest::get(confirmation_link).await.unwrap().error_for_status().unwrap();
+
+    app.login().await;
+    let html = app.get_admin_dashboard_html().await;
+
+    assert!(html.contains("<tr><td>podcast</td><td>spring</td><td>2</td><td>1</td><td>50%</td></tr>"));
+    assert!(html.contains("<tr><td>news.example.com</td><td>-</td><td>1</td><td>0</td><td>0%</td></tr>"));
+    assert!(html.contains("<tr><td>(direct)</td><td>-</td><td>1</td><td>0</td><td>0%</td></tr>"));
+}
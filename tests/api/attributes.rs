@@ -0,0 +1,216 @@
+//! tests/api/attributes.rs
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::OptInMode;
+
+async fn define_attributes(app: &TestApp) {
+    app.login().await;
+    for body in [
+        serde_json::json!({"key": "company", "label": "Company", "kind": "text"}),
+        serde_json::json!({"key": "employees", "label": "Employees", "kind": "number"}),
+        serde_json::json!({"key": "country", "label": "Country", "kind": "enum", "options": "DE, FR", "required": "true"}),
+    ] {
+        let response = app.post_attribute(&body).await;
+        assert_is_redirect_to(&response, "/admin/attributes");
+    }
+}
+
+async fn stored_attributes(app: &TestApp, email: &str) -> serde_json::Value {
+    sqlx::query!("SELECT attributes FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .attributes
+}
+
+#[tokio::test]
+async fn admins_define_attributes_shown_on_the_subscribe_form() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    let html = app.get_attributes_html().await;
+    assert!(html.contains("The attribute country has been added."));
+    assert!(html.contains("<td>company</td>"));
+    assert!(html.contains("DE, FR"));
+
+    let home = reqwest::get(&format!("{}/", &app.address)).await.unwrap().text().await.unwrap();
+    assert!(home.contains(r#"<input type="text" name="attributes[company]" maxlength="200">"#));
+    assert!(home.contains(r#"<input type="number" name="attributes[employees]" step="any">"#));
+    assert!(home.contains(r#"<select name="attributes[country]" required>"#));
+    assert!(home.contains(r#"<option value="FR">FR</option>"#));
+}
+
+#[tokio::test]
+async fn invalid_attribute_definitions_are_rejected() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+    app.get_attributes_html().await;
+
+    let test_cases = vec![
+        (serde_json::json!({"key": "Company Name", "label": "Company", "kind": "text"}), "is not a valid key"),
+        (serde_json::json!({"key": "size", "label": "Size", "kind": "enum", "options": ""}), "needs at least one option"),
+        (serde_json::json!({"key": "company", "label": "Company", "kind": "text"}), "already exists"),
+    ];
+    for (body, error) in test_cases {
+        let response = app.post_attribute(&body).await;
+        assert_is_redirect_to(&response, "/admin/attributes");
+
+        let html = app.get_attributes_html().await;
+        assert!(html.contains(error), "No '{}' error for {}", error, body);
+    }
+}
+
+#[tokio::test]
+async fn attributes_are_stored_with_the_subscription() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com\
+        &attributes%5Bcompany%5D=Earthsea&attributes%5Bemployees%5D=12&attributes%5Bcountry%5D=FR".into(),
+    ).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "Octavia Butler",
+        "email": "octavia@example.com",
+        "attributes": {"employees": 3.5, "country": "DE"},
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(
+        stored_attributes(&app, "ursula_le_guin@gmail.com").await,
+        serde_json::json!({"company": "Earthsea", "employees": 12, "country": "FR"}),
+    );
+    assert_eq!(
+        stored_attributes(&app, "octavia@example.com").await,
+        serde_json::json!({"employees": 3.5, "country": "DE"}),
+    );
+}
+
+#[tokio::test]
+async fn invalid_attributes_are_reported_as_field_errors() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "",
+        "email": "ursula_le_guin@gmail.com",
+        "attributes": {"employees": "a dozen", "shoe_size": "38"},
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let mut fields: Vec<_> = body["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|e| e["field"].as_str().unwrap().to_owned())
+        .collect();
+    fields.sort();
+    assert_eq!(fields, vec!["attributes.country", "attributes.employees", "attributes.shoe_size", "name"]);
+    let count = sqlx::query!("SELECT count(*) AS \"count!\" FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn deleting_an_attribute_deletes_its_values() {
+    let app = spawn_app_with(|c| c.application.opt_in_mode = OptInMode::Single).await;
+    define_attributes(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions_json(&serde_json::json!({
+        "name": "Octavia Butler",
+        "email": "octavia@example.com",
+        "attributes": {"company": "Parable", "country": "DE"},
+    })).await.error_for_status().unwrap();
+
+    let response = app.post_delete_attribute("company").await;
+    assert_is_redirect_to(&response, "/admin/attributes");
+
+    let html = app.get_attributes_html().await;
+    assert!(html.contains("The attribute company has been deleted."));
+    assert!(!html.contains("<td>company</td>"));
+    assert_eq!(stored_attributes(&app, "octavia@example.com").await, serde_json::json!({"country": "DE"}));
+}
+
+#[tokio::test]
+async fn newsletters_go_to_the_segment_and_are_personalized() {
+    let app = spawn_app_with(|c| c.application.opt_in_mode = OptInMode::Single).await;
+    define_attributes(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    for (name, email, attributes) in [
+        ("Ursula", "ursula@example.com", serde_json::json!({"company": "Earth<sea>", "employees": 40, "country": "FR"})),
+        ("Octavia", "octavia@example.com", serde_json::json!({"company": "Parable", "employees": 3, "country": "FR"})),
+        ("Ted", "ted@example.com", serde_json::json!({"employees": 80, "country": "DE"})),
+    ] {
+        app.post_subscriptions_json(&serde_json::json!({"name": name, "email": email, "attributes": attributes}))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    let welcome_emails = app.email_server.received_requests().await.unwrap().len();
+
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "News for {{attributes.company}}",
+        "content": {
+            "text": "Hi {{subscriber_name}}, {{attributes.employees}} of you at {{attributes.company}}",
+            "html": "<p>Hi {{subscriber_name}} at {{attributes.company}}</p>",
+        },
+        "segment": [
+            {"attribute": "country", "operator": "equals", "value": "FR"},
+            {"attribute": "employees", "operator": "greater_than", "value": "10"},
+        ],
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), welcome_emails + 1);
+    let body: serde_json::Value = serde_json::from_slice(&requests.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+    assert_eq!(body["Subject"], "News for Earth<sea>");
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Hi Ursula, 40 of you at Earth<sea>"));
+    assert!(body["HtmlBody"].as_str().unwrap().starts_with("<p>Hi Ursula at Earth&lt;sea&gt;</p>"));
+}
+
+#[tokio::test]
+async fn newsletters_with_invalid_segments_or_placeholders_are_rejected() {
+    let app = spawn_app().await;
+    define_attributes(&app).await;
+    let newsletter = |title: &str, segment: serde_json::Value| serde_json::json!({
+        "title": title,
+        "content": {"text": "newsletter content", "html": "<p>newsletter content</p>"},
+        "segment": segment,
+    });
+    let test_cases = vec![
+        (newsletter("News", serde_json::json!([{"attribute": "shoe_size", "operator": "is_set"}])), "unknown attribute"),
+        (
+            newsletter("News", serde_json::json!([{"attribute": "company", "operator": "greater_than", "value": "a"}])),
+            "operator not supported by the type",
+        ),
+        (newsletter("News for {{attributes.shoe_size}}", serde_json::json!([])), "unknown placeholder"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = app.post_newsletters(body).await;
+
+        assert_eq!(response.status().as_u16(), 400, "The API did not fail with 400 for an {}.", description);
+    }
+}
@@ -0,0 +1,239 @@
+//! tests/api/bot_protection.rs
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configurations::{BotProtectionSettings, ChallengeSettings};
+
+async fn mock_email_server(app: &TestApp, expected_emails: u64) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(expected_emails)
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn form_stamp_from_home_page(app: &TestApp) -> String {
+    let html = app.api_client
+        .get(&app.address)
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    let marker = r#"name="form_stamp" value=""#;
+    let start = html.find(marker).unwrap() + marker.len();
+    let end = start + html[start..].find('"').unwrap();
+    html[start..end].to_owned()
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn a_filled_in_honeypot_is_silently_ignored() {
+    let app = spawn_app().await;
+    mock_email_server(&app, 0).await;
+
+    let response = app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example".into()
+    ).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn forms_submitted_faster_than_the_minimum_fill_time_are_rejected() {
+    let app = spawn_app_with(|c| c.bot_protection.min_fill_seconds = 3).await;
+    mock_email_server(&app, 1).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 400, "the stamp is required");
+
+    let response = app.post_subscriptions(format!("{}&form_stamp=1700000000.abcd", body)).await;
+    assert_eq!(response.status().as_u16(), 400, "forged stamps are rejected");
+
+    let stamp = form_stamp_from_home_page(&app).await;
+    let response = app.post_subscriptions(format!("{}&form_stamp={}", body, stamp)).await;
+    assert_eq!(response.status().as_u16(), 400, "the form was filled in too quickly");
+
+    tokio::time::sleep(std::time::Duration::from_millis(3100)).await;
+    let response = app.post_subscriptions(format!("{}&form_stamp={}", body, stamp)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+/// The bot protection of `base.yaml`, which `local.yaml` relaxes for curl.
+fn base_bot_protection() -> BotProtectionSettings {
+    config::Config::builder()
+        .add_source(config::File::with_name("configuration/base.yaml"))
+        .build()
+        .expect("Failed to read the base configuration")
+        .get("bot_protection")
+        .expect("Failed to parse the base bot protection settings")
+}
+
+#[tokio::test]
+async fn json_clients_need_a_form_stamp_from_the_api_with_the_base_settings() {
+    let app = spawn_app_with(|c| c.bot_protection = base_bot_protection()).await;
+    mock_email_server(&app, 1).await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    });
+
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(response.status().as_u16(), 400, "sending JSON does not skip the stamp");
+
+    let response = app.api_client
+        .get(&format!("{}/subscriptions/form_stamp", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let stamp: serde_json::Value = response.json().await.unwrap();
+    let mut body = body;
+    body["form_stamp"] = stamp["form_stamp"].clone();
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(response.status().as_u16(), 400, "the stamp is subject to the minimum fill time");
+
+    tokio::time::sleep(std::time::Duration::from_millis(3100)).await;
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscriptions_from_the_same_ip_are_rate_limited() {
+    let app = spawn_app_with(|c| c.bot_protection.per_ip_limit = 2).await;
+    mock_email_server(&app, 2).await;
+
+    for i in 0..2 {
+        let response = app.post_subscriptions(format!("name=ursula&email=ursula{}%40gmail.com", i)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_subscriptions("name=ursula&email=ursula2%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+async fn post_subscriptions_forwarded_for(app: &TestApp, body: &str, client_ip: &str) -> reqwest::Response {
+    app.api_client
+        .post(&format!("{}/subscriptions", &app.address))
+        .header("Content-type", "application/x-www-form-urlencoded")
+        .header("X-Forwarded-For", client_ip)
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn behind_a_trusted_proxy_the_ip_limit_applies_to_the_forwarded_client() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.per_ip_limit = 1;
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+    })
+    .await;
+    mock_email_server(&app, 2).await;
+
+    let response = post_subscriptions_forwarded_for(&app, "name=ursula&email=ursula0%40gmail.com", "203.0.113.7").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = post_subscriptions_forwarded_for(&app, "name=ursula&email=ursula1%40gmail.com", "203.0.113.7").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = post_subscriptions_forwarded_for(&app, "name=ursula&email=ursula2%40gmail.com", "198.51.100.4").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn spoofed_addresses_left_of_the_trusted_proxies_are_ignored() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.per_ip_limit = 1;
+        c.application.trusted_proxies = vec!["127.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()];
+    })
+    .await;
+    mock_email_server(&app, 1).await;
+
+    let response = post_subscriptions_forwarded_for(
+        &app,
+        "name=ursula&email=ursula0%40gmail.com",
+        "192.0.2.1, 203.0.113.7, 10.0.0.2",
+    ).await;
+    assert_eq!(response.status().as_u16(), 200);
+    // the client made up a different leftmost address, our proxies still saw the same one
+    let response = post_subscriptions_forwarded_for(
+        &app,
+        "name=ursula&email=ursula1%40gmail.com",
+        "192.0.2.2, 203.0.113.7, 10.0.0.2",
+    ).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn forwarded_addresses_from_untrusted_peers_are_ignored() {
+    let app = spawn_app_with(|c| c.bot_protection.per_ip_limit = 1).await;
+    mock_email_server(&app, 1).await;
+
+    let response = post_subscriptions_forwarded_for(&app, "name=ursula&email=ursula0%40gmail.com", "203.0.113.7").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = post_subscriptions_forwarded_for(&app, "name=ursula&email=ursula1%40gmail.com", "198.51.100.4").await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn subscribing_the_same_address_again_is_rate_limited() {
+    let app = spawn_app_with(|c| c.bot_protection.per_email_limit = 2).await;
+    mock_email_server(&app, 3).await;
+
+    for _ in 0..2 {
+        let response = app.post_subscriptions("name=ursula&email=ursula%40gmail.com".into()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_subscriptions("name=ursula&email=URSULA%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_subscriptions("name=ursula&email=someone_else%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscriptions_to_the_same_email_domain_are_rate_limited() {
+    let app = spawn_app_with(|c| c.bot_protection.per_email_domain_limit = 1).await;
+    mock_email_server(&app, 2).await;
+
+    let response = app.post_subscriptions("name=ursula&email=ursula%40victim.example".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_subscriptions("name=ursula&email=le_guin%40VICTIM.example".into()).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_subscriptions("name=ursula&email=ursula%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscriptions_must_pass_the_configured_challenge() {
+    let app = spawn_app_with(|c| {
+        c.bot_protection.challenge = Some(ChallengeSettings::Stub {
+            expected_response: "solved".into(),
+        })
+    }).await;
+    mock_email_server(&app, 1).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app.post_subscriptions(format!("{}&challenge_response=wrong", body)).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app.post_subscriptions(format!("{}&challenge_response=solved", body)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
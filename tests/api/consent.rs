@@ -0,0 +1,181 @@
+//! tests/api/consent.rs
+
+use crate::helpers::{spawn_app, create_confirmed_subscriber, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::consent::CONSENT_TEXT_VERSION;
+
+fn query_param(link: &reqwest::Url, name: &str) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+struct SavedConsentEvent {
+    event_type: String,
+    source: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    consent_text_version: Option<String>,
+}
+
+async fn consent_events(app: &TestApp) -> Vec<SavedConsentEvent> {
+    sqlx::query_as!(
+        SavedConsentEvent,
+        r#"
+        SELECT event_type, source, ip_address, user_agent, consent_text_version
+        FROM consent_events
+        ORDER BY occurred_at
+        "#,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .expect("Failed to fetch the consent events.")
+}
+
+#[tokio::test]
+async fn subscribing_and_confirming_are_recorded() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.api_client
+        .post(&format!("{}/subscriptions", &app.address))
+        .header("User-Agent", "consent-test/1.0")
+        .form(&[
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("consent_version", CONSENT_TEXT_VERSION),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html).await.unwrap().error_for_status().unwrap();
+
+    let events = consent_events(&app).await;
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].event_type, "subscribed");
+    assert_eq!(events[0].source, "form");
+    assert_eq!(events[0].ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(events[0].user_agent.as_deref(), Some("consent-test/1.0"));
+    assert_eq!(events[0].consent_text_version.as_deref(), Some(CONSENT_TEXT_VERSION));
+    assert_eq!(events[1].event_type, "confirmed");
+    assert_eq!(events[1].source, "email_link");
+    assert_eq!(events[1].consent_text_version, None);
+}
+
+#[tokio::test]
+async fn subscriptions_without_a_consent_version_record_none() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    })).await.error_for_status().unwrap();
+
+    let events = consent_events(&app).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].source, "api");
+    assert_eq!(events[0].consent_text_version, None);
+}
+
+#[tokio::test]
+async fn subscriptions_with_an_unknown_consent_version_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "consent_version": "1999-01-01",
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "consent_version");
+    assert!(consent_events(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn preference_changes_and_unsubscribing_are_recorded_and_shown_to_admins() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+    let subscriber_id = query_param(&link, "subscriber_id");
+
+    app.post_preferences(&[
+        ("subscriber_id", subscriber_id.as_str()),
+        ("signature", query_param(&link, "signature").as_str()),
+        ("name", "Ursula"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("frequency", "monthly"),
+    ]).await;
+    let link = app.get_unsubscribe_link().await;
+    for _ in 0..2 {
+        app.post_unsubscribe(&serde_json::json!({
+            "subscriber_id": subscriber_id,
+            "signature": query_param(&link, "signature"),
+        })).await.error_for_status().unwrap();
+    }
+
+    let events: Vec<_> = consent_events(&app).await.into_iter().map(|e| e.event_type).collect();
+    assert_eq!(events, ["subscribed", "confirmed", "preferences_updated", "unsubscribed"]);
+
+    app.login().await;
+    let html = app.api_client
+        .get(&format!("{}/admin/consent", &app.address))
+        .query(&[("email", "Ursula_Le_Guin@gmail.com")])
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("<td>preferences_updated</td>"));
+    assert!(html.contains("<td>unsubscribed</td>"));
+}
+
+#[tokio::test]
+async fn consent_events_cannot_be_changed_or_deleted() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let update = sqlx::query!("UPDATE consent_events SET event_type = 'confirmed'")
+        .execute(&app.db_pool)
+        .await;
+    assert!(update.is_err());
+    let delete = sqlx::query!("DELETE FROM consent_events")
+        .execute(&app.db_pool)
+        .await;
+    assert!(delete.is_err());
+    assert_eq!(consent_events(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn consent_history_is_exported_and_its_network_details_erased() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    let response = app.post_admin_data_request("export", "ursula_le_guin@gmail.com").await;
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["consent_events"].as_array().unwrap().len(), 2);
+    assert_eq!(export["consent_events"][0]["ip_address"], "127.0.0.1");
+
+    app.post_admin_data_request("erase", "ursula_le_guin@gmail.com").await;
+    let events = consent_events(&app).await;
+    assert_eq!(events.len(), 2);
+    assert!(events.iter().all(|e| e.ip_address.is_none() && e.user_agent.is_none()));
+}
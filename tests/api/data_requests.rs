@@ -0,0 +1,179 @@
+//! tests/api/data_requests.rs
+
+use crate::helpers::{spawn_app, spawn_app_with, assert_is_redirect_to, create_confirmed_subscriber, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+/// Requests the data of the confirmed test subscriber and returns the emailed
+/// confirmation link, taken from the text body where `&` is not escaped.
+async fn request_data(app: &TestApp, request: &str) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app.post_data_request(EMAIL, request).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    app.get_confirmation_links(&email_request).plain_text
+}
+
+/// The confirmation page only shows a form, the hidden fields are posted back.
+async fn confirm(app: &TestApp, link: &reqwest::Url) -> reqwest::Response {
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(r#"action="/subscriptions/data/confirm""#));
+
+    let fields: Vec<(String, String)> = link.query_pairs().into_owned().collect();
+    app.api_client
+        .post(&format!("{}/subscriptions/data/confirm", &app.address))
+        .form(&fields)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data_after_confirming_by_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_data(&app, "export").await;
+    let response = confirm(&app, &link).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Disposition"].to_str().unwrap().contains("attachment"));
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriptions"][0]["email"], EMAIL);
+    assert_eq!(export["subscriptions"][0]["status"], "confirmed");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn an_erased_subscriber_is_anonymized_and_no_longer_emailed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = request_data(&app, "erasure").await;
+    let response = confirm(&app, &link).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "erased");
+    assert_eq!(saved.name, "");
+    assert!(saved.email.ends_with("@erased.invalid"));
+    let tokens = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 0);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        }
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn requests_for_unknown_addresses_look_the_same_but_send_nothing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let known = app.post_data_request(EMAIL, "export").await;
+    let unknown = app.post_data_request("someone@example.com", "export").await;
+
+    assert_eq!(known.status().as_u16(), 200);
+    assert_eq!(unknown.status().as_u16(), 200);
+    assert_eq!(known.text().await.unwrap(), unknown.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn requests_for_the_same_address_are_rate_limited() {
+    let app = spawn_app_with(|c| c.bot_protection.per_email_limit = 2).await;
+
+    for _ in 0..2 {
+        let response = app.post_data_request("someone@example.com", "export").await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_data_request("SOMEONE@example.com", "erasure").await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_data_request("someone_else@example.com", "export").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_tampered_data_request_link_is_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let mut link = request_data(&app, "export").await;
+    let tampered: Vec<(String, String)> = link
+        .query_pairs()
+        .into_owned()
+        .map(|(k, v)| if k == "request" { (k, "erasure".to_owned()) } else { (k, v) })
+        .collect();
+    link.query_pairs_mut().clear().extend_pairs(&tampered);
+
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.api_client
+        .post(&format!("{}/subscriptions/data/confirm", &app.address))
+        .form(&tampered)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn admins_can_export_and_erase_subscriber_data() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    let response = app.post_admin_data_request("export", "Ursula_Le_Guin@Gmail.com").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscriptions"][0]["email"], EMAIL);
+
+    let response = app.post_admin_data_request("erase", EMAIL).await;
+    assert_is_redirect_to(&response, "/admin/data-requests");
+    let html = app.api_client
+        .get(&format!("{}/admin/data-requests", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("1 subscription(s) anonymized"));
+}
@@ -0,0 +1,162 @@
+//! tests/api/domain_policy.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to, create_confirmed_subscriber, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn mock_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+async fn subscribe(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": email,
+    })).await
+}
+
+#[tokio::test]
+async fn disposable_email_addresses_are_rejected_with_a_clear_error() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+
+    for email in ["ursula@mailinator.com", "ursula@inbox.Mailinator.com"] {
+        let response = subscribe(&app, email).await;
+
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", email);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["errors"][0]["field"], "email");
+        assert!(body["errors"][0]["message"].as_str().unwrap().contains("disposable email provider"));
+    }
+}
+
+#[tokio::test]
+async fn admins_can_block_and_unblock_a_domain() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.login().await;
+
+    let response = app.post_domain_rule("@Spammy.example", "block").await;
+    assert_is_redirect_to(&response, "/admin/domains");
+    assert!(app.get_domains_html().await.contains("spammy.example is now blocked."));
+
+    let response = subscribe(&app, "ursula@spammy.example").await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    app.post_remove_domain_rule("spammy.example").await;
+    let response = subscribe(&app, "ursula@spammy.example").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_rule_for_an_internationalized_domain_matches_its_addresses() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.login().await;
+
+    app.post_domain_rule("Bücher.de", "block").await;
+
+    for email in ["ursula@bücher.de", "ursula@xn--bcher-kva.de"] {
+        let response = subscribe(&app, email).await;
+        assert_eq!(response.status().as_u16(), 400, "{} was accepted", email);
+    }
+}
+
+#[tokio::test]
+async fn an_allow_rule_overrides_the_disposable_domain_list() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.login().await;
+
+    app.post_domain_rule("mailinator.com", "allow").await;
+    let response = subscribe(&app, "ursula@mailinator.com").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn the_most_specific_domain_rule_wins() {
+    let app = spawn_app().await;
+    mock_email_server(&app).await;
+    app.login().await;
+
+    app.post_domain_rule("example.com", "allow").await;
+    app.post_domain_rule("evil.example.com", "block").await;
+    app.post_domain_rule("corp.example.net", "allow").await;
+    app.post_domain_rule("example.net", "block").await;
+
+    for (email, status) in [
+        ("ursula@example.com", 200),
+        ("ursula@evil.example.com", 400),
+        ("ursula@mail.evil.example.com", 400),
+        ("ursula@example.net", 400),
+        ("ursula@corp.example.net", 200),
+    ] {
+        let response = subscribe(&app, email).await;
+        assert_eq!(response.status().as_u16(), status, "{}", email);
+    }
+}
+
+#[tokio::test]
+async fn invalid_domain_rules_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    app.post_domain_rule("not a domain", "block").await;
+    assert!(app.get_domains_html().await.contains("is not a valid domain."));
+    app.post_domain_rule("example.com", "maybe").await;
+    assert!(app.get_domains_html().await.contains("is not a supported domain rule."));
+}
+
+#[tokio::test]
+async fn subscribers_cannot_change_their_address_to_a_blocked_domain() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+    let query: Vec<(String, String)> = link.query_pairs().into_owned().collect();
+    let param = |name: &str| query.iter().find(|(k, _)| k == name).unwrap().1.clone();
+
+    let response = app.post_preferences(&[
+        ("subscriber_id", param("subscriber_id").as_str()),
+        ("signature", param("signature").as_str()),
+        ("name", "le guin"),
+        ("email", "ursula@yopmail.com"),
+        ("frequency", "every_issue"),
+        ("list", "general"),
+    ]).await;
+
+    assert_is_redirect_to(&response, &format!("{}?{}", link.path(), link.query().unwrap()));
+    let html = app.get_preferences_html(&link).await;
+    assert!(html.contains("disposable email provider"));
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn admins_cannot_add_subscribers_from_a_blocked_domain() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_domain_rule("spammy.example", "block").await;
+
+    let response = app
+        .post_admin_subscriber_action("", &[("email", "ursula@spammy.example"), ("name", "Ursula"), ("confirmed", "on")])
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html.contains("Email addresses at spammy.example are not accepted."));
+    let count = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 0);
+}
@@ -0,0 +1,105 @@
+//! tests/api/email_templates.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_edit_email_templates() {
+    let app = spawn_app().await;
+
+    let response = app.get_email_template("confirmation").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_email_template("confirmation", &serde_json::json!({
+        "subject": "Hi",
+        "html_body": "Hi",
+        "text_body": "Hi",
+    })).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn confirmation_emails_use_the_customized_template() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_email_template("confirmation", &serde_json::json!({
+        "subject": "Please confirm, {{subscriber_name}}",
+        "html_body": "<p>Hi {{subscriber_name}}, <a href=\"{{confirmation_link}}\">confirm</a></p>",
+        "text_body": "Hi {{subscriber_name}}, confirm at {{confirmation_link}}",
+    })).await;
+    assert_is_redirect_to(&response, "/admin/templates/confirmation?locale=en");
+    let html = app.get_email_template_html("confirmation").await;
+    assert!(html.contains("<p><i>The template has been saved.</i></p>"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Please confirm, le guin");
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Hi le guin, confirm at http"));
+    let links = app.get_confirmation_links(email_request);
+    assert_eq!(links.html, links.plain_text);
+}
+
+#[tokio::test]
+async fn templates_with_unknown_variables_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_email_template("confirmation", &serde_json::json!({
+        "subject": "Welcome",
+        "html_body": "Confirm at {{confirmation_url}}",
+        "text_body": "Confirm at {{confirmation_link}}",
+    })).await;
+    assert_is_redirect_to(&response, "/admin/templates/confirmation?locale=en");
+
+    let html = app.get_email_template_html("confirmation").await;
+    assert!(html.contains("{{confirmation_url}} is not a known variable."));
+    assert!(html.contains("Welcome to our newsletter!"));
+}
+
+#[tokio::test]
+async fn resetting_a_template_restores_the_default() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_email_template("welcome", &serde_json::json!({
+        "subject": "Custom subject",
+        "html_body": "Custom body",
+        "text_body": "Custom body",
+    })).await;
+
+    let response = app.post_reset_email_template("welcome").await;
+    assert_is_redirect_to(&response, "/admin/templates/welcome?locale=en");
+
+    let html = app.get_email_template_html("welcome").await;
+    assert!(html.contains("The template has been reset to its default."));
+    assert!(!html.contains("Custom subject"));
+    assert!(html.contains("Welcome aboard"));
+}
+
+#[tokio::test]
+async fn the_password_reset_template_can_be_customized() {
+    let app = spawn_app().await;
+    app.login().await;
+    let html = app.get_email_template_html("password_reset").await;
+    assert!(html.contains("Reset your password"));
+
+    let response = app.post_email_template("password_reset", &serde_json::json!({
+        "subject": "New password for {{username}}",
+        "html_body": "<a href=\"{{reset_link}}\">Choose a new password</a>",
+        "text_body": "Choose a new password at {{reset_link}}",
+    })).await;
+    assert_is_redirect_to(&response, "/admin/templates/password_reset?locale=en");
+
+    let html = app.get_email_template_html("password_reset").await;
+    assert!(html.contains("<p><i>The template has been saved.</i></p>"));
+    assert!(html.contains("New password for {{username}}"));
+}
@@ -0,0 +1,111 @@
+//! tests/api/export.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to, create_confirmed_subscriber, create_pending_subscriber, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// A confirmed subscriber and a pending one.
+async fn create_subscribers(app: &TestApp) {
+    create_confirmed_subscriber(app).await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=Ted%20Chiang&email=ted%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+fn csv_records(body: &str) -> Vec<csv::StringRecord> {
+    csv::Reader::from_reader(body.as_bytes())
+        .records()
+        .map(Result::unwrap)
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscriber_export("format=csv").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv() {
+    let app = spawn_app().await;
+    create_subscribers(&app).await;
+    app.login().await;
+
+    let response = app.get_subscriber_export("format=csv").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "text/csv; charset=utf-8");
+    assert!(response.headers()["Content-Disposition"].to_str().unwrap().contains("subscribers.csv"));
+    let body = response.text().await.unwrap();
+    assert!(body.starts_with("id,email,name,status,locale,tags,subscribed_at,confirmed_at,unsubscribed_at,attributes\n"));
+    let records = csv_records(&body);
+    assert_eq!(records.len(), 2);
+    assert_eq!(&records[0][1], "ursula_le_guin@gmail.com");
+    assert_eq!(&records[0][3], "confirmed");
+    assert!(!records[0][7].is_empty());
+    assert_eq!(&records[1][1], "ted@example.com");
+    assert_eq!(&records[1][3], "pending_confirmation");
+    assert!(records[1][7].is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_ndjson_filtered_by_status() {
+    let app = spawn_app().await;
+    create_subscribers(&app).await;
+    app.login().await;
+
+    let response = app.get_subscriber_export("format=ndjson&status=pending_confirmation&from=&to=").await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let lines: Vec<serde_json::Value> = body.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0]["email"], "ted@example.com");
+    assert_eq!(lines[0]["tags"], serde_json::json!([]));
+    assert_eq!(lines[0]["confirmed_at"], serde_json::Value::Null);
+}
+
+#[tokio::test]
+async fn the_export_can_be_limited_to_a_date_range() {
+    let app = spawn_app().await;
+    create_pending_subscriber(&app).await;
+    app.login().await;
+    let today = chrono::Utc::now().date_naive();
+
+    let before = app.get_subscriber_export("format=csv&from=2000-01-01&to=2000-12-31").await;
+    let including_today = app
+        .get_subscriber_export(&format!("format=csv&from={}&to={}", today, today))
+        .await;
+
+    assert!(csv_records(&before.text().await.unwrap()).is_empty());
+    assert_eq!(csv_records(&including_today.text().await.unwrap()).len(), 1);
+}
+
+#[tokio::test]
+async fn an_invalid_filter_is_reported_on_the_export_page() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.get_subscriber_export("format=csv&from=01/05/2025").await;
+
+    assert_is_redirect_to(&response, "/admin/export");
+    let html = app.api_client
+        .get(&format!("{}/admin/export", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("01/05/2025 is not a valid date, use YYYY-MM-DD."));
+}
@@ -2,14 +2,18 @@
 
 use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
 use uuid::Uuid;
-use wiremock::MockServer;
-use zero2prod::configurations::{get_configuration, DatabaseSettings};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::matchers::{method, path};
+use zero2prod::configurations::{get_configuration, DatabaseSettings, MaintenanceSettings, Settings};
+use zero2prod::email_client::EmailClient;
+use zero2prod::maintenance_worker::{run_maintenance, MaintenanceReport};
 // use sqlx::{PgConnection, Connection};
 use sqlx::{Connection, PgConnection, PgPool, Executor};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 use once_cell::sync::Lazy;
 use zero2prod::startup;
-// use secrecy::ExposeSecret;
+use secrecy::ExposeSecret;
+use hmac::{Hmac, Mac};
 
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info".to_string();
@@ -32,6 +36,9 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub webhook_secret: String,
+    pub email_client: EmailClient,
+    pub base_url: String,
 }
 
 pub struct ConfirmationLinks {
@@ -51,6 +58,40 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscriptions_with_language(
+        &self,
+        body: String,
+        accept_language: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions", &self.address))
+            .header("Content-type", "application/x-www-form-urlencoded")
+            .header("Accept-Language", accept_language)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/resend", &self.address))
+            .header("Content-type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub fn get_confirmation_links(
         &self,
         email_request: &wiremock::Request,
@@ -91,6 +132,55 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    /// Publishes a newsletter and returns the unsubscribe link of its (only) recipient.
+    pub async fn get_unsubscribe_link(&self) -> reqwest::Url {
+        self.get_newsletter_link("/subscriptions/unsubscribe").await
+    }
+
+    /// Publishes a newsletter and returns the preferences link of its (only) recipient.
+    pub async fn get_preferences_link(&self) -> reqwest::Url {
+        self.get_newsletter_link("/subscriptions/preferences").await
+    }
+
+    async fn get_newsletter_link(&self, link_path: &str) -> reqwest::Url {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount_as_scoped(&self.email_server)
+            .await;
+        self.post_newsletters(serde_json::json!({
+            "title": "newsletter title",
+            "content": {
+                "text": "newsletter content",
+                "html": "<p>newsletter content</p>"
+            }
+        })).await;
+
+        let email_requests = self.email_server.received_requests().await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+        let link = linkify::LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .find(|l| l.as_str().contains(link_path))
+            .unwrap()
+            .as_str()
+            .to_owned();
+        let mut link = reqwest::Url::parse(&link).unwrap();
+        link.set_port(Some(self.port)).unwrap();
+        link
+    }
+
+    pub async fn post_unsubscribe<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/subscriptions/unsubscribe", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     // pub async fn test_user(&self) -> (String, String) {
     //     let row = sqlx::query!("SELECT username, password FROM users LIMIT 1")
     //         .fetch_one(&self.db_pool)
@@ -115,6 +205,14 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn login(&self) {
+        let response = self.post_login(&serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password
+        })).await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
     pub async fn get_login_html(&self) -> String {
         // reqwest::Client::new()
         self.api_client
@@ -167,6 +265,310 @@ impl TestApp {
         self.get_change_password().await.text().await.unwrap()
     }
 
+    pub async fn post_email_event(&self, body: &serde_json::Value) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+        let signature = {
+            let mut mac = Hmac::<sha2::Sha256>::new_from_slice(self.webhook_secret.as_bytes()).unwrap();
+            mac.update(&body);
+            hex::encode(mac.finalize().into_bytes())
+        };
+        self.api_client
+            .post(&format!("{}/webhooks/postmark", &self.address))
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Signature", signature)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.get_suppressions().await.text().await.unwrap()
+    }
+
+    pub async fn post_suppression<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/suppressions", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_import_suppressions<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/suppressions/import", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_email_template(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/templates/{}", &self.address, name))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_email_template_html(&self, name: &str) -> String {
+        self.get_email_template(name).await.text().await.unwrap()
+    }
+
+    pub async fn post_email_template<Body>(&self, name: &str, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/templates/{}", &self.address, name))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_reset_email_template(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/templates/{}/reset", &self.address, name))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_preferences_html(&self, link: &reqwest::Url) -> String {
+        self.api_client
+            .get(link.clone())
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_preferences<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize + ?Sized,
+    {
+        self.api_client
+            .post(&format!("{}/subscriptions/preferences", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/lists", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_list<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/lists", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_domains_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/domains", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_domain_rule(&self, domain: &str, rule: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/domains", &self.address))
+            .form(&[("domain", domain), ("rule", rule)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_remove_domain_rule(&self, domain: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/domains/remove", &self.address))
+            .form(&[("domain", domain)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_data_request(&self, email: &str, request: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/data", &self.address))
+            .form(&[("email", email), ("request", request)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_admin_data_request(&self, action: &str, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/data-requests/{}", &self.address, action))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Uploads a CSV to the import dry run, built by hand as reqwest is
+    /// compiled without multipart support.
+    pub async fn post_subscriber_import(&self, csv: &str, send_confirmations: bool) -> reqwest::Response {
+        self.post_subscriber_import_into(csv, send_confirmations, "").await
+    }
+
+    /// Uploads `csv` as collected for the list `list_key`, empty for none.
+    pub async fn post_subscriber_import_into(
+        &self,
+        csv: &str,
+        send_confirmations: bool,
+        list_key: &str,
+    ) -> reqwest::Response {
+        let boundary = "zero2prod-import-boundary";
+        let mut body = format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"subscribers.csv\"\r\n\
+            Content-Type: text/csv\r\n\r\n\
+            {csv}\r\n"
+        );
+        if send_confirmations {
+            body.push_str(&format!(
+                "--{boundary}\r\n\
+                Content-Disposition: form-data; name=\"send_confirmations\"\r\n\r\n\
+                on\r\n"
+            ));
+        }
+        body.push_str(&format!(
+            "--{boundary}\r\n\
+            Content-Disposition: form-data; name=\"list\"\r\n\r\n\
+            {list_key}\r\n"
+        ));
+        body.push_str(&format!("--{boundary}--\r\n"));
+        self.api_client
+            .post(&format!("{}/admin/import", &self.address))
+            .header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_confirm_import(&self, import_path: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}{}", &self.address, import_path))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_import_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/import", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Posts to `/admin/subscribers` followed by `action`, e.g. `/{id}/name`.
+    pub async fn post_admin_subscriber_action<Body>(&self, action: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/subscribers{}", &self.address, action))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_attributes_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/attributes", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_attribute<Body>(&self, body: &Body) -> reqwest::Response
+        where Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/attributes", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_delete_attribute(&self, key: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/attributes/{}/delete", &self.address, key))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber_export(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/export/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Runs the maintenance job as if it were `now`.
+    pub async fn run_maintenance(
+        &self,
+        settings: &MaintenanceSettings,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> MaintenanceReport {
+        run_maintenance(&self.db_pool, &self.email_client, &self.base_url, settings, now)
+            .await
+            .unwrap()
+    }
+
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/admin/logout", &self.address))
@@ -217,6 +619,11 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, with a chance to tweak the configuration first.
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     // zero2prod::run().await
 
@@ -226,6 +633,7 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        customize(&mut c);
         c
     };
 
@@ -245,20 +653,24 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
 
+    let db_pool = startup::get_connection_pool(&configuration.database);
     let test_app =TestApp {
         address,
-        db_pool: startup::get_connection_pool(&configuration.database),
+        email_client: startup::get_email_client(configuration.email_client.clone(), &db_pool),
+        base_url: configuration.application.base_url.clone(),
+        db_pool,
         email_server,
         port,
         test_user: TestUser::create(),
         api_client: client,
+        webhook_secret: configuration.email_client.webhook_secret.expose_secret().to_owned(),
     };
     test_app.test_user.save(&test_app.db_pool).await;
     
     test_app
 }
 
-async fn configure_database(config: &DatabaseSettings) -> PgPool {
+pub async fn configure_database(config: &DatabaseSettings) -> PgPool {
     // let mut connection = PgConnection::connect(&config.connection_string_without_db().expose_secret())
     let mut connection = PgConnection::connect_with(&config.without_db())
         .await
@@ -292,6 +704,40 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 //     .expect("Failed to create test user");
 // }
 
+pub async fn create_pending_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create Pending Subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_links = create_pending_subscriber(app).await;
+
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
@@ -0,0 +1,217 @@
+//! tests/api/import.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to, create_confirmed_subscriber, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use chrono::{Duration, Utc};
+use zero2prod::configurations::MaintenanceSettings;
+
+const CSV: &str = "email,name,tags,status
+octavia@example.com,Octavia Butler,sf; classics,confirmed
+ted@example.com,Ted Chiang,,
+james@example.com,James Tiptree,,unsubscribed";
+
+/// Uploads `csv` and returns the dry run page with the path its form posts to.
+async fn dry_run(app: &TestApp, csv: &str, send_confirmations: bool) -> (String, String) {
+    let response = app.post_subscriber_import(csv, send_confirmations).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    let import_path = html
+        .split(r#"<form action=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap_or_default()
+        .to_owned();
+    (html, import_path)
+}
+
+async fn subscriber_count(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriber_import(CSV, false).await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_dry_run_reports_invalid_and_duplicate_rows_without_importing() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    let csv = "email,name
+octavia@example.com,Octavia Butler
+not-an-address,Someone
+OCTAVIA@example.com,Octavia
+Ursula_Le_Guin@gmail.com,Ursula Le Guin
+ted@mailinator.com,Ted";
+    let (html, import_path) = dry_run(&app, csv, false).await;
+
+    assert!(html.contains("1 valid, 2 invalid, 2 duplicates."));
+    assert!(html.contains("Repeats line 2."));
+    assert!(html.contains("Already a subscriber."));
+    assert!(html.contains("disposable email provider"));
+    assert!(import_path.starts_with("/admin/import/"));
+    assert_eq!(subscriber_count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn confirming_the_dry_run_imports_the_valid_rows() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+
+    let (_, import_path) = dry_run(&app, CSV, true).await;
+    let response = app.post_confirm_import(&import_path).await;
+
+    assert_is_redirect_to(&response, "/admin/import");
+    let html = app.get_import_html().await;
+    assert!(html.contains("Imported 3 subscribers, skipped 0 duplicates and 0 invalid rows."));
+    assert!(html.contains("Sent 1 confirmation emails."));
+
+    let saved = sqlx::query!("SELECT email, status, tags FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let saved: Vec<_> = saved.iter().map(|r| (r.email.as_str(), r.status.as_str(), r.tags.clone())).collect();
+    assert_eq!(
+        saved,
+        [
+            ("james@example.com", "unsubscribed", vec![]),
+            ("octavia@example.com", "confirmed", vec!["sf".to_owned(), "classics".to_owned()]),
+            ("ted@example.com", "pending_confirmation", vec![]),
+        ],
+    );
+    // only the pending subscriber was sent a link
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ted@example.com");
+
+    let sources = sqlx::query!(r#"SELECT DISTINCT source FROM consent_events"#)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].source, "import");
+}
+
+#[tokio::test]
+async fn an_upload_is_imported_only_once() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+
+    let (_, import_path) = dry_run(&app, CSV, false).await;
+    app.post_confirm_import(&import_path).await;
+    let response = app.post_confirm_import(&import_path).await;
+
+    assert_is_redirect_to(&response, "/admin/import");
+    assert!(app.get_import_html().await.contains("This file has already been imported."));
+    assert_eq!(subscriber_count(&app).await, 3);
+}
+
+#[tokio::test]
+async fn pending_rows_imported_without_confirmations_are_not_reminded_or_deleted() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+
+    let (_, import_path) = dry_run(&app, CSV, false).await;
+    app.post_confirm_import(&import_path).await;
+    let settings = MaintenanceSettings::default();
+    let report = app.run_maintenance(&settings, Utc::now() + Duration::hours(settings.reminder_after_hours + 1)).await;
+    assert_eq!(report.reminders_sent, 0);
+    let report = app.run_maintenance(&settings, Utc::now() + Duration::days(settings.pending_retention_days + 1)).await;
+    assert_eq!(report.subscribers_deleted, 0);
+
+    let saved = sqlx::query!(
+        "SELECT reminder_sent_at, confirmation_requested FROM subscriptions WHERE email = 'ted@example.com'",
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.reminder_sent_at.is_none());
+    assert!(!saved.confirmation_requested);
+}
+
+async fn stored_uploads(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT csv FROM subscriber_imports")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.csv)
+        .collect()
+}
+
+#[tokio::test]
+async fn uploads_are_not_kept_once_imported() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let (_, import_path) = dry_run(&app, CSV, false).await;
+    assert_eq!(stored_uploads(&app).await, [CSV]);
+    app.post_confirm_import(&import_path).await;
+
+    assert_eq!(stored_uploads(&app).await, [""]);
+}
+
+#[tokio::test]
+async fn uploads_never_imported_expire() {
+    let app = spawn_app().await;
+    app.login().await;
+    dry_run(&app, CSV, false).await;
+    let settings = MaintenanceSettings::default();
+
+    app.run_maintenance(&settings, Utc::now() + Duration::hours(settings.import_retention_hours - 1)).await;
+    assert_eq!(stored_uploads(&app).await.len(), 1);
+    app.run_maintenance(&settings, Utc::now() + Duration::hours(settings.import_retention_hours + 1)).await;
+    assert!(stored_uploads(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn erasing_an_address_drops_the_uploads_waiting_to_import_it() {
+    let app = spawn_app().await;
+    app.login().await;
+    dry_run(&app, CSV, false).await;
+    dry_run(&app, "email,name\nsomeone@example.com,Someone", false).await;
+
+    app.post_admin_data_request("erase", "TED@example.com").await;
+
+    assert_eq!(stored_uploads(&app).await, ["email,name\nsomeone@example.com,Someone"]);
+}
+
+#[tokio::test]
+async fn a_file_without_the_required_columns_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_subscriber_import("address,full_name\nted@example.com,Ted", false).await;
+
+    assert_is_redirect_to(&response, "/admin/import");
+    assert!(app.get_import_html().await.contains("must have an &#x27;email&#x27; and a &#x27;name&#x27; column"));
+}
@@ -0,0 +1,102 @@
+//! tests/api/localization.rs
+
+use crate::helpers::spawn_app;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn the_confirmation_email_is_sent_in_the_accept_language_of_the_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_with_language(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com".into(),
+        "zh-CN,zh;q=0.9,en;q=0.8",
+    ).await;
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.locale, "zh");
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "欢迎订阅");
+    let links = app.get_confirmation_links(email_request);
+    assert_eq!(links.html, links.plain_text);
+}
+
+#[tokio::test]
+async fn the_locale_form_field_takes_precedence_over_the_accept_language_header() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_with_language(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=en".into(),
+        "zh-CN",
+    ).await;
+
+    let saved = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.locale, "en");
+}
+
+#[tokio::test]
+async fn unsupported_languages_fall_back_to_english() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr".into(),
+    ).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["Subject"], "Welcome");
+}
+
+#[tokio::test]
+async fn public_pages_are_rendered_in_the_requested_language() {
+    let app = spawn_app().await;
+
+    let html = app.api_client
+        .get(&format!("{}/", &app.address))
+        .header("Accept-Language", "zh-TW")
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"<html lang="zh">"#));
+    assert!(html.contains("欢迎订阅我们的电子报！"));
+
+    let html = app.api_client
+        .get(&format!("{}/login?lang=en", &app.address))
+        .header("Accept-Language", "zh-TW")
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"<button type="submit">Login</button>"#));
+}
@@ -7,4 +7,22 @@ mod subscriptions_confirm;
 mod newsletter;
 mod login;
 mod admin_dashboard;
-mod password;
\ No newline at end of file
+mod password;
+mod webhooks;
+mod suppressions;
+mod email_templates;
+mod localization;
+mod unsubscribe;
+mod preferences;
+mod opt_in;
+mod bot_protection;
+mod domain_policy;
+mod data_requests;
+mod consent;
+mod maintenance;
+mod import;
+mod export;
+mod subscribers;
+mod attributes;
+mod acquisition;
+mod startup;
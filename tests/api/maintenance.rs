@@ -0,0 +1,126 @@
+//! tests/api/maintenance.rs
+
+use chrono::{Duration, Utc};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configurations::MaintenanceSettings;
+use zero2prod::maintenance_worker::MaintenanceReport;
+
+use crate::helpers::{spawn_app, create_confirmed_subscriber, create_pending_subscriber};
+
+#[tokio::test]
+async fn pending_subscribers_get_a_single_reminder_with_a_working_link() {
+    let app = spawn_app().await;
+    create_pending_subscriber(&app).await;
+    let settings = MaintenanceSettings::default();
+    let later = Utc::now() + Duration::hours(settings.reminder_after_hours + 1);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let report = app.run_maintenance(&settings, later).await;
+    assert_eq!(report.reminders_sent, 1);
+    // the reminder's own link is older than the delay too, but nobody is reminded twice
+    let report = app.run_maintenance(&settings, later + Duration::hours(settings.reminder_after_hours + 1)).await;
+    assert_eq!(report.reminders_sent, 0);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn recent_pending_subscribers_are_left_alone() {
+    let app = spawn_app().await;
+    create_pending_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let report = app.run_maintenance(&MaintenanceSettings::default(), Utc::now()).await;
+
+    assert_eq!(report, MaintenanceReport::default());
+}
+
+#[tokio::test]
+async fn stale_pending_subscribers_are_deleted_with_their_tokens() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET email = 'confirmed@example.com'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    create_pending_subscriber(&app).await;
+    let settings = MaintenanceSettings::default();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let report = app.run_maintenance(&settings, Utc::now() + Duration::days(settings.pending_retention_days + 1)).await;
+
+    assert_eq!(report.subscribers_deleted, 1);
+    assert_eq!(report.tokens_deleted, 2);
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+    // the consent trail of the deleted subscriber stays, without network details
+    let detached = sqlx::query!("SELECT ip_address FROM consent_events WHERE subscriber_id IS NULL",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(detached.len(), 1);
+    assert!(detached[0].ip_address.is_none());
+}
+
+#[tokio::test]
+async fn the_reminder_does_not_postpone_the_clean_up() {
+    let app = spawn_app().await;
+    create_pending_subscriber(&app).await;
+    let settings = MaintenanceSettings::default();
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '2 days'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let report = app.run_maintenance(&settings, Utc::now()).await;
+    assert_eq!(report.reminders_sent, 1);
+    assert_eq!(report.subscribers_deleted, 0);
+
+    // the first link is past the retention, the reminder's is not
+    let report = app.run_maintenance(&settings, Utc::now() + Duration::days(settings.pending_retention_days - 1)).await;
+    assert_eq!(report.subscribers_deleted, 1);
+}
+
+#[tokio::test]
+async fn the_dashboard_shows_the_last_maintenance_run() {
+    let app = spawn_app().await;
+    app.login().await;
+    assert!(app.get_admin_dashboard_html().await.contains("The maintenance job has not run yet."));
+
+    app.run_maintenance(&MaintenanceSettings::default(), Utc::now()).await;
+
+    let html = app.get_admin_dashboard_html().await;
+    assert!(html.contains("0 reminder(s) sent, 0 unconfirmed subscriber(s) and 0 token(s) deleted."));
+}
@@ -1,6 +1,6 @@
 //! tests/api/newsletter.rs
 
-use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use crate::helpers::{spawn_app, create_confirmed_subscriber, create_pending_subscriber};
 use uuid::Uuid;
 use wiremock::matchers::{method, path, any};
 use wiremock::{Mock, ResponseTemplate};
@@ -32,40 +32,6 @@ async fn newsletters_are_not_delivered_to_pending_subscribers() {
     assert_eq!(reponse.status().as_u16(), 200);
 }
 
-async fn create_pending_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
-
-    let _mock_guard = Mock::given(path("/email"))
-        .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create Pending Subscriber")
-        .expect(1)
-        .mount_as_scoped(&app.email_server)
-        .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
-
-    let email_request = &app.email_server
-        .received_requests()
-        .await
-        .unwrap()
-        .pop()
-        .unwrap();
-    app.get_confirmation_links(&email_request)
-}
-
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_links = create_pending_subscriber(app).await;
-
-    reqwest::get(confirmation_links.html)
-        .await
-        .unwrap()
-        .error_for_status()
-        .unwrap();
-}
-
 #[tokio::test]
 async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Arrange
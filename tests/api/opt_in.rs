@@ -0,0 +1,282 @@
+//! tests/api/opt_in.rs
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::OptInMode;
+
+async fn subscriber_status(app: &TestApp, email: &str) -> String {
+    sqlx::query!("SELECT status FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+async fn last_email_subject(app: &TestApp) -> String {
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    body["Subject"].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn single_opt_in_confirms_right_away_and_sends_a_welcome_email() {
+    let app = spawn_app_with(|c| c.application.opt_in_mode = OptInMode::Single).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app, "ursula_le_guin@gmail.com").await, "confirmed");
+    assert_eq!(last_email_subject(&app).await, "Welcome aboard");
+    let tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(tokens.is_empty());
+}
+
+#[tokio::test]
+async fn the_public_form_can_require_double_opt_in_through_a_list_but_not_waive_it() {
+    let app = spawn_app_with(|c| c.application.opt_in_mode = OptInMode::Single).await;
+    app.login().await;
+    app.post_list(&serde_json::json!({
+        "key": "events",
+        "name": "Events",
+        "description": "Attendees of our events",
+        "opt_in_mode": "double",
+    })).await;
+    app.post_list(&serde_json::json!({
+        "key": "partners",
+        "name": "Partners",
+        "description": "Addresses from our partners",
+        "opt_in_mode": "single",
+    })).await;
+    let html = app.get_lists_html().await;
+    assert!(html.contains("<td>Double opt-in</td>"));
+    assert!(html.contains("<td>Single opt-in</td>"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=ursula&email=ursula%40gmail.com&list=events".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_status(&app, "ursula@gmail.com").await, "pending_confirmation");
+    assert_eq!(last_email_subject(&app).await, "Welcome");
+
+    // without a stricter list the global single opt-in applies
+    app.post_subscriptions("name=le%20guin&email=le_guin%40gmail.com&list=partners".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_status(&app, "le_guin@gmail.com").await, "confirmed");
+    assert_eq!(last_email_subject(&app).await, "Welcome aboard");
+}
+
+#[tokio::test]
+async fn a_single_opt_in_list_does_not_waive_double_opt_in_on_the_public_form() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_list(&serde_json::json!({
+        "key": "partners",
+        "name": "Partners",
+        "description": "Addresses from our partners",
+        "opt_in_mode": "single",
+    })).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=ursula&email=ursula%40gmail.com&list=partners".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_status(&app, "ursula@gmail.com").await, "pending_confirmation");
+    assert_eq!(last_email_subject(&app).await, "Welcome");
+}
+
+#[tokio::test]
+async fn importing_into_a_single_opt_in_list_confirms_the_rows_without_a_status() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_list(&serde_json::json!({
+        "key": "partners",
+        "name": "Partners",
+        "description": "Addresses from our partners",
+        "opt_in_mode": "single",
+    })).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "email,name,tags,status\nted@example.com,Ted Chiang,,\njames@example.com,James Tiptree,,unsubscribed";
+    let response = app.post_subscriber_import_into(csv, true, "partners").await;
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Importing into Partners, which uses single opt-in"));
+    let import_path = html
+        .split(r#"<form action=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+    let response = app.post_confirm_import(import_path).await;
+
+    assert_is_redirect_to(&response, "/admin/import");
+    let html = app.get_import_html().await;
+    assert!(html.contains("Sent 1 welcome emails."));
+    assert_eq!(subscriber_status(&app, "ted@example.com").await, "confirmed");
+    assert_eq!(subscriber_status(&app, "james@example.com").await, "unsubscribed");
+    assert_eq!(last_email_subject(&app).await, "Welcome aboard");
+}
+
+async fn newsletter_for_list(app: &TestApp, list: &str) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        },
+        "list": list,
+    })).await
+}
+
+#[tokio::test]
+async fn a_single_opt_in_import_into_a_list_only_grants_that_list() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.post_list(&serde_json::json!({
+        "key": "partners",
+        "name": "Partners",
+        "description": "Addresses from our partners",
+        "opt_in_mode": "single",
+    })).await;
+    let csv = "email,name,tags,status\nted@example.com,Ted Chiang,,";
+    let html = app.post_subscriber_import_into(csv, false, "partners").await.text().await.unwrap();
+    let import_path = html
+        .split(r#"<form action=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .unwrap();
+
+    let _welcome = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_confirm_import(import_path).await;
+    drop(_welcome);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = newsletter_for_list(&app, "general").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribing_through_a_list_only_grants_that_list() {
+    let app = spawn_app_with(|c| c.application.opt_in_mode = OptInMode::Single).await;
+    app.login().await;
+    app.post_list(&serde_json::json!({
+        "key": "events",
+        "name": "Events",
+        "description": "Attendees of our events",
+        "opt_in_mode": "",
+    })).await;
+
+    let _welcome = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions("name=ursula&email=ursula%40gmail.com&list=events".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(subscriber_status(&app, "ursula@gmail.com").await, "confirmed");
+    drop(_welcome);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    newsletter_for_list(&app, "general").await.error_for_status().unwrap();
+    newsletter_for_list(&app, "events").await.error_for_status().unwrap();
+}
+
+#[tokio::test]
+async fn importing_into_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let csv = "email,name,tags,status\nted@example.com,Ted Chiang,,";
+    let response = app.post_subscriber_import_into(csv, false, "does_not_exist").await;
+
+    assert_is_redirect_to(&response, "/admin/import");
+    assert!(app.get_import_html().await.contains("There is no list named does_not_exist."));
+}
+
+#[tokio::test]
+async fn single_opt_in_does_not_confirm_subscribers_who_left_again() {
+    let app = spawn_app_with(|c| c.application.opt_in_mode = OptInMode::Single).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app, "ursula_le_guin@gmail.com").await, "pending_confirmation");
+    assert_eq!(last_email_subject(&app).await, "Welcome");
+}
+
+#[tokio::test]
+async fn subscribing_through_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "list": "does_not_exist",
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["errors"][0]["field"], "list");
+}
@@ -0,0 +1,350 @@
+//! tests/api/preferences.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to, create_confirmed_subscriber, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn query_param(link: &reqwest::Url, name: &str) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+fn preferences_location(link: &reqwest::Url) -> String {
+    format!("{}?{}", link.path(), link.query().unwrap())
+}
+
+async fn newsletter_for_list(app: &TestApp, list: &str) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        },
+        "list": list,
+    })).await
+}
+
+#[tokio::test]
+async fn newsletters_link_to_the_preferences_of_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = app.get_preferences_link().await;
+    let html = app.get_preferences_html(&link).await;
+
+    assert!(html.contains(r#"name="name" value="le guin""#));
+    assert!(html.contains(r#"name="email" value="ursula_le_guin@gmail.com""#));
+    assert!(html.contains(r#"<input type="checkbox" name="list" value="general" checked>"#));
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_name_frequency_and_lists() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+
+    let subscriber_id = query_param(&link, "subscriber_id");
+    let signature = query_param(&link, "signature");
+    let response = app.post_preferences(&[
+        ("subscriber_id", subscriber_id.as_str()),
+        ("signature", signature.as_str()),
+        ("name", "Ursula"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("frequency", "monthly"),
+    ]).await;
+    assert_is_redirect_to(&response, &preferences_location(&link));
+
+    let html = app.get_preferences_html(&link).await;
+    assert!(html.contains("Your preferences have been saved."));
+    assert!(html.contains(r#"<input type="checkbox" name="list" value="general">"#));
+    let saved = sqlx::query!("SELECT name, frequency, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.frequency, "monthly");
+    assert_eq!(saved.status, "confirmed");
+
+    // opted out of the only list
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = newsletter_for_list(&app, "general").await;
+    assert_eq!(response.status().as_u16(), 200);
+    // newsletters without a list go to the general list
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        },
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn weekly_subscribers_skip_issues_of_a_list_published_within_a_week_of_their_last_one() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+    app.post_list(&serde_json::json!({
+        "key": "events",
+        "name": "Events",
+        "description": "Our upcoming events",
+        "opt_in_mode": "",
+    })).await;
+    sqlx::query!("UPDATE subscriptions SET frequency = 'weekly'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    newsletter_for_list(&app, "events").await.error_for_status().unwrap();
+    // an issue of another list does not hold back the general one
+    newsletter_for_list(&app, "general").await.error_for_status().unwrap();
+    newsletter_for_list(&app, "general").await.error_for_status().unwrap();
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), sent_before + 2);
+
+    sqlx::query!("UPDATE list_deliveries SET last_sent_at = now() - interval '8 days' WHERE list_key = 'general'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    newsletter_for_list(&app, "general").await.error_for_status().unwrap();
+    newsletter_for_list(&app, "events").await.error_for_status().unwrap();
+}
+
+#[tokio::test]
+async fn invalid_preferences_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+
+    let subscriber_id = query_param(&link, "subscriber_id");
+    let signature = query_param(&link, "signature");
+    let response = app.post_preferences(&[
+        ("subscriber_id", subscriber_id.as_str()),
+        ("signature", signature.as_str()),
+        ("name", "<script>"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("frequency", "weekly"),
+        ("list", "general"),
+    ]).await;
+    assert_is_redirect_to(&response, &preferences_location(&link));
+
+    let html = app.get_preferences_html(&link).await;
+    assert!(html.contains("is not a valid subscriber name."));
+    let saved = sqlx::query!("SELECT name, frequency FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.frequency, "every_issue");
+}
+
+#[tokio::test]
+async fn changing_the_email_address_requires_a_new_confirmation() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = query_param(&link, "subscriber_id");
+    let signature = query_param(&link, "signature");
+    app.post_preferences(&[
+        ("subscriber_id", subscriber_id.as_str()),
+        ("signature", signature.as_str()),
+        ("name", "le guin"),
+        ("email", "ursula@example.com"),
+        ("frequency", "every_issue"),
+        ("list", "general"),
+    ]).await;
+
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula@example.com");
+    assert_eq!(saved.status, "pending_confirmation");
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+}
+
+#[tokio::test]
+async fn subscribers_who_complained_cannot_change_their_email_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'complained'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = query_param(&link, "subscriber_id");
+    let signature = query_param(&link, "signature");
+    let response = app.post_preferences(&[
+        ("subscriber_id", subscriber_id.as_str()),
+        ("signature", signature.as_str()),
+        ("name", "Ursula"),
+        ("email", "ursula@example.com"),
+        ("frequency", "monthly"),
+    ]).await;
+    assert_is_redirect_to(&response, &preferences_location(&link));
+
+    let html = app.get_preferences_html(&link).await;
+    assert!(html.contains("The email address can only be changed while you are subscribed."));
+    let saved = sqlx::query!("SELECT name, email, status, frequency FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula");
+    assert_eq!(saved.frequency, "monthly");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "complained");
+}
+
+#[tokio::test]
+async fn an_address_taken_while_changing_to_it_is_reported() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+    // the other sign-up is not committed yet when the address is checked
+    let mut other = app.db_pool.begin().await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@example.com', 'Ursula', now(), 'pending_confirmation')
+        "#,
+        uuid::Uuid::new_v4(),
+    )
+    .execute(&mut *other)
+    .await
+    .unwrap();
+
+    let subscriber_id = query_param(&link, "subscriber_id");
+    let signature = query_param(&link, "signature");
+    let fields = [
+        ("subscriber_id", subscriber_id),
+        ("signature", signature),
+        ("name", "le guin".to_owned()),
+        ("email", "ursula@example.com".to_owned()),
+        ("frequency", "every_issue".to_owned()),
+    ];
+    let client = app.api_client.clone();
+    let url = format!("{}/subscriptions/preferences", &app.address);
+    let change = tokio::spawn(async move { client.post(url).form(&fields).send().await.unwrap() });
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    other.commit().await.unwrap();
+    let response = change.await.unwrap();
+    assert_is_redirect_to(&response, &preferences_location(&link));
+
+    let html = app.get_preferences_html(&link).await;
+    assert!(html.contains("This email address cannot be used."));
+    let saved = sqlx::query!("SELECT status FROM subscriptions WHERE email = 'ursula_le_guin@gmail.com'",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn changing_only_the_case_of_the_email_address_keeps_the_subscription_confirmed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_preferences_link().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let subscriber_id = query_param(&link, "subscriber_id");
+    let signature = query_param(&link, "signature");
+    app.post_preferences(&[
+        ("subscriber_id", subscriber_id.as_str()),
+        ("signature", signature.as_str()),
+        ("name", "le guin"),
+        ("email", "Ursula_Le_Guin@Gmail.com"),
+        ("frequency", "every_issue"),
+        ("list", "general"),
+    ]).await;
+
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "Ursula_Le_Guin@gmail.com");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn preferences_links_with_an_invalid_signature_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let unsubscribe_link = app.get_unsubscribe_link().await;
+
+    // a signature for another page is not valid here
+    let mut link = unsubscribe_link.clone();
+    link.set_path("/subscriptions/preferences");
+    let response = app.api_client.get(link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn newsletters_for_an_unknown_list_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = newsletter_for_list(&app, "does_not_exist").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn admins_can_add_newsletter_lists() {
+    let app = spawn_app().await;
+    let response = app.post_list(&serde_json::json!({
+        "key": "events",
+        "name": "Events",
+        "description": "Meetups and conferences",
+    })).await;
+    assert_is_redirect_to(&response, "/login");
+
+    app.login().await;
+    let response = app.post_list(&serde_json::json!({
+        "key": "Events",
+        "name": "Events",
+        "description": "Meetups and conferences",
+    })).await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    let html = app.get_lists_html().await;
+    assert!(html.contains("The list events has been added."));
+    assert!(html.contains("<td>Meetups and conferences</td>"));
+}
@@ -0,0 +1,52 @@
+//! tests/api/startup.rs
+
+use crate::helpers::configure_database;
+use chrono::Utc;
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod::configurations::{get_configuration, DkimSettings};
+use zero2prod::dkim::DkimAlgorithm;
+use zero2prod::startup::Application;
+
+#[tokio::test]
+async fn the_application_does_not_start_with_an_invalid_dkim_key() {
+    let mut configuration = get_configuration().expect("Failed to get configuration.");
+    configuration.application.port = 0;
+    configuration.email_client.dkim = Some(DkimSettings {
+        domain: "example.com".into(),
+        selector: "newsletter".into(),
+        algorithm: DkimAlgorithm::RsaSha256,
+        private_key: Secret::new("not a key".into()),
+    });
+
+    let result = Application::build(configuration).await;
+
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn stored_addresses_are_normalized_before_the_application_serves_requests() {
+    let mut configuration = get_configuration().expect("Failed to get configuration.");
+    configuration.application.port = 0;
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    let pool = configure_database(&configuration.database).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula@bücher.de', 'Ursula', $2, 'confirmed')
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    Application::build(configuration).await.expect("Failed to build application.");
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@xn--bcher-kva.de");
+}
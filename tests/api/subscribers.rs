@@ -0,0 +1,459 @@
+//! tests/api/subscribers.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to, create_pending_subscriber, TestApp};
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::startup::normalize_stored_addresses;
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str, subscribed_at: DateTime<Utc>) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        id,
+        email,
+        name,
+        subscribed_at,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to insert a subscriber.");
+    id
+}
+
+/// The addresses listed on a subscriber list page, in order.
+fn listed_emails(html: &str) -> Vec<String> {
+    html.split(r#"<td><a href="/admin/subscribers/"#)
+        .skip(1)
+        .map(|row| row.split_once("\">").unwrap().1.split_once("</a>").unwrap().0.to_owned())
+        .collect()
+}
+
+fn next_page_link(html: &str) -> Option<String> {
+    let (before, _) = html.split_once(">Next page</a>")?;
+    let href = before.rsplit_once(r#"<a href=""#)?.1.trim_end_matches('"');
+    Some(href.replace("&amp;", "&"))
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_browse_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get_subscribers("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered() {
+    let app = spawn_app().await;
+    let now = Utc::now();
+    insert_subscriber(&app, "octavia@example.com", "Octavia Butler", "confirmed", now - Duration::days(40)).await;
+    insert_subscriber(&app, "ted@example.com", "Ted Chiang", "pending_confirmation", now - Duration::days(2)).await;
+    insert_subscriber(&app, "erased-1@erased.invalid", "", "erased", now - Duration::days(1)).await;
+    insert_subscriber(&app, "ursula@example.org", "Ursula Le Guin", "unsubscribed", now).await;
+    app.login().await;
+
+    let all = app.get_subscribers("").await.text().await.unwrap();
+    let by_search = app.get_subscribers("search=BUTLER").await.text().await.unwrap();
+    let by_domain = app.get_subscribers("search=%40example.com&status=").await.text().await.unwrap();
+    let by_status = app.get_subscribers("status=pending_confirmation").await.text().await.unwrap();
+    let by_date = app
+        .get_subscribers(&format!("from={}&to={}", (now - Duration::days(3)).date_naive(), now.date_naive()))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let wildcard = app.get_subscribers("search=%25").await.text().await.unwrap();
+
+    // newest first, erased subscribers only when asked for
+    assert_eq!(listed_emails(&all), ["ursula@example.org", "ted@example.com", "octavia@example.com"]);
+    assert_eq!(listed_emails(&by_search), ["octavia@example.com"]);
+    assert_eq!(listed_emails(&by_domain), ["ted@example.com", "octavia@example.com"]);
+    assert_eq!(listed_emails(&by_status), ["ted@example.com"]);
+    assert_eq!(listed_emails(&by_date), ["ursula@example.org", "ted@example.com"]);
+    assert!(listed_emails(&wildcard).is_empty());
+    assert!(by_search.contains(r#"value="BUTLER""#));
+}
+
+#[tokio::test]
+async fn the_subscriber_list_is_paginated() {
+    let app = spawn_app().await;
+    let now = Utc::now();
+    for i in 0..55 {
+        insert_subscriber(&app, &format!("reader{:02}@example.com", i), "Reader", "confirmed", now - Duration::minutes(i)).await;
+    }
+    app.login().await;
+
+    let first_page = app.get_subscribers("search=reader").await.text().await.unwrap();
+    let next = next_page_link(&first_page).expect("The first page has no next page link");
+    let second_page = app.api_client
+        .get(&format!("{}{}", &app.address, next))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let first_emails = listed_emails(&first_page);
+    assert_eq!(first_emails.len(), 50);
+    assert_eq!(first_emails[0], "reader00@example.com");
+    assert_eq!(first_emails[49], "reader49@example.com");
+    assert!(next.contains("search=reader"));
+    assert_eq!(
+        listed_emails(&second_page),
+        ["reader50@example.com", "reader51@example.com", "reader52@example.com", "reader53@example.com", "reader54@example.com"],
+    );
+    assert_eq!(next_page_link(&second_page), None);
+    assert!(second_page.contains("First page"));
+}
+
+#[tokio::test]
+async fn an_invalid_cursor_is_reported() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.get_subscribers("cursor=not-a-cursor").await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html = app.get_subscribers("").await.text().await.unwrap();
+    assert!(html.contains("not-a-cursor is not a valid page cursor."));
+}
+
+#[tokio::test]
+async fn the_details_page_shows_history_tokens_and_deliveries() {
+    let app = spawn_app().await;
+    create_pending_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (id, subscriber_id, email, event_type, description, received_at)
+        VALUES ($1, $2, 'ursula_le_guin@gmail.com', 'soft_bounce', 'Mailbox <full>', now())
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.login().await;
+
+    let response = app.api_client
+        .get(&format!("{}/admin/subscribers/{}", &app.address, subscriber_id))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("<h2>ursula_le_guin@gmail.com</h2>"));
+    assert!(html.contains("Status: pending_confirmation"));
+    assert!(html.contains("<td>subscribed</td><td>form</td>"));
+    assert_eq!(html.matches("<tr><td>").count(), 3);
+    assert!(html.contains("soft_bounce"));
+    assert!(html.contains("Mailbox &lt;full&gt;"));
+}
+
+#[tokio::test]
+async fn an_unknown_subscriber_is_reported() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.api_client
+        .get(&format!("{}/admin/subscribers/{}", &app.address, Uuid::new_v4()))
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+}
+
+async fn get_details_html(app: &TestApp, subscriber_id: Uuid) -> String {
+    app.api_client
+        .get(&format!("{}/admin/subscribers/{}", &app.address, subscriber_id))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap()
+}
+
+async fn admin_actions(app: &TestApp, subscriber_id: Uuid) -> Vec<(String, Uuid)> {
+    sqlx::query!(
+        "SELECT action, user_id FROM admin_actions WHERE subscriber_id = $1 ORDER BY performed_at",
+        subscriber_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.action, r.user_id))
+    .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ted@example.com", "Ted Chiang", "pending_confirmation", Utc::now()).await;
+
+    for action in ["", "/name", "/confirm", "/unsubscribe", "/delete"] {
+        let path = if action.is_empty() { String::new() } else { format!("/{}{}", subscriber_id, action) };
+        let response = app
+            .post_admin_subscriber_action(&path, &[("email", "ted@example.com"), ("name", "Ted")])
+            .await;
+
+        assert_is_redirect_to(&response, "/login");
+    }
+    assert!(admin_actions(&app, subscriber_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn a_subscriber_added_by_hand_gets_a_confirmation_email() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+
+    let response = app
+        .post_admin_subscriber_action("", &[("email", "ted@example.com"), ("name", "Ted Chiang")])
+        .await;
+
+    let saved = sqlx::query!("SELECT id, status FROM subscriptions WHERE email = 'ted@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", saved.id));
+    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(admin_actions(&app, saved.id).await, [("added".to_owned(), app.test_user.user_id)]);
+    let html = get_details_html(&app, saved.id).await;
+    assert!(html.contains("ted@example.com has been added."));
+    assert!(html.contains(&format!("<td>added</td><td>{}</td>", app.test_user.username)));
+    assert!(html.contains("<td>subscribed</td><td>admin</td>"));
+}
+
+#[tokio::test]
+async fn a_subscriber_who_already_agreed_can_be_added_as_confirmed() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+
+    app.post_admin_subscriber_action(
+        "",
+        &[("email", "ted@example.com"), ("name", "Ted Chiang"), ("confirmed", "on")],
+    )
+    .await;
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions WHERE email = 'ted@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn invalid_or_known_addresses_are_not_added() {
+    let app = spawn_app().await;
+    let existing_id = insert_subscriber(&app, "ted@example.com", "Ted Chiang", "confirmed", Utc::now()).await;
+    app.login().await;
+
+    let invalid = app
+        .post_admin_subscriber_action("", &[("email", "not-an-address"), ("name", "Someone")])
+        .await;
+    let known = app
+        .post_admin_subscriber_action("", &[("email", "TED@example.com"), ("name", "Ted")])
+        .await;
+
+    assert_is_redirect_to(&invalid, "/admin/subscribers");
+    assert_is_redirect_to(&known, &format!("/admin/subscribers/{}", existing_id));
+    assert!(get_details_html(&app, existing_id).await.contains("TED@example.com is already a subscriber."));
+    let count = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn a_misspelled_name_can_be_corrected() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ted@example.com", "Ted Chaing", "confirmed", Utc::now()).await;
+    app.login().await;
+
+    let rejected = app.post_admin_subscriber_action(&format!("/{}/name", subscriber_id), &[("name", "Ted<Chiang>")]).await;
+    let response = app.post_admin_subscriber_action(&format!("/{}/name", subscriber_id), &[("name", "Ted Chiang")]).await;
+
+    assert_is_redirect_to(&rejected, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let saved = sqlx::query!("SELECT name FROM subscriptions").fetch_one(&app.db_pool).await.unwrap();
+    assert_eq!(saved.name, "Ted Chiang");
+    assert_eq!(admin_actions(&app, subscriber_id).await, [("renamed".to_owned(), app.test_user.user_id)]);
+}
+
+#[tokio::test]
+async fn only_pending_subscribers_can_be_confirmed_by_hand() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ted@example.com", "Ted Chiang", "pending_confirmation", Utc::now()).await;
+    app.login().await;
+
+    app.post_admin_subscriber_action(&format!("/{}/confirm", subscriber_id), &()).await;
+    app.post_admin_subscriber_action(&format!("/{}/confirm", subscriber_id), &()).await;
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions").fetch_one(&app.db_pool).await.unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert!(get_details_html(&app, subscriber_id).await.contains("Only subscribers pending confirmation can be confirmed."));
+    assert_eq!(admin_actions(&app, subscriber_id).await, [("confirmed".to_owned(), app.test_user.user_id)]);
+    let consent = sqlx::query!("SELECT event_type, source FROM consent_events").fetch_one(&app.db_pool).await.unwrap();
+    assert_eq!((consent.event_type.as_str(), consent.source.as_str()), ("confirmed", "admin"));
+}
+
+#[tokio::test]
+async fn a_subscriber_can_be_unsubscribed_by_hand() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ted@example.com", "Ted Chiang", "confirmed", Utc::now()).await;
+    app.login().await;
+
+    app.post_admin_subscriber_action(&format!("/{}/unsubscribe", subscriber_id), &[("reason", "Asked by phone")]).await;
+
+    let saved = sqlx::query!("SELECT status, unsubscribe_reason FROM subscriptions").fetch_one(&app.db_pool).await.unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+    assert_eq!(saved.unsubscribe_reason.as_deref(), Some("Asked by phone"));
+    assert_eq!(admin_actions(&app, subscriber_id).await, [("unsubscribed".to_owned(), app.test_user.user_id)]);
+}
+
+#[tokio::test]
+async fn unsubscribing_by_hand_keeps_a_bounce() {
+    let app = spawn_app().await;
+    let subscriber_id = insert_subscriber(&app, "ted@example.com", "Ted Chiang", "bounced", Utc::now()).await;
+    app.login().await;
+
+    app.post_admin_subscriber_action(&format!("/{}/unsubscribe", subscriber_id), &[("reason", "")]).await;
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions").fetch_one(&app.db_pool).await.unwrap();
+    assert_eq!(saved.status, "bounced");
+    assert!(admin_actions(&app, subscriber_id).await.is_empty());
+}
+
+#[tokio::test]
+async fn unsubscribing_by_hand_invalidates_pending_confirmation_links() {
+    let app = spawn_app().await;
+    let links = create_pending_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions").fetch_one(&app.db_pool).await.unwrap().id;
+    app.login().await;
+
+    app.post_admin_subscriber_action(&format!("/{}/unsubscribe", subscriber_id), &[("reason", "")]).await;
+
+    let tokens = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(tokens.count, 0);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_subscriber_can_be_deleted_on_request() {
+    let app = spawn_app().await;
+    create_pending_subscriber(&app).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions").fetch_one(&app.db_pool).await.unwrap().id;
+    app.login().await;
+
+    let response = app.post_admin_subscriber_action(&format!("/{}/delete", subscriber_id), &()).await;
+
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let remaining = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(remaining, 0);
+    // the consent history stays, detached from the deleted subscriber
+    let consent = sqlx::query!("SELECT subscriber_id FROM consent_events").fetch_one(&app.db_pool).await.unwrap();
+    assert_eq!(consent.subscriber_id, None);
+    assert_eq!(admin_actions(&app, subscriber_id).await, [("deleted".to_owned(), app.test_user.user_id)]);
+}
+
+#[tokio::test]
+async fn stored_unicode_domains_are_converted_like_new_input_and_duplicates_merged() {
+    let app = spawn_app().await;
+    let now = Utc::now();
+    // saved before domains were normalized, both map to xn--bcher-kva.de
+    let duplicate_id = insert_subscriber(&app, "ursula@\u{ff22}ücher.de", "Ursula", "pending_confirmation", now - Duration::days(2)).await;
+    let kept_id = insert_subscriber(&app, "ursula@xn--bcher-kva.de", "Ursula", "confirmed", now - Duration::days(1)).await;
+    let converted_id = insert_subscriber(&app, "le_guin@bücher\u{3002}de", "Le Guin", "confirmed", now).await;
+    sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ('a-token', $1)"#,
+        duplicate_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    normalize_stored_addresses(&app.db_pool).await.unwrap();
+    // there is nothing left to do the second time
+    normalize_stored_addresses(&app.db_pool).await.unwrap();
+
+    let saved = sqlx::query!("SELECT id, email, email_display FROM subscriptions ORDER BY subscribed_at",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].id, kept_id);
+    assert_eq!(saved[0].email, "ursula@xn--bcher-kva.de");
+    assert_eq!(saved[1].id, converted_id);
+    assert_eq!(saved[1].email, "le_guin@xn--bcher-kva.de");
+    assert_eq!(saved[1].email_display.as_deref(), Some("le_guin@bücher\u{3002}de"));
+    let token = sqlx::query!("SELECT subscriber_id FROM subscription_tokens",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(token.subscriber_id, kept_id);
+}
+
+#[tokio::test]
+async fn merging_duplicates_keeps_the_most_restrictive_status() {
+    let app = spawn_app().await;
+    let now = Utc::now();
+    insert_subscriber(&app, "ursula@bücher.de", "Ursula", "confirmed", now - Duration::days(2)).await;
+    let unsubscribed_id = insert_subscriber(&app, "ursula@xn--bcher-kva.de", "Ursula", "unsubscribed", now).await;
+    insert_subscriber(&app, "le_guin@bücher.de", "Le Guin", "complained", now - Duration::days(2)).await;
+    insert_subscriber(&app, "le_guin@xn--bcher-kva.de", "Le Guin", "confirmed", now).await;
+
+    normalize_stored_addresses(&app.db_pool).await.unwrap();
+
+    let saved = sqlx::query!("SELECT id, email, status FROM subscriptions ORDER BY email",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let saved: Vec<_> = saved.iter().map(|r| (r.email.as_str(), r.status.as_str())).collect();
+    assert_eq!(
+        saved,
+        [("le_guin@xn--bcher-kva.de", "complained"), ("ursula@xn--bcher-kva.de", "unsubscribed")],
+    );
+    let kept = sqlx::query!("SELECT id FROM subscriptions WHERE status = 'unsubscribed'",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(kept.id, unsubscribed_id);
+}
@@ -2,7 +2,7 @@
 
 use wiremock::{matchers::{method, path}, Mock, ResponseTemplate};
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, assert_is_redirect_to, create_confirmed_subscriber};
 
 #[tokio::test]
 async fn subscribe_returns_200_valid() {
@@ -172,4 +172,340 @@ async fn subscribe_fails_if_there_is_a_fatal_db_error() {
     let response = app.post_subscriptions(body.into()).await;
 
     assert_eq!(response.status().as_u16(), 500);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn subscribing_twice_while_pending_sends_a_new_confirmation_link() {
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let response = app.post_subscriptions(body.into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_links = app.get_confirmation_links(&email_requests[0]);
+    let second_links = app.get_confirmation_links(&email_requests[1]);
+    assert_ne!(first_links.html, second_links.html);
+
+    // the first link has been rotated out
+    let response = reqwest::get(first_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(second_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribing_again_when_confirmed_succeeds_without_sending_an_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_starts_a_new_double_opt_in() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+    // whoever submitted the form may not own the address
+    assert_eq!(saved.name, "le guin");
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let links = app.get_confirmation_links(email_requests.last().unwrap());
+    reqwest::get(links.html).await.unwrap().error_for_status().unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_accepts_a_json_body_and_answers_with_json() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"].is_string());
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_returns_field_level_json_errors_for_invalid_data() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (serde_json::json!({"name": "", "email": "not-an-email"}), vec!["name", "email"]),
+        (serde_json::json!({"name": "Ursula"}), vec!["email"]),
+        (serde_json::json!({"email": "ursula_le_guin@gmail.com"}), vec!["name"]),
+    ];
+
+    for (body, expected_fields) in test_cases {
+        let response = app.post_subscriptions_json(&body).await;
+
+        assert_eq!(response.status().as_u16(), 400, "payload was {}", body);
+        let errors: serde_json::Value = response.json().await.unwrap();
+        let fields: Vec<_> = errors["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| {
+                assert!(e["message"].is_string());
+                e["field"].as_str().unwrap().to_owned()
+            })
+            .collect();
+        assert_eq!(fields, expected_fields, "payload was {}", body);
+    }
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_for_malformed_json() {
+    let app = spawn_app().await;
+
+    let response = app.api_client
+        .post(&format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body("{\"name\": ")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"].is_string());
+    assert_eq!(body["errors"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn subscribe_does_not_expose_internal_errors_to_json_clients() {
+    let app = spawn_app().await;
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN email;",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 500);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["message"], "Something went wrong, please try again later.");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_a_bounce_or_complaint_sends_no_email() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for status in ["bounced", "complained"] {
+        sqlx::query!("UPDATE subscriptions SET status = $1", status)
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+
+        let response = app.post_subscriptions("name=ursula&email=ursula_le_guin%40gmail.com".into()).await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        let saved = sqlx::query!("SELECT status FROM subscriptions",)
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved subscription.");
+        assert_eq!(saved.status, status);
+    }
+}
+
+#[tokio::test]
+async fn concurrent_first_sign_ups_with_the_same_address_all_succeed() {
+    let app = spawn_app_with(|c| c.bot_protection.per_email_limit = 5).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let responses = futures_util::future::join_all((0..5).map(|_| {
+        app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+    }))
+    .await;
+
+    for response in responses {
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let saved = sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn email_addresses_differing_only_in_case_are_the_same_subscriber() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=ursula&email=Ursula%40Example.COM".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_subscriptions("name=ursula&email=ursula%40example.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let saved = sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert_eq!(saved.len(), 1);
+    // the local part keeps the case it was first typed in
+    assert_eq!(saved[0].email, "Ursula@example.com");
+}
+
+#[tokio::test]
+async fn internationalized_domains_are_stored_in_punycode_next_to_the_typed_form() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions_json(&serde_json::json!({
+        "name": "ursula",
+        "email": "ursula@Bücher.de",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let saved = sqlx::query!("SELECT email, email_display FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula@xn--bcher-kva.de");
+    assert_eq!(saved.email_display.as_deref(), Some("ursula@Bücher.de"));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_shows_a_thank_you_page() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"].to_str().unwrap().starts_with("text/html"));
+    assert!(response.text().await.unwrap().contains("Please check your inbox"));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_shows_an_escaped_error_page_for_invalid_data() {
+    let app = spawn_app().await;
+
+    let response = app.post_subscriptions("name=le%20guin&email=%3Cscript%3E".into()).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("We could not process your subscription"));
+    assert!(html.contains("&lt;script&gt; is not a valid subscriber email"));
+    assert!(html.contains(r#"<a href="/">"#));
+}
+
+#[tokio::test]
+async fn the_subscribe_form_redirects_to_the_configured_page() {
+    let app = spawn_app_with(|c| {
+        c.application.redirects.subscribed_url = Some("https://example.com/thanks".into())
+    }).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+    assert_is_redirect_to(&response, "https://example.com/thanks");
+
+    // JSON clients are never redirected
+    let response = app.post_subscriptions_json(&serde_json::json!({
+        "name": "le guin",
+        "email": "ursula@example.com",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
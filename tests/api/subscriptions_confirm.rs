@@ -1,6 +1,6 @@
 //! tests/api/subscriptions_confirm.rs
 
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with, assert_is_redirect_to, create_pending_subscriber};
 use wiremock::{
     ResponseTemplate, 
     Mock,
@@ -39,4 +39,216 @@ async fn the_link_returned_by_subscribe_returns_200_if_called() {
         .unwrap();
 
     assert_eq!(reponse.status().as_u16(), 200);
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn confirming_shows_a_success_page() {
+    let app = spawn_app().await;
+    let confirmation_links = create_pending_subscriber(&app).await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("Your subscription is confirmed"));
+}
+
+#[tokio::test]
+async fn clicking_a_confirmation_link_again_shows_the_subscription_is_already_confirmed() {
+    let app = spawn_app().await;
+    let confirmation_links = create_pending_subscriber(&app).await;
+
+    let response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("already confirmed"));
+    let events = sqlx::query!(r#"SELECT count(*) AS "count!" FROM consent_events WHERE event_type = 'confirmed'"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(events.count, 1);
+}
+
+#[tokio::test]
+async fn a_used_confirmation_link_is_rejected_once_the_subscriber_left() {
+    let app = spawn_app().await;
+    let confirmation_links = create_pending_subscriber(&app).await;
+    reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unknown_confirmation_tokens_get_an_html_page_pointing_to_resend() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        app.address,
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers()["Content-Type"].to_str().unwrap().starts_with("text/html"));
+    let html = response.text().await.unwrap();
+    assert!(html.contains("This confirmation link is not valid"));
+    assert!(html.contains(r#"href="/subscriptions/resend""#));
+}
+
+#[tokio::test]
+async fn the_confirmation_page_is_in_the_language_of_the_subscriber() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&locale=zh".into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert!(response.text().await.unwrap().contains("您的订阅已确认"));
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_are_sent_to_the_configured_page() {
+    let app = spawn_app_with(|c| {
+        c.application.redirects.confirmed_url = Some("https://example.com/welcome".into())
+    }).await;
+    let confirmation_links = create_pending_subscriber(&app).await;
+
+    let response = app.api_client.get(confirmation_links.html).send().await.unwrap();
+
+    assert_is_redirect_to(&response, "https://example.com/welcome");
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_410() {
+    let app = spawn_app().await;
+    let confirmation_links = create_pending_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '3 days'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response.text().await.unwrap().contains("/subscriptions/resend"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn pending_subscribers_can_request_a_new_confirmation_link() {
+    let app = spawn_app().await;
+    let expired_links = create_pending_subscriber(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET created_at = now() - interval '3 days'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_resend_confirmation("email=ursula_le_guin%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let new_links = app.get_confirmation_links(email_requests.last().unwrap());
+    let response = reqwest::get(expired_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(new_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_an_unknown_address_succeeds_without_sending_an_email() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_resend_confirmation("email=ursula_le_guin%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_to_the_same_address_is_rate_limited() {
+    // subscribing counts towards the limit too
+    let app = spawn_app_with(|c| c.bot_protection.per_email_limit = 3).await;
+    create_pending_subscriber(&app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    for _ in 0..2 {
+        let response = app.post_resend_confirmation("email=ursula_le_guin%40gmail.com".into()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_resend_confirmation("email=Ursula_Le_Guin%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 429);
+
+    let response = app.post_resend_confirmation("email=someone_else%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn resending_from_the_same_ip_is_rate_limited() {
+    let app = spawn_app_with(|c| c.bot_protection.per_ip_limit = 2).await;
+
+    for i in 0..2 {
+        let response = app.post_resend_confirmation(format!("email=ursula{}%40gmail.com", i)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    let response = app.post_resend_confirmation("email=ursula2%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn an_old_link_does_not_bring_back_a_subscriber_who_left() {
+    let app = spawn_app().await;
+    let links = create_pending_subscriber(&app).await;
+    for status in ["unsubscribed", "bounced", "complained"] {
+        sqlx::query!("UPDATE subscriptions SET status = $1", status)
+            .execute(&app.db_pool)
+            .await
+            .unwrap();
+
+        let response = reqwest::get(links.html.clone()).await.unwrap();
+
+        assert_eq!(response.status().as_u16(), 401);
+        let saved = sqlx::query!("SELECT status FROM subscriptions",)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(saved.status, status);
+    }
+}
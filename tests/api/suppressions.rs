@@ -0,0 +1,146 @@
+//! tests/api/suppressions.rs
+
+use crate::helpers::{spawn_app, assert_is_redirect_to, create_confirmed_subscriber};
+use wiremock::matchers::any;
+use chrono::Utc;
+use uuid::Uuid;
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::startup::normalize_stored_addresses;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_the_suppression_list() {
+    let app = spawn_app().await;
+
+    let response = app.get_suppressions().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.post_suppression(&serde_json::json!({
+        "kind": "address",
+        "value": "ursula_le_guin@gmail.com",
+        "reason": "test",
+    })).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_do_not_receive_confirmation_emails() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_suppression(&serde_json::json!({
+        "kind": "address",
+        "value": "Ursula_Le_Guin@gmail.com",
+        "reason": "Requested by the recipient",
+    })).await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html = app.get_suppressions_html().await;
+    assert!(html.contains("ursula_le_guin@gmail.com has been added to the suppression list."));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn suppressed_domains_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login().await;
+
+    app.post_suppression(&serde_json::json!({
+        "kind": "domain",
+        "value": "gmail.com",
+        "reason": "Domain rejects our mail",
+    })).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        }
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn entries_can_be_imported_in_bulk() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app.post_import_suppressions(&serde_json::json!({
+        "entries": "first@example.com\n@mailinator.com\n\nnot an entry\nfirst@example.com",
+        "reason": "Imported from the previous provider",
+    })).await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    let html = app.get_suppressions_html().await;
+    assert!(html.contains("Imported 2 entries, 1 were already suppressed."));
+    assert!(html.contains("Skipped invalid entries: not an entry"));
+    assert!(html.contains("Suppressed (2)"));
+}
+
+#[tokio::test]
+async fn hard_bounced_addresses_are_added_to_the_suppression_list() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    app.post_email_event(&serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "Email": "ursula_le_guin@gmail.com",
+    })).await;
+
+    let entry = sqlx::query!("SELECT kind, value, source FROM suppressions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch suppression list entry.");
+    assert_eq!(entry.kind, "address");
+    assert_eq!(entry.value, "ursula_le_guin@gmail.com");
+    assert_eq!(entry.source, "webhook");
+}
+
+#[tokio::test]
+async fn stored_unicode_suppressions_are_converted_like_new_entries() {
+    let app = spawn_app().await;
+    // saved before domains were normalized
+    for (kind, value) in [
+        ("domain", "b\u{fc}cher\u{3002}de"),
+        ("address", "ursula@\u{ff22}\u{fc}cher.de"),
+        ("address", "ursula@xn--bcher-kva.de"),
+    ] {
+        sqlx::query!(
+            r#"
+            INSERT INTO suppressions (id, kind, value, reason, source, created_at)
+            VALUES ($1, $2, $3, 'test', 'admin', $4)
+            "#,
+            Uuid::new_v4(),
+            kind,
+            value,
+            Utc::now(),
+        )
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    }
+
+    normalize_stored_addresses(&app.db_pool).await.unwrap();
+
+    let entries = sqlx::query!("SELECT kind, value FROM suppressions ORDER BY kind",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let entries: Vec<_> = entries.iter().map(|e| (e.kind.as_str(), e.value.as_str())).collect();
+    assert_eq!(entries, [("address", "ursula@xn--bcher-kva.de"), ("domain", "xn--bcher-kva.de")]);
+}
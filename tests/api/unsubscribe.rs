@@ -0,0 +1,154 @@
+//! tests/api/unsubscribe.rs
+
+use crate::helpers::{spawn_app, create_confirmed_subscriber};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn query_param(link: &reqwest::Url, name: &str) -> String {
+    link.query_pairs()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn newsletters_contain_a_link_to_an_unsubscribe_confirmation_page() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let link = app.get_unsubscribe_link().await;
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<form action="/subscriptions/unsubscribe" method="post">"#));
+
+    // visiting the link alone does not unsubscribe
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link().await;
+
+    let response = app.post_unsubscribe(&serde_json::json!({
+        "subscriber_id": query_param(&link, "subscriber_id"),
+        "signature": query_param(&link, "signature"),
+        "reason": "Too many emails",
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("You have been unsubscribed"));
+
+    let saved = sqlx::query!("SELECT status, unsubscribe_reason FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+    assert_eq!(saved.unsubscribe_reason.as_deref(), Some("Too many emails"));
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        }
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn unsubscribing_sends_a_confirmation_with_a_link_to_subscribe_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link().await;
+    let body = serde_json::json!({
+        "subscriber_id": query_param(&link, "subscriber_id"),
+        "signature": query_param(&link, "signature"),
+    });
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_unsubscribe(&body).await;
+    // unsubscribing again is confirmed only once
+    app.post_unsubscribe(&body).await;
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "You have been unsubscribed");
+    let resubscribe_link = app.get_confirmation_links(&email_request).html;
+    assert_eq!(resubscribe_link.path(), "/");
+    let html = reqwest::get(resubscribe_link).await.unwrap().text().await.unwrap();
+    assert!(html.contains(r#"action="/subscriptions""#));
+}
+
+#[tokio::test]
+async fn unsubscribe_links_with_an_invalid_signature_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link().await;
+    let mut signature = query_param(&link, "signature");
+    signature.replace_range(0..2, if signature.starts_with("00") { "11" } else { "00" });
+
+    let response = app.post_unsubscribe(&serde_json::json!({
+        "subscriber_id": query_param(&link, "subscriber_id"),
+        "signature": signature,
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_unsubscribe(&serde_json::json!({
+        "subscriber_id": uuid::Uuid::new_v4().to_string(),
+        "signature": query_param(&link, "signature"),
+    })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn unsubscribing_after_a_complaint_keeps_the_address_from_being_emailed_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let link = app.get_unsubscribe_link().await;
+    app.post_email_event(&serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "Type": "SpamComplaint",
+        "Email": "ursula_le_guin@gmail.com",
+    })).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_unsubscribe(&serde_json::json!({
+        "subscriber_id": query_param(&link, "subscriber_id"),
+        "signature": query_param(&link, "signature"),
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into()).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "complained");
+}
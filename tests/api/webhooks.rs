@@ -0,0 +1,126 @@
+//! tests/api/webhooks.rs
+
+use crate::helpers::{spawn_app, create_confirmed_subscriber, create_pending_subscriber, TestApp};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+fn bounce_event(bounce_type: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "Email": "ursula_le_guin@gmail.com",
+        "Description": "The server was unable to deliver your message",
+        "BouncedAt": "2025-03-21T10:00:00Z"
+    })
+}
+
+async fn subscriber_status(app: &TestApp) -> String {
+    sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.")
+        .status
+}
+
+#[tokio::test]
+async fn webhook_requests_without_a_valid_signature_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app.api_client
+        .post(&format!("{}/webhooks/postmark", &app.address))
+        .json(&bounce_event("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.api_client
+        .post(&format!("{}/webhooks/postmark", &app.address))
+        .header("X-Webhook-Signature", "deadbeef")
+        .json(&bounce_event("HardBounce"))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_excludes_the_subscriber_from_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_event(&bounce_event("HardBounce")).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_newsletters(serde_json::json!({
+        "title": "newsletter title",
+        "content": {
+            "text": "newsletter content",
+            "html": "<p>newsletter content</p>"
+        }
+    })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_invalidates_pending_confirmation_links() {
+    let app = spawn_app().await;
+    let links = create_pending_subscriber(&app).await;
+
+    app.post_email_event(&bounce_event("HardBounce")).await;
+
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_status(&app).await, "bounced");
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_event(&serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "Type": "SpamComplaint",
+        "Email": "ursula_le_guin@gmail.com",
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "complained");
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_without_changing_the_status() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_event(&bounce_event("SoftBounce")).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).await, "confirmed");
+    let event = sqlx::query!("SELECT event_type, subscriber_id FROM email_events",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved email event.");
+    assert_eq!(event.event_type, "soft_bounce");
+    assert!(event.subscriber_id.is_some());
+}
+
+#[tokio::test]
+async fn unknown_event_types_are_acknowledged() {
+    let app = spawn_app().await;
+
+    let response = app.post_email_event(&serde_json::json!({
+        "RecordType": "Delivery",
+        "Recipient": "ursula_le_guin@gmail.com",
+    })).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+}